@@ -0,0 +1,298 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use super::gpx_parser::read_gpx_document_from;
+use super::{Gpx, ParseError, ParseOptions, ParseWarning};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZIP_MAGIC: [u8; 4] = [0x50, 0x4b, 0x03, 0x04];
+const EMPTY_ZIP_MAGIC: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Compression {
+    None,
+    Gzip,
+    Zip,
+}
+
+/// Detects compression by the magic bytes at the beginning of the input
+pub(crate) fn detect_compression(header: &[u8]) -> Compression {
+    if header.starts_with(&GZIP_MAGIC) {
+        Compression::Gzip
+    } else if header.starts_with(&ZIP_MAGIC) || header.starts_with(&EMPTY_ZIP_MAGIC) {
+        Compression::Zip
+    } else {
+        Compression::None
+    }
+}
+
+/// GPX document found in the input, tagged with the name it was found under
+#[derive(Debug)]
+pub struct GpxEntry {
+    /// Archive entry name, or file name for inputs which aren't archives
+    pub name: String,
+    pub gpx: Gpx,
+}
+
+impl From<ZipError> for ParseError {
+    fn from(err: ZipError) -> Self {
+        match err {
+            ZipError::Io(err) => ParseError::Io(err),
+            err => ParseError::InvalidArchive(err.to_string()),
+        }
+    }
+}
+
+fn is_gpx_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with(".gpx") || name.ends_with(".gpx.gz")
+}
+
+/// Plain or gzip-compressed GPX, whichever the magic bytes say
+fn read_maybe_gzipped<R: Read>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<(Gpx, Vec<ParseWarning>), ParseError> {
+    let mut reader = BufReader::new(reader);
+    match detect_compression(reader.fill_buf()?) {
+        Compression::Gzip => read_gpx_document_from(BufReader::new(GzDecoder::new(reader)), options),
+        _ => read_gpx_document_from(reader, options),
+    }
+}
+
+fn for_each_zip_entry<R, F>(reader: R, options: &ParseOptions, mut f: F) -> Result<(), ParseError>
+where
+    R: Read + Seek,
+    F: FnMut(String, Gpx, Vec<ParseWarning>) -> bool,
+{
+    let mut archive = ZipArchive::new(reader)?;
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        if !entry.is_file() || !is_gpx_name(entry.name()) {
+            continue;
+        }
+
+        let name = entry.name().to_string();
+        let (gpx, warnings) = read_maybe_gzipped(entry, options)?;
+        if !f(name, gpx, warnings) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the first GPX document of plain, gzip-compressed or zip input
+pub(crate) fn read_first_gpx_document<R: Read + Seek>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<(Gpx, Vec<ParseWarning>), ParseError> {
+    let mut reader = BufReader::new(reader);
+    if detect_compression(reader.fill_buf()?) != Compression::Zip {
+        return read_maybe_gzipped(reader, options);
+    }
+
+    let mut first = None;
+    for_each_zip_entry(reader, options, |_, gpx, warnings| {
+        first = Some((gpx, warnings));
+        false
+    })?;
+
+    first.ok_or_else(|| ParseError::InvalidArchive(String::from("archive contains no GPX files")))
+}
+
+/// Same as `read_first_gpx_document` for inputs without random access. Zip archive keeps
+/// its directory at the end, so zip input is read into memory first.
+pub(crate) fn read_first_gpx_document_from_stream<R: Read>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<(Gpx, Vec<ParseWarning>), ParseError> {
+    let mut reader = BufReader::new(reader);
+    if detect_compression(reader.fill_buf()?) != Compression::Zip {
+        return read_maybe_gzipped(reader, options);
+    }
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    read_first_gpx_document(Cursor::new(data), options)
+}
+
+/// Reads every GPX document of the input. Zip archive gives one entry per GPX file inside,
+/// plain and gzip-compressed inputs give a single entry named `name`.
+pub fn read_gpx_entries_from_reader<R: Read + Seek>(
+    reader: R,
+    name: &str,
+) -> Result<Vec<GpxEntry>, ParseError> {
+    let options = ParseOptions::new();
+    let mut reader = BufReader::new(reader);
+    if detect_compression(reader.fill_buf()?) != Compression::Zip {
+        let (gpx, _) = read_maybe_gzipped(reader, &options)?;
+        return Ok(vec![GpxEntry {
+            name: name.to_string(),
+            gpx,
+        }]);
+    }
+
+    let mut entries = Vec::new();
+    for_each_zip_entry(reader, &options, |name, gpx, _| {
+        entries.push(GpxEntry { name, gpx });
+        true
+    })?;
+
+    Ok(entries)
+}
+
+pub fn read_gpx_entries(path: &str) -> Result<Vec<GpxEntry>, ParseError> {
+    let file = File::open(path)?;
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string());
+
+    read_gpx_entries_from_reader(file, &name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    fn gpx_with_track(name: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <trk>
+        <name>{}</name>
+        <trkseg>
+            <trkpt lat=\"10.1025420\" lon=\"15.1583540\"><time>2020-04-22T16:01:58Z</time></trkpt>
+        </trkseg>
+    </trk>
+</gpx>",
+            name
+        )
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_detect_compression() {
+        assert_eq!(detect_compression(b"<?xml"), Compression::None);
+        assert_eq!(detect_compression(&[0x1f, 0x8b, 0x08]), Compression::Gzip);
+        assert_eq!(detect_compression(b"PK\x03\x04"), Compression::Zip);
+        assert_eq!(detect_compression(b"PK\x05\x06"), Compression::Zip);
+        assert_eq!(detect_compression(&[0x1f]), Compression::None);
+    }
+
+    #[test]
+    fn test_read_plain_gpx() {
+        let data = gpx_with_track("Plain");
+        let (gpx, _) = read_first_gpx_document(Cursor::new(data), &ParseOptions::new()).unwrap();
+        assert_eq!(gpx.tracks[0].name, "Plain");
+    }
+
+    #[test]
+    fn test_read_gzipped_gpx() {
+        let data = gzip(gpx_with_track("Gzipped").as_bytes());
+        let (gpx, _) = read_first_gpx_document(Cursor::new(data), &ParseOptions::new()).unwrap();
+        assert_eq!(gpx.tracks[0].name, "Gzipped");
+
+        let data = gzip(gpx_with_track("Gzipped").as_bytes());
+        let entries = read_gpx_entries_from_reader(Cursor::new(data), "run.gpx.gz").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "run.gpx.gz");
+    }
+
+    #[test]
+    fn test_read_zip_entries() {
+        let first = gpx_with_track("First");
+        let second = gzip(gpx_with_track("Second").as_bytes());
+        let data = zip(&[
+            ("activities.csv", b"id,name\n1,First\n"),
+            ("activities/1.gpx", first.as_bytes()),
+            ("activities/2.gpx.gz", &second),
+        ]);
+
+        let entries = read_gpx_entries_from_reader(Cursor::new(data.clone()), "export.zip").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "activities/1.gpx");
+        assert_eq!(entries[0].gpx.tracks[0].name, "First");
+        assert_eq!(entries[1].name, "activities/2.gpx.gz");
+        assert_eq!(entries[1].gpx.tracks[0].name, "Second");
+
+        let (gpx, _) = read_first_gpx_document(Cursor::new(data), &ParseOptions::new()).unwrap();
+        assert_eq!(gpx.tracks[0].name, "First");
+    }
+
+    #[test]
+    fn test_read_compressed_stream() {
+        let data = gzip(gpx_with_track("Gzipped").as_bytes());
+        let (gpx, _) = read_first_gpx_document_from_stream(&data[..], &ParseOptions::new()).unwrap();
+        assert_eq!(gpx.tracks[0].name, "Gzipped");
+
+        let first = gpx_with_track("First");
+        let data = zip(&[("activities/1.gpx", first.as_bytes())]);
+        let (gpx, _) = read_first_gpx_document_from_stream(&data[..], &ParseOptions::new()).unwrap();
+        assert_eq!(gpx.tracks[0].name, "First");
+
+        let data = gpx_with_track("Plain");
+        let (gpx, _) =
+            read_first_gpx_document_from_stream(data.as_bytes(), &ParseOptions::new()).unwrap();
+        assert_eq!(gpx.tracks[0].name, "Plain");
+    }
+
+    #[test]
+    fn test_read_zip_without_gpx() {
+        let data = zip(&[("notes.txt", b"nothing here")]);
+
+        let result = read_first_gpx_document(Cursor::new(data.clone()), &ParseOptions::new());
+        assert!(matches!(result, Err(ParseError::InvalidArchive(_))));
+
+        let entries = read_gpx_entries_from_reader(Cursor::new(data), "export.zip").unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_read_corrupted_zip() {
+        let result = read_gpx_entries_from_reader(Cursor::new(b"PK\x03\x04garbage".to_vec()), "x.zip");
+        assert!(result.is_err());
+    }
+}
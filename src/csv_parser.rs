@@ -0,0 +1,490 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs::File;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use csv::{ReaderBuilder, StringRecord};
+
+use super::gpx_parser::invalid_value;
+use super::{Location, ParseError, Track, TrackPoint, TrackSegment};
+
+/// Column of CSV input, either by its header or by zero-based position
+#[derive(Debug, Clone, PartialEq)]
+pub enum CsvColumn {
+    Name(String),
+    Index(usize),
+}
+
+impl CsvColumn {
+    pub fn name(name: &str) -> CsvColumn {
+        CsvColumn::Name(name.to_string())
+    }
+}
+
+/// How values of the time column are written
+#[derive(Debug, Clone, PartialEq)]
+pub enum CsvTimeFormat {
+    /// E.g. `2020-04-22T16:01:58Z`
+    Rfc3339,
+    /// Seconds since 1970-01-01 UTC, fractions allowed
+    UnixSeconds,
+    /// Seconds since the given moment, e.g. start of a treadmill test
+    ElapsedSeconds(DateTime<Utc>),
+    /// `strftime`-like pattern, see `chrono::format::strftime`. Times without offset are UTC.
+    Custom(String),
+}
+
+/// Maps CSV columns to track point fields. Latitude and longitude are required,
+/// other columns are optional: mapped columns absent from the header are ignored.
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    /// Without header columns can be referred by index only
+    pub has_header: bool,
+    pub time_format: CsvTimeFormat,
+    pub time: Option<CsvColumn>,
+    pub latitude: CsvColumn,
+    pub longitude: CsvColumn,
+    pub elevation: Option<CsvColumn>,
+    pub heart_rate: Option<CsvColumn>,
+    pub cadence: Option<CsvColumn>,
+    pub power: Option<CsvColumn>,
+    pub speed: Option<CsvColumn>,
+    pub distance: Option<CsvColumn>,
+    /// Change of value in this column starts a new track segment
+    pub segment: Option<CsvColumn>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsvOptions {
+    /// Comma separated columns as written by `write_csv`
+    pub fn new() -> CsvOptions {
+        CsvOptions {
+            delimiter: b',',
+            has_header: true,
+            time_format: CsvTimeFormat::Rfc3339,
+            time: Some(CsvColumn::name("time")),
+            latitude: CsvColumn::name("lat"),
+            longitude: CsvColumn::name("lon"),
+            elevation: Some(CsvColumn::name("ele")),
+            heart_rate: Some(CsvColumn::name("hr")),
+            cadence: Some(CsvColumn::name("cad")),
+            power: Some(CsvColumn::name("power")),
+            speed: Some(CsvColumn::name("speed")),
+            distance: Some(CsvColumn::name("distance")),
+            segment: Some(CsvColumn::name("segment")),
+        }
+    }
+}
+
+/// Positions of mapped columns in a record
+struct ColumnIndices {
+    time: Option<usize>,
+    latitude: usize,
+    longitude: usize,
+    elevation: Option<usize>,
+    heart_rate: Option<usize>,
+    cadence: Option<usize>,
+    power: Option<usize>,
+    speed: Option<usize>,
+    distance: Option<usize>,
+    segment: Option<usize>,
+}
+
+fn find_column(column: &CsvColumn, header: Option<&StringRecord>) -> Option<usize> {
+    match column {
+        CsvColumn::Index(index) => Some(*index),
+        CsvColumn::Name(name) => header?.iter().position(|field| field.trim() == name),
+    }
+}
+
+fn find_required_column(
+    column: &CsvColumn,
+    header: Option<&StringRecord>,
+) -> Result<usize, ParseError> {
+    find_column(column, header).ok_or_else(|| ParseError::Syntax {
+        location: Location { line: 1, column: 1 },
+        message: format!("no column {:?}", column),
+    })
+}
+
+impl ColumnIndices {
+    fn new(options: &CsvOptions, header: Option<&StringRecord>) -> Result<ColumnIndices, ParseError> {
+        let find = |column: &Option<CsvColumn>| {
+            column.as_ref().and_then(|column| find_column(column, header))
+        };
+
+        Ok(ColumnIndices {
+            time: find(&options.time),
+            latitude: find_required_column(&options.latitude, header)?,
+            longitude: find_required_column(&options.longitude, header)?,
+            elevation: find(&options.elevation),
+            heart_rate: find(&options.heart_rate),
+            cadence: find(&options.cadence),
+            power: find(&options.power),
+            speed: find(&options.speed),
+            distance: find(&options.distance),
+            segment: find(&options.segment),
+        })
+    }
+}
+
+fn convert_csv_error(err: csv::Error) -> ParseError {
+    let location = Location {
+        line: err.position().map_or(0, |position| position.line()),
+        column: 1,
+    };
+    let message = err.to_string();
+
+    match err.into_kind() {
+        csv::ErrorKind::Io(err) => ParseError::Io(err),
+        _ => ParseError::Syntax { location, message },
+    }
+}
+
+/// Cell of the record. Column of the location is the field number counting from 1.
+struct Cell<'a> {
+    value: &'a str,
+    location: Location,
+}
+
+impl<'a> Cell<'a> {
+    fn parse<T: FromStr>(&self, field: &str) -> Result<T, ParseError> {
+        self.value
+            .parse()
+            .map_err(|_| invalid_value(field, self.value, self.location))
+    }
+}
+
+fn cell(record: &StringRecord, index: Option<usize>) -> Option<Cell<'_>> {
+    let index = index?;
+    let value = record.get(index)?.trim();
+    if value.is_empty() {
+        return None;
+    }
+
+    let line = record.position().map_or(0, |position| position.line());
+    Some(Cell {
+        value,
+        location: Location {
+            line,
+            column: index as u64 + 1,
+        },
+    })
+}
+
+fn parse_optional<T: FromStr>(
+    record: &StringRecord,
+    index: Option<usize>,
+    field: &str,
+) -> Result<Option<T>, ParseError> {
+    cell(record, index).map(|cell| cell.parse(field)).transpose()
+}
+
+fn parse_time(cell: &Cell, format: &CsvTimeFormat) -> Result<DateTime<Utc>, ParseError> {
+    let invalid_time = || invalid_value("time", cell.value, cell.location);
+    let seconds_to_duration = |seconds: f64| {
+        if seconds.is_finite() {
+            Some(Duration::milliseconds((seconds * 1000.0).round() as i64))
+        } else {
+            None
+        }
+    };
+
+    let time = match format {
+        CsvTimeFormat::Rfc3339 => DateTime::parse_from_rfc3339(cell.value)
+            .ok()
+            .map(|time| time.with_timezone(&Utc)),
+        CsvTimeFormat::UnixSeconds => cell
+            .value
+            .parse()
+            .ok()
+            .and_then(seconds_to_duration)
+            .and_then(|elapsed| {
+                let epoch = Utc.timestamp_opt(0, 0).single()?;
+                epoch.checked_add_signed(elapsed)
+            }),
+        CsvTimeFormat::ElapsedSeconds(start) => cell
+            .value
+            .parse()
+            .ok()
+            .and_then(seconds_to_duration)
+            .and_then(|elapsed| start.checked_add_signed(elapsed)),
+        CsvTimeFormat::Custom(pattern) => DateTime::parse_from_str(cell.value, pattern)
+            .map(|time| time.with_timezone(&Utc))
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(cell.value, pattern)
+                    .map(|time| Utc.from_utc_datetime(&time))
+            })
+            .ok(),
+    };
+
+    time.ok_or_else(invalid_time)
+}
+
+fn parse_record(
+    record: &StringRecord,
+    columns: &ColumnIndices,
+    options: &CsvOptions,
+) -> Result<TrackPoint, ParseError> {
+    let coordinate = |index: usize, field: &str| -> Result<f64, ParseError> {
+        match cell(record, Some(index)) {
+            Some(cell) => cell.parse(field),
+            None => {
+                let location = Location {
+                    line: record.position().map_or(0, |position| position.line()),
+                    column: index as u64 + 1,
+                };
+                Err(invalid_value(field, "", location))
+            }
+        }
+    };
+
+    let mut point = TrackPoint::from_coordinates(
+        coordinate(columns.latitude, "lat")?,
+        coordinate(columns.longitude, "lon")?,
+    );
+    point.time = cell(record, columns.time)
+        .map(|cell| parse_time(&cell, &options.time_format))
+        .transpose()?;
+    point.elevation = parse_optional(record, columns.elevation, "ele")?;
+    point.heart_rate = parse_optional(record, columns.heart_rate, "hr")?;
+    point.cadence = parse_optional(record, columns.cadence, "cad")?;
+    point.power = parse_optional(record, columns.power, "power")?;
+    point.speed = parse_optional(record, columns.speed, "speed")?;
+    point.distance = parse_optional(record, columns.distance, "distance")?;
+
+    Ok(point)
+}
+
+/// Reads track points from CSV, one point per record
+pub fn read_csv_from_reader<R: Read>(reader: R, options: &CsvOptions) -> Result<Track, ParseError> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .has_headers(options.has_header)
+        .flexible(true)
+        .from_reader(reader);
+
+    let header = if options.has_header {
+        Some(reader.headers().map_err(convert_csv_error)?.clone())
+    } else {
+        None
+    };
+    let columns = ColumnIndices::new(options, header.as_ref())?;
+
+    let mut track = Track::new();
+    let mut segment = TrackSegment::new();
+    let mut segment_id: Option<String> = None;
+    for record in reader.records() {
+        let record = record.map_err(convert_csv_error)?;
+
+        let record_segment_id = cell(&record, columns.segment).map(|cell| cell.value.to_string());
+        if record_segment_id != segment_id && !segment.points.is_empty() {
+            track.route.push(std::mem::take(&mut segment));
+        }
+        segment_id = record_segment_id;
+
+        segment.points.push(parse_record(&record, &columns, options)?);
+    }
+    if !segment.points.is_empty() {
+        track.route.push(segment);
+    }
+
+    track.sort_points_by_time();
+    track.creation_time = track
+        .route
+        .first()
+        .and_then(|segment| segment.points.first())
+        .and_then(|point| point.time);
+    Ok(track)
+}
+
+pub fn read_csv_from_str(csv: &str, options: &CsvOptions) -> Result<Track, ParseError> {
+    read_csv_from_reader(csv.as_bytes(), options)
+}
+
+pub fn read_csv(path: &str, options: &CsvOptions) -> Result<Track, ParseError> {
+    let file = File::open(path)?;
+
+    read_csv_from_reader(io::BufReader::new(file), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test]
+    fn test_reading_csv() {
+        let csv = "time,lat,lon,ele,hr,cad
+2020-04-22T16:01:58Z,10.1025420,15.1583540,478.2,95,79
+2020-04-22T16:02:04Z,10.1026420,15.1583542,,97,
+";
+
+        let track = read_csv_from_str(csv, &CsvOptions::new()).unwrap();
+        assert_eq!(track.creation_time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58)));
+        assert_eq!(track.route.len(), 1);
+
+        let points = &track.route[0].points;
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].latitude, 10.1025420);
+        assert_eq!(points[0].longitude, 15.1583540);
+        assert_eq!(points[0].elevation, Some(478.2));
+        assert_eq!(points[0].heart_rate, Some(95));
+        assert_eq!(points[0].cadence, Some(79));
+        assert_eq!(points[1].time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 2, 4)));
+        assert_eq!(points[1].elevation, None);
+        assert_eq!(points[1].cadence, None);
+        assert_eq!(points[1].power, None);
+    }
+
+    #[test]
+    fn test_reading_csv_with_column_mapping() {
+        let csv = "Elapsed;Latitude;Longitude;Heart Rate
+0;10.0;15.0;120
+1.5;10.0001;15.0;121
+";
+        let start = Utc.ymd(2020, 4, 22).and_hms(16, 0, 0);
+        let mut options = CsvOptions::new();
+        options.delimiter = b';';
+        options.time_format = CsvTimeFormat::ElapsedSeconds(start);
+        options.time = Some(CsvColumn::name("Elapsed"));
+        options.latitude = CsvColumn::name("Latitude");
+        options.longitude = CsvColumn::name("Longitude");
+        options.heart_rate = Some(CsvColumn::name("Heart Rate"));
+
+        let track = read_csv_from_str(csv, &options).unwrap();
+        let points = &track.route[0].points;
+        assert_eq!(points[1].time, Some(start + Duration::milliseconds(1500)));
+        assert_eq!(points[1].latitude, 10.0001);
+        assert_eq!(points[1].heart_rate, Some(121));
+    }
+
+    #[test]
+    fn test_reading_csv_without_header() {
+        let csv = "1587571318,10.0,15.0\n1587571319,10.0001,15.0\n";
+        let mut options = CsvOptions::new();
+        options.has_header = false;
+        options.time_format = CsvTimeFormat::UnixSeconds;
+        options.time = Some(CsvColumn::Index(0));
+        options.latitude = CsvColumn::Index(1);
+        options.longitude = CsvColumn::Index(2);
+
+        let track = read_csv_from_str(csv, &options).unwrap();
+        let points = &track.route[0].points;
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58)));
+        assert_eq!(points[1].longitude, 15.0);
+    }
+
+    #[test]
+    fn test_reading_csv_with_custom_time_format() {
+        let csv = "time,lat,lon\n22.04.2020 16:01:58,10.0,15.0\n";
+        let mut options = CsvOptions::new();
+        options.time_format = CsvTimeFormat::Custom(String::from("%d.%m.%Y %H:%M:%S"));
+
+        let track = read_csv_from_str(csv, &options).unwrap();
+        assert_eq!(
+            track.route[0].points[0].time,
+            Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58))
+        );
+    }
+
+    #[test]
+    fn test_reading_csv_with_unix_time_out_of_range() {
+        let csv = "time,lat,lon\n1e15,10.0,15.0\n";
+        let mut options = CsvOptions::new();
+        options.time_format = CsvTimeFormat::UnixSeconds;
+
+        match read_csv_from_str(csv, &options) {
+            Err(ParseError::InvalidValue { field, value, .. }) => {
+                assert_eq!(field, "time");
+                assert_eq!(value, "1e15");
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reading_csv_with_backwards_time() {
+        let csv = "time,lat,lon\n2020-04-22T16:02:00Z,52.0,13.0\n2020-04-22T16:01:00Z,52.001,13.0\n";
+
+        let track = read_csv_from_str(csv, &CsvOptions::new()).unwrap();
+        let points = &track.route[0].points;
+        assert_eq!(points[0].time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 0)));
+        assert_eq!(points[0].latitude, 52.001);
+        assert_eq!(track.creation_time, points[0].time);
+        assert_eq!(track.duration().as_secs(), 60);
+    }
+
+    #[test]
+    fn test_reading_csv_segments() {
+        let csv = "segment,lat,lon\n0,10.0,15.0\n0,10.1,15.0\n1,10.2,15.0\n";
+
+        let track = read_csv_from_str(csv, &CsvOptions::new()).unwrap();
+        assert_eq!(track.route.len(), 2);
+        assert_eq!(track.route[0].points.len(), 2);
+        assert_eq!(track.route[1].points.len(), 1);
+        assert_eq!(track.creation_time, None);
+    }
+
+    #[test]
+    fn test_reading_csv_without_coordinate_column() {
+        let csv = "time,latitude,lon\n";
+
+        match read_csv_from_str(csv, &CsvOptions::new()) {
+            Err(ParseError::Syntax { message, .. }) => assert!(message.contains("lat")),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test_case("time,lat,lon\nyesterday,10.0,15.0", "time", "yesterday", 1; "bad time")]
+    #[test_case("lat,lon,hr\n10.0,15.0,300", "hr", "300", 3; "bad heart rate")]
+    #[test_case("lat,lon\n10.0,", "lon", "", 2; "missing longitude")]
+    fn test_reading_csv_invalid_value(
+        csv: &str,
+        expected_field: &str,
+        expected_value: &str,
+        expected_column: u64,
+    ) {
+        match read_csv_from_str(csv, &CsvOptions::new()) {
+            Err(ParseError::InvalidValue {
+                field,
+                value,
+                location,
+            }) => {
+                assert_eq!(field, expected_field);
+                assert_eq!(value, expected_value);
+                assert_eq!(location, Location { line: 2, column: expected_column });
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reading_missing_csv_file() {
+        let result = read_csv("/nonexistent/path/to/track.csv", &CsvOptions::new());
+        assert!(matches!(result, Err(ParseError::Io(_))));
+    }
+}
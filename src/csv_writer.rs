@@ -0,0 +1,183 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs::File;
+use std::io::{self, Write};
+
+use chrono::SecondsFormat;
+
+use super::speed;
+use super::stats;
+use super::Track;
+
+const CSV_HEADER: [&str; 13] = [
+    "segment",
+    "time",
+    "lat",
+    "lon",
+    "ele",
+    "hr",
+    "cad",
+    "power",
+    "speed",
+    "distance",
+    "calc_distance",
+    "calc_speed",
+    "pace",
+];
+
+fn optional_to_string<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Writes every track point as a CSV record. Besides the recorded values each point gets
+/// `calc_distance` from the start of the track in meters, `calc_speed` since the previous
+/// point in meters per second and `pace` in seconds per kilometer. The output can be read
+/// back with default `CsvOptions`, calculated columns are ignored then.
+pub fn write_csv<W: Write>(track: &Track, writer: W) -> io::Result<()> {
+    let distances = stats::calc_track_point_distances(track);
+    let speeds = speed::calc_track_point_speeds(track);
+
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(CSV_HEADER)?;
+
+    for (segment_index, segment) in track.route.iter().enumerate() {
+        let values = distances[segment_index].iter().zip(&speeds[segment_index]);
+        for (point, (distance, speed)) in segment.points.iter().zip(values) {
+            let pace = speed.filter(|speed| *speed > 0.0).map(|speed| 1000.0 / speed);
+
+            writer.write_record(&[
+                segment_index.to_string(),
+                optional_to_string(
+                    point
+                        .time
+                        .map(|time| time.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+                ),
+                point.latitude.to_string(),
+                point.longitude.to_string(),
+                optional_to_string(point.elevation),
+                optional_to_string(point.heart_rate),
+                optional_to_string(point.cadence),
+                optional_to_string(point.power),
+                optional_to_string(point.speed),
+                optional_to_string(point.distance),
+                distance.to_string(),
+                optional_to_string(*speed),
+                optional_to_string(pace),
+            ])?;
+        }
+    }
+
+    writer.flush()
+}
+
+/// Writes track points as CSV file, replacing the existing one
+pub fn write_csv_file(track: &Track, path: &str) -> io::Result<()> {
+    let file = File::create(path)?;
+
+    write_csv(track, file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::super::csv_parser::{read_csv_from_str, CsvOptions};
+    use super::super::{TrackPoint, TrackSegment};
+
+    fn new_track() -> Track {
+        let mut first = TrackPoint::from_coordinates(0.0, 0.0);
+        first.elevation = Some(100.0);
+        first.time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58));
+        first.heart_rate = Some(95);
+        first.cadence = Some(79);
+
+        let mut second = TrackPoint::from_coordinates(0.0, 0.01);
+        second.elevation = Some(100.0);
+        second.time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 6, 0));
+        second.power = Some(250);
+        second.speed = Some(4.5);
+        second.distance = Some(1105.0);
+
+        let mut third = TrackPoint::from_coordinates(0.0, 0.02);
+        third.time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 10, 0));
+
+        let mut track = Track::new();
+        track.route.push(TrackSegment {
+            points: vec![first, second],
+        });
+        track.route.push(TrackSegment {
+            points: vec![third],
+        });
+        track
+    }
+
+    fn write_to_string(track: &Track) -> String {
+        let mut buffer = Vec::new();
+        write_csv(track, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_writing_csv() {
+        let csv = write_to_string(&new_track());
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "segment,time,lat,lon,ele,hr,cad,power,speed,distance,calc_distance,calc_speed,pace"
+        );
+        assert_eq!(lines[1], "0,2020-04-22T16:01:58Z,0,0,100,95,79,,,,0,,");
+        assert!(lines[2].starts_with("0,2020-04-22T16:06:00Z,0,0.01,100,,,250,4.5,1105,1111.9"));
+        // New segment starts without speed, but keeps the distance
+        assert!(lines[3].starts_with("1,2020-04-22T16:10:00Z,0,0.02,,,,,,,1111.9"));
+        assert!(lines[3].ends_with(",,"));
+
+        let fields: Vec<&str> = lines[2].split(',').collect();
+        let speed: f64 = fields[11].parse().unwrap();
+        let pace: f64 = fields[12].parse().unwrap();
+        assert!((speed - 1111.95 / 242.0).abs() <= 0.01);
+        assert!((pace - 242.0 / 1.11195).abs() <= 1.0);
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let expected = new_track();
+        let track = read_csv_from_str(&write_to_string(&expected), &CsvOptions::new()).unwrap();
+
+        assert_eq!(track.route.len(), 2);
+        assert_eq!(track.creation_time, expected.route[0].points[0].time);
+        for (segment, expected_segment) in track.route.iter().zip(&expected.route) {
+            assert_eq!(segment.points.len(), expected_segment.points.len());
+            for (point, expected_point) in segment.points.iter().zip(&expected_segment.points) {
+                assert_eq!(point.latitude, expected_point.latitude);
+                assert_eq!(point.longitude, expected_point.longitude);
+                assert_eq!(point.elevation, expected_point.elevation);
+                assert_eq!(point.time, expected_point.time);
+                assert_eq!(point.heart_rate, expected_point.heart_rate);
+                assert_eq!(point.cadence, expected_point.cadence);
+                assert_eq!(point.power, expected_point.power);
+                // Recorded values only, not the calculated ones
+                assert_eq!(point.speed, expected_point.speed);
+                assert_eq!(point.distance, expected_point.distance);
+            }
+        }
+        assert_eq!(track.distance(), expected.distance());
+        assert_eq!(track.duration(), expected.duration());
+    }
+}
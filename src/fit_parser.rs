@@ -0,0 +1,725 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::mem;
+use std::time::Duration;
+
+use chrono::prelude::*;
+
+use super::{Lap, ParseError, Track, TrackPoint, TrackSegment};
+
+/// Seconds between Unix epoch and FIT epoch, 1989-12-31T00:00:00Z
+const FIT_EPOCH_OFFSET: i64 = 631_065_600;
+const SEMICIRCLES_TO_DEGREES: f64 = 180.0 / 2_147_483_648.0;
+
+const FIT_SIGNATURE: &[u8] = b".FIT";
+const MIN_HEADER_SIZE: usize = 12;
+const HEADER_SIZE_WITH_CRC: usize = 14;
+const CRC_SIZE: usize = 2;
+
+const MESG_FILE_ID: u16 = 0;
+const MESG_SESSION: u16 = 18;
+const MESG_LAP: u16 = 19;
+const MESG_RECORD: u16 = 20;
+const MESG_EVENT: u16 = 21;
+
+/// Field number shared by all messages
+const FIELD_TIMESTAMP: u8 = 253;
+
+const FILE_ID_TIME_CREATED: u8 = 4;
+
+const SESSION_START_TIME: u8 = 2;
+
+const LAP_START_TIME: u8 = 2;
+const LAP_TOTAL_ELAPSED_TIME: u8 = 7;
+const LAP_TOTAL_TIMER_TIME: u8 = 8;
+const LAP_TOTAL_DISTANCE: u8 = 9;
+const LAP_TOTAL_CALORIES: u8 = 11;
+const LAP_AVG_HEART_RATE: u8 = 15;
+const LAP_MAX_HEART_RATE: u8 = 16;
+
+const RECORD_POSITION_LAT: u8 = 0;
+const RECORD_POSITION_LONG: u8 = 1;
+const RECORD_ALTITUDE: u8 = 2;
+const RECORD_HEART_RATE: u8 = 3;
+const RECORD_CADENCE: u8 = 4;
+const RECORD_DISTANCE: u8 = 5;
+const RECORD_SPEED: u8 = 6;
+const RECORD_POWER: u8 = 7;
+const RECORD_TEMPERATURE: u8 = 13;
+const RECORD_ENHANCED_SPEED: u8 = 73;
+const RECORD_ENHANCED_ALTITUDE: u8 = 78;
+
+const EVENT_EVENT: u8 = 0;
+const EVENT_EVENT_TYPE: u8 = 1;
+const EVENT_TIMER: i64 = 0;
+const EVENT_TYPE_STOP: i64 = 1;
+const EVENT_TYPE_STOP_ALL: i64 = 4;
+
+const CRC_TABLE: [u16; 16] = [
+    0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
+    0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+];
+
+/// CRC-16 as defined by FIT protocol
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0;
+    for &byte in data {
+        for nibble in &[byte & 0x0F, byte >> 4] {
+            let tmp = CRC_TABLE[(crc & 0x0F) as usize];
+            crc = (crc >> 4) & 0x0FFF;
+            crc = crc ^ tmp ^ CRC_TABLE[*nibble as usize];
+        }
+    }
+    crc
+}
+
+fn invalid_data(offset: usize, message: &str) -> ParseError {
+    ParseError::InvalidData {
+        offset: offset as u64,
+        message: message.to_string(),
+    }
+}
+
+/// Cursor over the file contents which reports truncation as `InvalidData`
+struct ByteReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8], offset: usize) -> ByteReader<'a> {
+        ByteReader { data, offset }
+    }
+
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], ParseError> {
+        let end = self.offset + count;
+        if end > self.data.len() {
+            return Err(invalid_data(self.data.len(), "unexpected end of data"));
+        }
+
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, ParseError> {
+        self.read_bytes(1).map(|bytes| bytes[0])
+    }
+}
+
+#[derive(Debug)]
+struct FieldDefinition {
+    number: u8,
+    size: u8,
+    base_type: u8,
+}
+
+#[derive(Debug)]
+struct MessageDefinition {
+    global_number: u16,
+    big_endian: bool,
+    fields: Vec<FieldDefinition>,
+    /// Developer fields aren't interpreted, only skipped
+    developer_data_size: usize,
+}
+
+/// Decodes a scalar integer field. Returns `None` for the invalid value of the base type,
+/// as well as for arrays, strings and floating point fields which runstats doesn't use.
+fn decode_field(bytes: &[u8], base_type: u8, big_endian: bool) -> Option<i64> {
+    let unsigned = |bytes: &[u8]| {
+        let fold = |value: u64, &byte: &u8| (value << 8) | u64::from(byte);
+        if big_endian {
+            bytes.iter().fold(0, fold)
+        } else {
+            bytes.iter().rev().fold(0, fold)
+        }
+    };
+
+    // Low 5 bits of the base type identify it, the high bit only flags multi-byte types
+    let (size, signed, invalid) = match base_type & 0x1F {
+        0x00 | 0x02 | 0x0D => (1, false, 0xFF),
+        0x01 => (1, true, 0x7F),
+        0x03 => (2, true, 0x7FFF),
+        0x04 => (2, false, 0xFFFF),
+        0x05 => (4, true, 0x7FFF_FFFF),
+        0x06 => (4, false, 0xFFFF_FFFF),
+        0x0A => (1, false, 0x00),
+        0x0B => (2, false, 0x0000),
+        0x0C => (4, false, 0x0000_0000),
+        _ => return None,
+    };
+
+    if bytes.len() != size {
+        return None;
+    }
+
+    let value = unsigned(bytes);
+    if value == invalid {
+        return None;
+    }
+
+    if signed {
+        let shift = 64 - 8 * size;
+        Some(((value << shift) as i64) >> shift)
+    } else {
+        Some(value as i64)
+    }
+}
+
+/// Decoded data message: field number and value, invalid values are left out
+struct Message {
+    global_number: u16,
+    fields: Vec<(u8, i64)>,
+}
+
+impl Message {
+    fn field(&self, number: u8) -> Option<i64> {
+        self.fields
+            .iter()
+            .find(|&&(field_number, _)| field_number == number)
+            .map(|&(_, value)| value)
+    }
+
+    fn scaled_field(&self, number: u8, scale: f64, offset: f64) -> Option<f64> {
+        self.field(number).map(|value| value as f64 / scale - offset)
+    }
+
+    fn time_field(&self, number: u8) -> Option<DateTime<Utc>> {
+        self.field(number).and_then(fit_time)
+    }
+
+    fn degrees_field(&self, number: u8) -> Option<f64> {
+        self.field(number)
+            .map(|semicircles| semicircles as f64 * SEMICIRCLES_TO_DEGREES)
+    }
+}
+
+/// `None` for values out of `DateTime` range. Decoded fields are 32-bit at most and always
+/// fit, the check keeps conversion of any other value from panicking.
+fn fit_time(timestamp: i64) -> Option<DateTime<Utc>> {
+    let timestamp = timestamp.checked_add(FIT_EPOCH_OFFSET)?;
+    Utc.timestamp_opt(timestamp, 0).single()
+}
+
+/// Restores full timestamp from the 5-bit offset of compressed timestamp header
+fn expand_compressed_timestamp(last_timestamp: u32, time_offset: u8) -> u32 {
+    let time_offset = u32::from(time_offset & 0x1F);
+    // Wraps around like the 32-bit timestamp it restores
+    let mut timestamp = (last_timestamp & !0x1F).wrapping_add(time_offset);
+    if time_offset < last_timestamp & 0x1F {
+        timestamp = timestamp.wrapping_add(0x20);
+    }
+    timestamp
+}
+
+struct DecoderContext {
+    definitions: HashMap<u8, MessageDefinition>,
+    last_timestamp: Option<u32>,
+    track: Track,
+    current_segment: TrackSegment,
+    /// Number of segments already covered by laps
+    lap_segments_end: usize,
+}
+
+impl DecoderContext {
+    fn new() -> DecoderContext {
+        DecoderContext {
+            definitions: HashMap::new(),
+            last_timestamp: None,
+            track: Track::new(),
+            current_segment: TrackSegment::new(),
+            lap_segments_end: 0,
+        }
+    }
+
+    fn finish_segment(&mut self) {
+        let segment = mem::take(&mut self.current_segment);
+        if !segment.points.is_empty() {
+            self.track.route.push(segment);
+        }
+    }
+}
+
+fn read_definition_message(
+    reader: &mut ByteReader,
+    has_developer_data: bool,
+) -> Result<MessageDefinition, ParseError> {
+    let _reserved = reader.read_u8()?;
+    let big_endian = match reader.read_u8()? {
+        0 => false,
+        1 => true,
+        _ => return Err(invalid_data(reader.offset - 1, "unknown architecture")),
+    };
+
+    let global_number = reader.read_bytes(2)?;
+    let global_number = if big_endian {
+        u16::from_be_bytes([global_number[0], global_number[1]])
+    } else {
+        u16::from_le_bytes([global_number[0], global_number[1]])
+    };
+
+    let field_count = reader.read_u8()?;
+    let mut fields = Vec::with_capacity(field_count as usize);
+    for _ in 0..field_count {
+        let field = reader.read_bytes(3)?;
+        fields.push(FieldDefinition {
+            number: field[0],
+            size: field[1],
+            base_type: field[2],
+        });
+    }
+
+    let mut developer_data_size = 0;
+    if has_developer_data {
+        let developer_field_count = reader.read_u8()?;
+        for _ in 0..developer_field_count {
+            developer_data_size += reader.read_bytes(3)?[1] as usize;
+        }
+    }
+
+    Ok(MessageDefinition {
+        global_number,
+        big_endian,
+        fields,
+        developer_data_size,
+    })
+}
+
+fn read_data_message(
+    reader: &mut ByteReader,
+    definition: &MessageDefinition,
+) -> Result<Message, ParseError> {
+    let mut fields = Vec::with_capacity(definition.fields.len());
+    for field in &definition.fields {
+        let bytes = reader.read_bytes(field.size as usize)?;
+        if let Some(value) = decode_field(bytes, field.base_type, definition.big_endian) {
+            fields.push((field.number, value));
+        }
+    }
+    reader.read_bytes(definition.developer_data_size)?;
+
+    Ok(Message {
+        global_number: definition.global_number,
+        fields,
+    })
+}
+
+fn apply_record(message: &Message, context: &mut DecoderContext) {
+    let time = match message.time_field(FIELD_TIMESTAMP) {
+        Some(time) => time,
+        None => return,
+    };
+    // Records logged before GPS fix or on a treadmill have no position.
+    // They can't take part in distance calculation, so they're dropped.
+    let (latitude, longitude) = match (
+        message.degrees_field(RECORD_POSITION_LAT),
+        message.degrees_field(RECORD_POSITION_LONG),
+    ) {
+        (Some(latitude), Some(longitude)) => (latitude, longitude),
+        _ => return,
+    };
+
+    let mut point = TrackPoint::from_coordinates(latitude, longitude);
+    point.time = Some(time);
+    point.elevation = message
+        .scaled_field(RECORD_ENHANCED_ALTITUDE, 5.0, 500.0)
+        .or_else(|| message.scaled_field(RECORD_ALTITUDE, 5.0, 500.0));
+    point.heart_rate = message.field(RECORD_HEART_RATE).map(|value| value as u8);
+    point.cadence = message.field(RECORD_CADENCE).map(|value| value as u8);
+    point.distance = message.scaled_field(RECORD_DISTANCE, 100.0, 0.0);
+    point.speed = message
+        .scaled_field(RECORD_ENHANCED_SPEED, 1000.0, 0.0)
+        .or_else(|| message.scaled_field(RECORD_SPEED, 1000.0, 0.0));
+    point.power = message.field(RECORD_POWER).map(|value| value as u16);
+    point.air_temperature = message.field(RECORD_TEMPERATURE).map(|value| value as f64);
+
+    context.current_segment.points.push(point);
+}
+
+/// Lap message is written when the lap ends, so it covers every record since the previous lap
+fn apply_lap(message: &Message, context: &mut DecoderContext) {
+    context.finish_segment();
+    // Segments of a lap that can't be read belong to no lap rather than to the next one
+    let first_segment = context.lap_segments_end;
+    context.lap_segments_end = context.track.route.len();
+
+    let start_time = match message.time_field(LAP_START_TIME) {
+        Some(start_time) => start_time,
+        None => return,
+    };
+
+    let mut lap = Lap::new(start_time);
+    let total_time = message
+        .scaled_field(LAP_TOTAL_TIMER_TIME, 1000.0, 0.0)
+        .or_else(|| message.scaled_field(LAP_TOTAL_ELAPSED_TIME, 1000.0, 0.0));
+    // Signed base type lets a damaged file declare negative time
+    lap.total_time = Duration::from_secs_f64(total_time.unwrap_or(0.0).max(0.0));
+    lap.distance = message
+        .scaled_field(LAP_TOTAL_DISTANCE, 100.0, 0.0)
+        .unwrap_or(0.0);
+    lap.calories = message.field(LAP_TOTAL_CALORIES).map(|value| value as u16);
+    lap.avg_heart_rate = message.field(LAP_AVG_HEART_RATE).map(|value| value as u8);
+    lap.max_heart_rate = message.field(LAP_MAX_HEART_RATE).map(|value| value as u8);
+    lap.first_segment = first_segment;
+    lap.segment_count = context.lap_segments_end - first_segment;
+    context.track.laps.push(lap);
+}
+
+fn apply_message(message: &Message, context: &mut DecoderContext) {
+    match message.global_number {
+        MESG_RECORD => apply_record(message, context),
+        MESG_LAP => apply_lap(message, context),
+        MESG_EVENT => {
+            // Pause of the timer breaks the track, points after resume go to a new segment
+            let is_timer = message.field(EVENT_EVENT) == Some(EVENT_TIMER);
+            let is_stop = matches!(
+                message.field(EVENT_EVENT_TYPE),
+                Some(EVENT_TYPE_STOP) | Some(EVENT_TYPE_STOP_ALL)
+            );
+            if is_timer && is_stop {
+                context.finish_segment();
+            }
+        }
+        MESG_SESSION => {
+            if let Some(start_time) = message.time_field(SESSION_START_TIME) {
+                context.track.creation_time = Some(start_time);
+            }
+        }
+        MESG_FILE_ID if context.track.creation_time.is_none() => {
+            context.track.creation_time = message.time_field(FILE_ID_TIME_CREATED);
+        }
+        _ => {}
+    }
+}
+
+fn read_record(reader: &mut ByteReader, context: &mut DecoderContext) -> Result<(), ParseError> {
+    let header_offset = reader.offset;
+    let header = reader.read_u8()?;
+
+    if header & 0x80 != 0 {
+        let local_type = (header >> 5) & 0x03;
+        let definition = context
+            .definitions
+            .get(&local_type)
+            .ok_or_else(|| invalid_data(header_offset, "data message of undefined type"))?;
+        let last_timestamp = context.last_timestamp.ok_or_else(|| {
+            invalid_data(header_offset, "compressed timestamp without preceding timestamp")
+        })?;
+
+        let mut message = read_data_message(reader, definition)?;
+        let timestamp = expand_compressed_timestamp(last_timestamp, header & 0x1F);
+        message.fields.push((FIELD_TIMESTAMP, i64::from(timestamp)));
+        context.last_timestamp = Some(timestamp);
+        apply_message(&message, context);
+        return Ok(());
+    }
+
+    let local_type = header & 0x0F;
+    if header & 0x40 != 0 {
+        let definition = read_definition_message(reader, header & 0x20 != 0)?;
+        context.definitions.insert(local_type, definition);
+        return Ok(());
+    }
+
+    let definition = context
+        .definitions
+        .get(&local_type)
+        .ok_or_else(|| invalid_data(header_offset, "data message of undefined type"))?;
+    let message = read_data_message(reader, definition)?;
+    if let Some(timestamp) = message.field(FIELD_TIMESTAMP) {
+        context.last_timestamp = Some(timestamp as u32);
+    }
+    apply_message(&message, context);
+
+    Ok(())
+}
+
+/// Checks header and CRCs of the FIT file starting at `start`. Returns offsets of its
+/// data records and the end of the file including trailing CRC.
+fn read_file_header(data: &[u8], start: usize) -> Result<(usize, usize, usize), ParseError> {
+    let mut reader = ByteReader::new(data, start);
+    let header_size = reader.read_u8()? as usize;
+    if header_size < MIN_HEADER_SIZE {
+        return Err(invalid_data(start, "header is too short"));
+    }
+
+    let header = ByteReader::new(data, start).read_bytes(header_size)?;
+    if &header[8..12] != FIT_SIGNATURE {
+        return Err(invalid_data(start + 8, "missing .FIT signature"));
+    }
+
+    if header_size >= HEADER_SIZE_WITH_CRC {
+        let header_crc = u16::from_le_bytes([header[12], header[13]]);
+        // Zero means the writer didn't compute header CRC
+        if header_crc != 0 && header_crc != crc16(&header[..12]) {
+            return Err(invalid_data(start + 12, "header CRC mismatch"));
+        }
+    }
+
+    let data_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let data_start = start + header_size;
+    let data_end = data_start + data_size;
+    let file = ByteReader::new(data, start).read_bytes(header_size + data_size + CRC_SIZE)?;
+    let (contents, crc) = file.split_at(header_size + data_size);
+    if u16::from_le_bytes([crc[0], crc[1]]) != crc16(contents) {
+        return Err(invalid_data(data_end, "file CRC mismatch"));
+    }
+
+    Ok((data_start, data_end, data_end + CRC_SIZE))
+}
+
+/// Decodes FIT activity held in memory. Several FIT files chained one after another
+/// give a single track.
+pub fn read_fit_from_bytes(data: &[u8]) -> Result<Track, ParseError> {
+    let mut context = DecoderContext::new();
+    let mut file_start = 0;
+
+    while file_start < data.len() {
+        let (data_start, data_end, file_end) = read_file_header(data, file_start)?;
+        // Local message types are scoped to a single file
+        context.definitions.clear();
+
+        let mut reader = ByteReader::new(&data[..data_end], data_start);
+        while reader.offset < data_end {
+            read_record(&mut reader, &mut context)?;
+        }
+
+        file_start = file_end;
+    }
+
+    context.finish_segment();
+    context.track.sort_points_by_time();
+    Ok(context.track)
+}
+
+pub fn read_fit_from_reader<R: Read>(mut reader: R) -> Result<Track, ParseError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    read_fit_from_bytes(&data)
+}
+
+pub fn read_fit(path: &str) -> Result<Track, ParseError> {
+    let file = File::open(path)?;
+
+    read_fit_from_reader(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    const ACTIVITY_FIT: &[u8] = include_bytes!("../tests/data/activity.fit");
+    const SHORT_HEADER_FIT: &[u8] = include_bytes!("../tests/data/short_header.fit");
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b""), 0);
+        assert_eq!(crc16(b"123456789"), 0xBB3D);
+    }
+
+    #[test_case(&[0x7F], 0x02, false, Some(127); "uint8")]
+    #[test_case(&[0xFF], 0x02, false, None; "invalid uint8")]
+    #[test_case(&[0xFE], 0x01, false, Some(-2); "sint8")]
+    #[test_case(&[0x34, 0x12], 0x84, false, Some(0x1234); "little endian uint16")]
+    #[test_case(&[0x12, 0x34], 0x84, true, Some(0x1234); "big endian uint16")]
+    #[test_case(&[0x00, 0x00, 0x00, 0x80], 0x85, false, Some(i32::MIN as i64); "sint32")]
+    #[test_case(&[0xFF, 0xFF, 0xFF, 0x7F], 0x85, false, None; "invalid sint32")]
+    #[test_case(&[0x00, 0x00], 0x8B, false, None; "invalid uint16z")]
+    #[test_case(&[0x01, 0x02], 0x02, false, None; "uint8 array")]
+    fn test_decode_field(bytes: &[u8], base_type: u8, big_endian: bool, expected: Option<i64>) {
+        assert_eq!(decode_field(bytes, base_type, big_endian), expected);
+    }
+
+    #[test_case(0x40, 0x05, 0x45; "offset after last")]
+    #[test_case(0x45, 0x05, 0x45; "same second")]
+    #[test_case(0x5E, 0x02, 0x62; "rollover")]
+    #[test_case(u32::MAX, 0x02, 0x02; "wraparound")]
+    fn test_expand_compressed_timestamp(last: u32, offset: u8, expected: u32) {
+        assert_eq!(expand_compressed_timestamp(last, offset), expected);
+    }
+
+    #[test]
+    fn test_fit_time() {
+        assert_eq!(fit_time(0), Some(Utc.ymd(1989, 12, 31).and_hms(0, 0, 0)));
+        assert_eq!(fit_time(i64::MAX), None);
+    }
+
+    #[test]
+    fn test_lap_with_negative_time() {
+        let message = Message {
+            global_number: MESG_LAP,
+            fields: vec![(LAP_START_TIME, 1_000_000_000), (LAP_TOTAL_TIMER_TIME, -5000)],
+        };
+        let mut context = DecoderContext::new();
+
+        apply_lap(&message, &mut context);
+        assert_eq!(context.track.laps[0].total_time, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_lap_after_lap_without_start_time() {
+        let mut context = DecoderContext::new();
+        context.track.route.push(TrackSegment::new());
+        apply_lap(
+            &Message {
+                global_number: MESG_LAP,
+                fields: vec![(LAP_TOTAL_TIMER_TIME, 5000)],
+            },
+            &mut context,
+        );
+
+        context.track.route.push(TrackSegment::new());
+        apply_lap(
+            &Message {
+                global_number: MESG_LAP,
+                fields: vec![(LAP_START_TIME, 1_000_000_000)],
+            },
+            &mut context,
+        );
+
+        assert_eq!(context.track.laps.len(), 1);
+        assert_eq!(context.track.laps[0].first_segment, 1);
+        assert_eq!(context.track.laps[0].segment_count, 1);
+    }
+
+    #[test]
+    fn test_reading_fit() {
+        let track = read_fit_from_bytes(ACTIVITY_FIT).unwrap();
+        assert_eq!(track.creation_time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58)));
+        assert_eq!(track.route.len(), 2);
+        assert_eq!(track.route[0].points.len(), 5);
+        assert_eq!(track.route[1].points.len(), 2);
+
+        let point = &track.route[0].points[0];
+        assert!((point.latitude - 10.1025420).abs() < 1e-6);
+        assert!((point.longitude - 15.1583540).abs() < 1e-6);
+        assert!((point.elevation.unwrap() - 478.2).abs() < 1e-9);
+        assert_eq!(point.time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58)));
+        assert_eq!(point.heart_rate, Some(95));
+        assert_eq!(point.cadence, Some(79));
+        assert_eq!(point.distance, Some(0.0));
+        assert_eq!(point.speed, Some(2.5));
+        assert_eq!(point.power, Some(250));
+
+        let point = &track.route[0].points[2];
+        assert_eq!(point.distance, Some(22.24));
+        assert_eq!(point.power, None);
+    }
+
+    #[test]
+    fn test_reading_fit_compressed_timestamps() {
+        let track = read_fit_from_bytes(ACTIVITY_FIT).unwrap();
+
+        // Written in big-endian with developer fields and compressed timestamp headers
+        let point = &track.route[0].points[3];
+        assert_eq!(point.time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 2, 13)));
+        assert_eq!(point.elevation, Some(481.0));
+        assert_eq!(point.heart_rate, Some(101));
+        assert_eq!(point.power, None);
+
+        let point = &track.route[0].points[4];
+        assert_eq!(point.time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 2, 18)));
+    }
+
+    #[test]
+    fn test_reading_fit_laps() {
+        let track = read_fit_from_bytes(ACTIVITY_FIT).unwrap();
+        assert_eq!(track.laps.len(), 2);
+
+        let lap = &track.laps[0];
+        assert_eq!(lap.start_time, Utc.ymd(2020, 4, 22).and_hms(16, 1, 58));
+        assert_eq!(lap.total_time, Duration::from_secs(20));
+        assert_eq!(lap.distance, 44.48);
+        assert_eq!(lap.calories, Some(3));
+        assert_eq!(lap.avg_heart_rate, Some(98));
+        assert_eq!(lap.max_heart_rate, Some(102));
+        assert_eq!(lap.first_segment, 0);
+        assert_eq!(lap.segment_count, 1);
+
+        let lap = &track.laps[1];
+        assert_eq!(lap.start_time, Utc.ymd(2020, 4, 22).and_hms(16, 2, 28));
+        assert_eq!(lap.first_segment, 1);
+        assert_eq!(lap.segment_count, 1);
+    }
+
+    #[test]
+    fn test_fit_track_stats() {
+        let track = read_fit_from_bytes(ACTIVITY_FIT).unwrap();
+        assert_eq!(track.distance(), 55);
+        assert_eq!(track.duration(), Duration::from_secs(25));
+        assert_eq!(track.max_power(), Some(310));
+    }
+
+    #[test]
+    fn test_reading_fit_with_short_header() {
+        let track = read_fit_from_bytes(SHORT_HEADER_FIT).unwrap();
+        assert_eq!(track.route.len(), 1);
+        assert_eq!(track.route[0].points.len(), 2);
+        assert_eq!(track.creation_time, None);
+    }
+
+    #[test]
+    fn test_reading_chained_fit() {
+        let data = [ACTIVITY_FIT, SHORT_HEADER_FIT].concat();
+
+        let track = read_fit_from_bytes(&data).unwrap();
+        assert_eq!(track.route.len(), 3);
+    }
+
+    #[test]
+    fn test_reading_corrupted_fit() {
+        let mut data = ACTIVITY_FIT.to_vec();
+        data[100] ^= 0x01;
+
+        match read_fit_from_bytes(&data) {
+            Err(ParseError::InvalidData { offset, message }) => {
+                assert_eq!(offset, ACTIVITY_FIT.len() as u64 - 2);
+                assert_eq!(message, "file CRC mismatch");
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reading_truncated_fit() {
+        let data = &ACTIVITY_FIT[..ACTIVITY_FIT.len() - 10];
+
+        match read_fit_from_bytes(data) {
+            Err(ParseError::InvalidData { message, .. }) => {
+                assert_eq!(message, "unexpected end of data")
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reading_not_fit() {
+        let result = read_fit_from_bytes(b"<?xml version=\"1.0\"?><gpx></gpx>");
+        assert!(matches!(result, Err(ParseError::InvalidData { .. })));
+    }
+
+    #[test]
+    fn test_reading_missing_fit_file() {
+        let result = read_fit("/nonexistent/path/to/activity.fit");
+        assert!(matches!(result, Err(ParseError::Io(_))));
+    }
+}
@@ -0,0 +1,736 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::{SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+
+use super::{ActivityType, Lap, Link, Location, ParseError, Track, TrackPoint, TrackSegment};
+
+/// `[longitude, latitude]` or `[longitude, latitude, elevation]`
+#[derive(Debug, PartialEq)]
+struct Position(f64, f64, Option<f64>);
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = if self.2.is_some() { 3 } else { 2 };
+        let mut seq = serializer.serialize_seq(Some(len))?;
+        seq.serialize_element(&self.0)?;
+        seq.serialize_element(&self.1)?;
+        if let Some(elevation) = self.2 {
+            seq.serialize_element(&elevation)?;
+        }
+        seq.end()
+    }
+}
+
+struct PositionVisitor;
+
+impl<'de> Visitor<'de> for PositionVisitor {
+    type Value = Position;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("position of two or three numbers")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Position, A::Error> {
+        let longitude = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let latitude = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let elevation = seq.next_element()?;
+        // Positions may carry more values, e.g. a timestamp, which runstats doesn't know about
+        while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+
+        Ok(Position(longitude, latitude, elevation))
+    }
+}
+
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Position, D::Error> {
+        deserializer.deserialize_seq(PositionVisitor)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    LineString { coordinates: Vec<Position> },
+    MultiLineString { coordinates: Vec<Vec<Position>> },
+    /// Points and polygons don't make a track
+    #[serde(other)]
+    Other,
+}
+
+/// Per-point values of `coordinateProperties`. `MultiLineString` nests them by line,
+/// `LineString` keeps a flat list.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum PointValues<T> {
+    Lines(Vec<Vec<Option<T>>>),
+    Line(Vec<Option<T>>),
+}
+
+impl<T: Copy> PointValues<T> {
+    fn from_track<F>(track: &Track, value: F) -> Option<PointValues<T>>
+    where
+        F: Fn(&TrackPoint) -> Option<T>,
+    {
+        let lines: Vec<Vec<Option<T>>> = track
+            .route
+            .iter()
+            .map(|segment| segment.points.iter().map(&value).collect())
+            .collect();
+
+        if lines.iter().flatten().any(Option::is_some) {
+            Some(PointValues::Lines(lines))
+        } else {
+            None
+        }
+    }
+
+    /// Value of the point by its segment and index within segment, as well as
+    /// its index counting from the start of the track
+    fn get(&self, segment: usize, index: usize, flat_index: usize) -> Option<T> {
+        match self {
+            PointValues::Lines(lines) => lines.get(segment).and_then(|line| line.get(index)),
+            PointValues::Line(line) => line.get(flat_index),
+        }
+        .copied()
+        .flatten()
+    }
+}
+
+/// Value of the point at `(segment, index, flat_index)`, see `PointValues::get`
+fn point_value<T: Copy>(values: &Option<PointValues<T>>, at: (usize, usize, usize)) -> Option<T> {
+    let (segment, index, flat_index) = at;
+    values
+        .as_ref()
+        .and_then(|values| values.get(segment, index, flat_index))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CoordinateProperties {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    times: Option<PointValues<DateTime<Utc>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    heart_rates: Option<PointValues<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cadences: Option<PointValues<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    powers: Option<PointValues<u16>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    speeds: Option<PointValues<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    courses: Option<PointValues<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    air_temperatures: Option<PointValues<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    water_temperatures: Option<PointValues<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    depths: Option<PointValues<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    distances: Option<PointValues<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hdops: Option<PointValues<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    satellites: Option<PointValues<u8>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LapProperties {
+    start_time: DateTime<Utc>,
+    /// Seconds
+    total_time: f64,
+    distance: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    calories: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    avg_heart_rate: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_heart_rate: Option<u8>,
+    first_segment: usize,
+    segment_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LinkProperties {
+    href: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mime_type: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Properties {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    time: Option<DateTime<Utc>>,
+    /// Activity type as written by `ActivityType`'s `Display`, e.g. `running`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    activity_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    links: Vec<LinkProperties>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    number: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    device: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    laps: Vec<LapProperties>,
+    #[serde(default)]
+    coordinate_properties: CoordinateProperties,
+}
+
+/// GeoJSON object. Only the kinds which can hold tracks are supported.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum GeoJson {
+    FeatureCollection {
+        features: Vec<GeoJson>,
+    },
+    Feature {
+        geometry: Option<Geometry>,
+        #[serde(default)]
+        properties: Option<Box<Properties>>,
+    },
+}
+
+fn track_to_feature(track: &Track) -> GeoJson {
+    let coordinates = track
+        .route
+        .iter()
+        .map(|segment| {
+            segment
+                .points
+                .iter()
+                .map(|point| Position(point.longitude, point.latitude, point.elevation))
+                .collect()
+        })
+        .collect();
+
+    let laps = track
+        .laps
+        .iter()
+        .map(|lap| LapProperties {
+            start_time: lap.start_time,
+            total_time: lap.total_time.as_secs_f64(),
+            distance: lap.distance,
+            calories: lap.calories,
+            avg_heart_rate: lap.avg_heart_rate,
+            max_heart_rate: lap.max_heart_rate,
+            first_segment: lap.first_segment,
+            segment_count: lap.segment_count,
+        })
+        .collect();
+
+    let links = track
+        .links
+        .iter()
+        .map(|link| LinkProperties {
+            href: link.href.clone(),
+            text: link.text.clone(),
+            mime_type: link.mime_type.clone(),
+        })
+        .collect();
+
+    let coordinate_properties = CoordinateProperties {
+        times: PointValues::from_track(track, |point| point.time),
+        heart_rates: PointValues::from_track(track, |point| point.heart_rate),
+        cadences: PointValues::from_track(track, |point| point.cadence),
+        powers: PointValues::from_track(track, |point| point.power),
+        speeds: PointValues::from_track(track, |point| point.speed),
+        courses: PointValues::from_track(track, |point| point.course),
+        air_temperatures: PointValues::from_track(track, |point| point.air_temperature),
+        water_temperatures: PointValues::from_track(track, |point| point.water_temperature),
+        depths: PointValues::from_track(track, |point| point.depth),
+        distances: PointValues::from_track(track, |point| point.distance),
+        hdops: PointValues::from_track(track, |point| point.hdop),
+        satellites: PointValues::from_track(track, |point| point.satellites),
+    };
+
+    GeoJson::Feature {
+        geometry: Some(Geometry::MultiLineString { coordinates }),
+        properties: Some(Box::new(Properties {
+            name: Some(track.name.clone()).filter(|name| !name.is_empty()),
+            time: track.creation_time,
+            activity_type: track.activity_type.as_ref().map(ToString::to_string),
+            description: track.description.clone(),
+            comment: track.comment.clone(),
+            source: track.source.clone(),
+            links,
+            number: track.number,
+            device: track.device.clone(),
+            laps,
+            coordinate_properties,
+        })),
+    }
+}
+
+fn lap_from_properties(lap: LapProperties) -> Result<Lap, ParseError> {
+    // Values are checked once the whole document is read, so their position is unknown
+    let total_time = Duration::try_from_secs_f64(lap.total_time.max(0.0)).map_err(|_| {
+        ParseError::InvalidValue {
+            field: String::from("totalTime"),
+            value: lap.total_time.to_string(),
+            location: Location { line: 1, column: 1 },
+        }
+    })?;
+
+    let mut converted = Lap::new(lap.start_time);
+    converted.total_time = total_time;
+    converted.distance = lap.distance;
+    converted.calories = lap.calories;
+    converted.avg_heart_rate = lap.avg_heart_rate;
+    converted.max_heart_rate = lap.max_heart_rate;
+    converted.first_segment = lap.first_segment;
+    converted.segment_count = lap.segment_count;
+    Ok(converted)
+}
+
+fn feature_to_track(
+    geometry: Geometry,
+    properties: Option<Box<Properties>>,
+) -> Result<Option<Track>, ParseError> {
+    let lines = match geometry {
+        Geometry::LineString { coordinates } => vec![coordinates],
+        Geometry::MultiLineString { coordinates } => coordinates,
+        Geometry::Other => return Ok(None),
+    };
+    let properties = properties.map(|properties| *properties).unwrap_or_default();
+    let values = &properties.coordinate_properties;
+
+    let mut track = Track::new();
+    track.name = properties.name.unwrap_or_default();
+    track.creation_time = properties.time;
+    track.activity_type = properties
+        .activity_type
+        .as_deref()
+        .map(ActivityType::from_name);
+    track.description = properties.description;
+    track.comment = properties.comment;
+    track.source = properties.source;
+    track.links = properties
+        .links
+        .into_iter()
+        .map(|link| Link {
+            href: link.href,
+            text: link.text,
+            mime_type: link.mime_type,
+        })
+        .collect();
+    track.number = properties.number;
+    track.device = properties.device;
+
+    let mut flat_index = 0;
+    for (segment_index, line) in lines.into_iter().enumerate() {
+        let mut segment = TrackSegment::new();
+        for (index, Position(longitude, latitude, elevation)) in line.into_iter().enumerate() {
+            let at = (segment_index, index, flat_index);
+
+            let mut point = TrackPoint::from_coordinates(latitude, longitude);
+            point.elevation = elevation;
+            point.time = point_value(&values.times, at);
+            point.heart_rate = point_value(&values.heart_rates, at);
+            point.cadence = point_value(&values.cadences, at);
+            point.power = point_value(&values.powers, at);
+            point.speed = point_value(&values.speeds, at);
+            point.course = point_value(&values.courses, at);
+            point.air_temperature = point_value(&values.air_temperatures, at);
+            point.water_temperature = point_value(&values.water_temperatures, at);
+            point.depth = point_value(&values.depths, at);
+            point.distance = point_value(&values.distances, at);
+            point.hdop = point_value(&values.hdops, at);
+            point.satellites = point_value(&values.satellites, at);
+            segment.points.push(point);
+            flat_index += 1;
+        }
+        track.route.push(segment);
+    }
+
+    track.laps = properties
+        .laps
+        .into_iter()
+        .map(lap_from_properties)
+        .collect::<Result<_, _>>()?;
+
+    Ok(Some(track))
+}
+
+fn collect_tracks(document: GeoJson, tracks: &mut Vec<Track>) -> Result<(), ParseError> {
+    match document {
+        GeoJson::FeatureCollection { features } => {
+            for feature in features {
+                collect_tracks(feature, tracks)?;
+            }
+        }
+        GeoJson::Feature {
+            geometry: Some(geometry),
+            properties,
+        } => tracks.extend(feature_to_track(geometry, properties)?),
+        GeoJson::Feature { geometry: None, .. } => {}
+    }
+
+    Ok(())
+}
+
+fn convert_json_error(err: serde_json::Error) -> ParseError {
+    if err.is_io() {
+        return ParseError::Io(io::Error::from(err));
+    }
+
+    let location = Location {
+        line: err.line() as u64,
+        column: err.column() as u64,
+    };
+    // serde_json appends location to the message, it's reported separately
+    let message = err.to_string();
+    let message = match message.rfind(" at line ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    };
+
+    ParseError::Syntax { location, message }
+}
+
+/// Writes tracks as GeoJSON `FeatureCollection`. Each track becomes a `MultiLineString`
+/// feature with a line per segment, point data other than coordinates goes to
+/// `coordinateProperties`.
+pub fn write_geojson<W: Write>(tracks: &[Track], writer: W) -> io::Result<()> {
+    let features = tracks.iter().map(track_to_feature).collect();
+    serde_json::to_writer(writer, &GeoJson::FeatureCollection { features })?;
+    Ok(())
+}
+
+pub fn tracks_to_geojson(tracks: &[Track]) -> String {
+    let mut buffer = Vec::new();
+    write_geojson(tracks, &mut buffer).expect("writing to memory can't fail");
+    String::from_utf8(buffer).expect("JSON is always valid UTF-8")
+}
+
+/// Reads tracks from GeoJSON `FeatureCollection` or a single `Feature`. `LineString` and
+/// `MultiLineString` features become tracks, features of other geometries are skipped.
+pub fn read_geojson_tracks_from_reader<R: Read>(reader: R) -> Result<Vec<Track>, ParseError> {
+    let document: GeoJson =
+        serde_json::from_reader(BufReader::new(reader)).map_err(convert_json_error)?;
+
+    let mut tracks = Vec::new();
+    collect_tracks(document, &mut tracks)?;
+    Ok(tracks)
+}
+
+pub fn read_geojson_tracks_from_str(geojson: &str) -> Result<Vec<Track>, ParseError> {
+    read_geojson_tracks_from_reader(geojson.as_bytes())
+}
+
+pub fn read_geojson_tracks(path: &str) -> Result<Vec<Track>, ParseError> {
+    let file = File::open(path)?;
+
+    read_geojson_tracks_from_reader(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use serde_json::{json, Value};
+
+    fn new_track() -> Track {
+        let mut first = TrackPoint::from_coordinates(10.1025420, 15.1583540);
+        first.elevation = Some(478.2);
+        first.time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58));
+        first.heart_rate = Some(95);
+        first.cadence = Some(79);
+        first.distance = Some(0.0);
+
+        let mut second = TrackPoint::from_coordinates(10.1026420, 15.1583542);
+        second.time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 2, 4));
+        second.power = Some(250);
+        second.speed = Some(2.5);
+        second.distance = Some(11.1);
+        second.hdop = Some(0.9);
+        second.satellites = Some(8);
+
+        let mut third = TrackPoint::from_coordinates(10.1027420, 15.1583544);
+        third.elevation = Some(481.0);
+        third.time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 3, 0));
+        third.heart_rate = Some(101);
+        third.course = Some(90.5);
+        third.air_temperature = Some(21.5);
+        third.water_temperature = Some(18.0);
+        third.depth = Some(1.5);
+
+        let mut lap = Lap::new(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58));
+        lap.total_time = Duration::from_millis(62500);
+        lap.distance = 22.2;
+        lap.calories = Some(12);
+        lap.avg_heart_rate = Some(98);
+        lap.max_heart_rate = Some(104);
+        lap.segment_count = 2;
+
+        let mut track = Track::new();
+        track.name = String::from("Evening run");
+        track.creation_time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58));
+        track.activity_type = Some(ActivityType::Running);
+        track.description = Some(String::from("Easy pace along the river"));
+        track.comment = Some(String::from("Windy"));
+        track.source = Some(String::from("Forerunner 245"));
+        track.links.push(Link {
+            href: String::from("https://example.com/activity/1"),
+            text: Some(String::from("Activity")),
+            mime_type: Some(String::from("text/html")),
+        });
+        track.number = Some(3);
+        track.device = Some(String::from("Garmin Connect"));
+        track.route.push(TrackSegment {
+            points: vec![first, second],
+        });
+        track.route.push(TrackSegment {
+            points: vec![third],
+        });
+        track.laps.push(lap);
+        track
+    }
+
+    fn assert_points_eq(actual: &TrackPoint, expected: &TrackPoint) {
+        assert_eq!(actual.latitude, expected.latitude);
+        assert_eq!(actual.longitude, expected.longitude);
+        assert_eq!(actual.elevation, expected.elevation);
+        assert_eq!(actual.time, expected.time);
+        assert_eq!(actual.heart_rate, expected.heart_rate);
+        assert_eq!(actual.cadence, expected.cadence);
+        assert_eq!(actual.speed, expected.speed);
+        assert_eq!(actual.course, expected.course);
+        assert_eq!(actual.air_temperature, expected.air_temperature);
+        assert_eq!(actual.water_temperature, expected.water_temperature);
+        assert_eq!(actual.depth, expected.depth);
+        assert_eq!(actual.power, expected.power);
+        assert_eq!(actual.distance, expected.distance);
+        assert_eq!(actual.hdop, expected.hdop);
+        assert_eq!(actual.satellites, expected.satellites);
+    }
+
+    #[test]
+    fn test_writing_geojson() {
+        let geojson = tracks_to_geojson(&[new_track()]);
+        let value: Value = serde_json::from_str(&geojson).unwrap();
+
+        assert_eq!(value["type"], "FeatureCollection");
+        let feature = &value["features"][0];
+        assert_eq!(feature["type"], "Feature");
+        assert_eq!(
+            feature["geometry"],
+            json!({
+                "type": "MultiLineString",
+                "coordinates": [
+                    [[15.1583540, 10.1025420, 478.2], [15.1583542, 10.1026420]],
+                    [[15.1583544, 10.1027420, 481.0]]
+                ]
+            })
+        );
+
+        let properties = &feature["properties"];
+        assert_eq!(properties["name"], "Evening run");
+        assert_eq!(properties["time"], "2020-04-22T16:01:58Z");
+        assert_eq!(properties["activityType"], "running");
+        assert_eq!(
+            properties["links"],
+            json!([{
+                "href": "https://example.com/activity/1",
+                "text": "Activity",
+                "mimeType": "text/html"
+            }])
+        );
+        assert_eq!(properties["laps"][0]["totalTime"], 62.5);
+        assert_eq!(
+            properties["coordinateProperties"]["times"],
+            json!([
+                ["2020-04-22T16:01:58Z", "2020-04-22T16:02:04Z"],
+                ["2020-04-22T16:03:00Z"]
+            ])
+        );
+        assert_eq!(
+            properties["coordinateProperties"]["heartRates"],
+            json!([[95, null], [101]])
+        );
+        assert_eq!(properties["coordinateProperties"]["cadences"], json!([[79, null], [null]]));
+    }
+
+    #[test]
+    fn test_writing_geojson_skips_missing_values() {
+        let mut track = Track::new();
+        track.route.push(TrackSegment {
+            points: vec![TrackPoint::from_coordinates(10.0, 15.0)],
+        });
+
+        let geojson = tracks_to_geojson(&[track]);
+        assert_eq!(
+            geojson,
+            "{\"type\":\"FeatureCollection\",\"features\":[{\"type\":\"Feature\",\
+\"geometry\":{\"type\":\"MultiLineString\",\"coordinates\":[[[15.0,10.0]]]},\
+\"properties\":{\"coordinateProperties\":{}}}]}"
+        );
+    }
+
+    #[test]
+    fn test_geojson_round_trip() {
+        let expected = new_track();
+        let tracks = read_geojson_tracks_from_str(&expected.to_geojson()).unwrap();
+        assert_eq!(tracks.len(), 1);
+
+        let track = &tracks[0];
+        assert_eq!(track.name, expected.name);
+        assert_eq!(track.creation_time, expected.creation_time);
+        assert_eq!(track.activity_type, expected.activity_type);
+        assert_eq!(track.description, expected.description);
+        assert_eq!(track.comment, expected.comment);
+        assert_eq!(track.source, expected.source);
+        assert_eq!(track.links, expected.links);
+        assert_eq!(track.number, expected.number);
+        assert_eq!(track.device, expected.device);
+        assert_eq!(track.route.len(), expected.route.len());
+        for (segment, expected_segment) in track.route.iter().zip(&expected.route) {
+            assert_eq!(segment.points.len(), expected_segment.points.len());
+            for (point, expected_point) in segment.points.iter().zip(&expected_segment.points) {
+                assert_points_eq(point, expected_point);
+            }
+        }
+
+        assert_eq!(track.laps.len(), 1);
+        assert_eq!(track.laps[0].start_time, expected.laps[0].start_time);
+        assert_eq!(track.laps[0].total_time, expected.laps[0].total_time);
+        assert_eq!(track.laps[0].distance, expected.laps[0].distance);
+        assert_eq!(track.laps[0].calories, Some(12));
+        assert_eq!(track.laps[0].avg_heart_rate, Some(98));
+        assert_eq!(track.laps[0].max_heart_rate, Some(104));
+        assert_eq!(track.laps[0].first_segment, 0);
+        assert_eq!(track.laps[0].segment_count, 2);
+        assert_eq!(track.distance(), expected.distance());
+        assert_eq!(track.duration(), expected.duration());
+    }
+
+    #[test]
+    fn test_reading_line_string_feature() {
+        let geojson = r#"{
+            "type": "Feature",
+            "geometry": {
+                "type": "LineString",
+                "coordinates": [[15.15, 10.10, 478.2, 1587571318], [15.16, 10.11]]
+            },
+            "properties": {
+                "name": "Morning ride",
+                "coordinateProperties": {
+                    "times": ["2020-04-22T16:01:58Z", "2020-04-22T18:02:04+02:00"],
+                    "heartRates": [null, 120]
+                }
+            }
+        }"#;
+
+        let tracks = read_geojson_tracks_from_str(geojson).unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].name, "Morning ride");
+        assert_eq!(tracks[0].route.len(), 1);
+
+        let points = &tracks[0].route[0].points;
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].elevation, Some(478.2));
+        assert_eq!(points[0].heart_rate, None);
+        assert_eq!(points[1].elevation, None);
+        assert_eq!(points[1].time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 2, 4)));
+        assert_eq!(points[1].heart_rate, Some(120));
+    }
+
+    #[test]
+    fn test_reading_skips_other_geometries() {
+        let geojson = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "geometry": {"type": "Point", "coordinates": [15.15, 10.10]}},
+            {"type": "Feature", "geometry": null, "properties": {"name": "Nothing"}},
+            {"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[15.15, 10.10]]}}
+        ]}"#;
+
+        let tracks = read_geojson_tracks_from_str(geojson).unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].route[0].points.len(), 1);
+        assert_eq!(tracks[0].route[0].points[0].time, None);
+    }
+
+    #[test]
+    fn test_reading_malformed_geojson() {
+        let geojson = "{\"type\": \"FeatureCollection\",\n\"features\": [";
+
+        match read_geojson_tracks_from_str(geojson) {
+            Err(ParseError::Syntax { location, message }) => {
+                assert_eq!(location.line, 2);
+                assert_eq!(message, "EOF while parsing a list");
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reading_invalid_position() {
+        let geojson = r#"{"type": "Feature",
+"geometry": {"type": "LineString", "coordinates": [[15.15]]}}"#;
+
+        match read_geojson_tracks_from_str(geojson) {
+            Err(ParseError::Syntax { message, .. }) => {
+                assert!(message.contains("position of two or three numbers"))
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reading_lap_time_out_of_range() {
+        let geojson = r#"{"type": "Feature",
+"geometry": {"type": "LineString", "coordinates": [[15.15, 10.10]]},
+"properties": {"laps": [{"startTime": "2020-04-22T16:01:58Z", "totalTime": 1e300,
+"distance": 0.0, "firstSegment": 0, "segmentCount": 1}]}}"#;
+
+        match read_geojson_tracks_from_str(geojson) {
+            Err(ParseError::InvalidValue { field, value, .. }) => {
+                assert_eq!(field, "totalTime");
+                assert_eq!(value, 1e300.to_string());
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reading_missing_geojson_file() {
+        let result = read_geojson_tracks("/nonexistent/path/to/track.geojson");
+        assert!(matches!(result, Err(ParseError::Io(_))));
+    }
+}
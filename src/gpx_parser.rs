@@ -16,15 +16,21 @@
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::mem;
+use std::str::FromStr;
 
 use xml::attribute::OwnedAttribute;
+use xml::common::{Position, TextPosition};
 use xml::name::OwnedName;
-use xml::reader::XmlEvent;
+use xml::reader::{Error as XmlError, ErrorKind as XmlErrorKind, XmlEvent};
 use xml::EventReader;
 
 use chrono::prelude::*;
 
-use super::{ParseError, Track, TrackPoint, TrackSegment};
+use super::archive::{read_first_gpx_document, read_first_gpx_document_from_stream};
+use super::{
+    ActivityType, Bounds, Copyright, Gpx, Link, Location, Metadata, ParseError, ParseOptions,
+    ParseWarning, Person, Route, Track, TrackPoint, TrackSegment, Waypoint,
+};
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum GpxXmlTag {
@@ -36,8 +42,77 @@ enum GpxXmlTag {
     TrackPoint,
     Elevation,
     Time,
+    Waypoint,
+    Route,
+    RoutePoint,
+    Description,
+    Symbol,
+    Speed,
+    Course,
+    Type,
+    Comment,
+    Source,
+    Link,
+    LinkText,
+    Number,
+    Author,
+    Email,
+    Copyright,
+    Year,
+    License,
+    Keywords,
+    Bounds,
+    Url,
+    UrlName,
     ExtHeartRate,
     ExtCadence,
+    ExtAirTemperature,
+    ExtWaterTemperature,
+    ExtDepth,
+    ExtPower,
+}
+
+impl GpxXmlTag {
+    fn name(self) -> &'static str {
+        match self {
+            GpxXmlTag::Gpx => "gpx",
+            GpxXmlTag::Metadata => "metadata",
+            GpxXmlTag::Track => "trk",
+            GpxXmlTag::Name => "name",
+            GpxXmlTag::TrackSegment => "trkseg",
+            GpxXmlTag::TrackPoint => "trkpt",
+            GpxXmlTag::Elevation => "ele",
+            GpxXmlTag::Time => "time",
+            GpxXmlTag::Waypoint => "wpt",
+            GpxXmlTag::Route => "rte",
+            GpxXmlTag::RoutePoint => "rtept",
+            GpxXmlTag::Description => "desc",
+            GpxXmlTag::Symbol => "sym",
+            GpxXmlTag::Speed => "speed",
+            GpxXmlTag::Course => "course",
+            GpxXmlTag::Type => "type",
+            GpxXmlTag::Comment => "cmt",
+            GpxXmlTag::Source => "src",
+            GpxXmlTag::Link => "link",
+            GpxXmlTag::LinkText => "text",
+            GpxXmlTag::Number => "number",
+            GpxXmlTag::Author => "author",
+            GpxXmlTag::Email => "email",
+            GpxXmlTag::Copyright => "copyright",
+            GpxXmlTag::Year => "year",
+            GpxXmlTag::License => "license",
+            GpxXmlTag::Keywords => "keywords",
+            GpxXmlTag::Bounds => "bounds",
+            GpxXmlTag::Url => "url",
+            GpxXmlTag::UrlName => "urlname",
+            GpxXmlTag::ExtHeartRate => "hr",
+            GpxXmlTag::ExtCadence => "cad",
+            GpxXmlTag::ExtAirTemperature => "atemp",
+            GpxXmlTag::ExtWaterTemperature => "wtemp",
+            GpxXmlTag::ExtDepth => "depth",
+            GpxXmlTag::ExtPower => "power",
+        }
+    }
 }
 
 struct ParserContext {
@@ -46,10 +121,28 @@ struct ParserContext {
     in_track: bool,
     in_track_segment: bool,
     in_track_point: bool,
+    in_route: bool,
+    in_waypoint: bool,
+    in_author: bool,
+    in_link: bool,
     current_tag: Option<GpxXmlTag>,
+    current_track: Track,
     current_segment: TrackSegment,
     current_track_point: TrackPoint,
+    current_route: Route,
+    current_waypoint: Waypoint,
+    current_link: Link,
     should_sort_track_segment: bool,
+    /// Streaming mode hands every finished point over instead of collecting tracks
+    streaming: bool,
+    completed_point: Option<TrackPoint>,
+    track_index: usize,
+    segment_index: usize,
+    options: ParseOptions,
+    warnings: Vec<ParseWarning>,
+    /// Number of `<trkpt>` elements met so far, including skipped ones
+    point_count: usize,
+    skip_current_point: bool,
 }
 
 impl ParserContext {
@@ -60,19 +153,63 @@ impl ParserContext {
             in_track: false,
             in_track_segment: false,
             in_track_point: false,
+            in_route: false,
+            in_waypoint: false,
+            in_author: false,
+            in_link: false,
             current_tag: None,
+            current_track: Track::new(),
             current_segment: TrackSegment::new(),
             current_track_point: TrackPoint::new(),
+            current_route: Route::new(),
+            current_waypoint: Waypoint::new(0.0, 0.0),
+            current_link: Link::new(""),
             should_sort_track_segment: false,
+            streaming: false,
+            completed_point: None,
+            track_index: 0,
+            segment_index: 0,
+            options: ParseOptions::new(),
+            warnings: Vec::new(),
+            point_count: 0,
+            skip_current_point: false,
+        }
+    }
+
+    fn with_options(options: &ParseOptions) -> ParserContext {
+        ParserContext {
+            options: *options,
+            ..ParserContext::new()
         }
     }
+
+    fn new_streaming() -> ParserContext {
+        ParserContext {
+            streaming: true,
+            ..ParserContext::new()
+        }
+    }
+
+    /// GPX 1.0 has no `<metadata>`, so document name and time are direct children of `<gpx>`
+    fn is_at_document_level(&self) -> bool {
+        self.in_gpx && !self.in_track && !self.in_route && !self.in_waypoint
+    }
+
+    /// Track itself rather than one of its points
+    fn is_at_track_level(&self) -> bool {
+        self.in_track && !self.in_track_point
+    }
 }
 
-const TOPOGRAFIX_GPX_SCHEMA: &'static str = "http://www.topografix.com/GPX/1/1";
-const GARMIN_TRACK_POINT_EXT_SCHEMA: &'static str =
+const TOPOGRAFIX_GPX_SCHEMA: &str = "http://www.topografix.com/GPX/1/1";
+const TOPOGRAFIX_GPX_1_0_SCHEMA: &str = "http://www.topografix.com/GPX/1/0";
+const GARMIN_TRACK_POINT_EXT_SCHEMA: &str =
     "http://www.garmin.com/xmlschemas/TrackPointExtension/v1";
+const GARMIN_TRACK_POINT_EXT_V2_SCHEMA: &str =
+    "http://www.garmin.com/xmlschemas/TrackPointExtension/v2";
+const GARMIN_POWER_EXT_SCHEMA: &str = "http://www.garmin.com/xmlschemas/PowerExtension/v1";
 
-const TOPOGRAFIX_GPX_MAPPINGS: [(&'static str, GpxXmlTag); 8] = [
+const TOPOGRAFIX_GPX_MAPPINGS: [(&str, GpxXmlTag); 27] = [
     ("gpx", GpxXmlTag::Gpx),
     ("metadata", GpxXmlTag::Metadata),
     ("trk", GpxXmlTag::Track),
@@ -81,123 +218,361 @@ const TOPOGRAFIX_GPX_MAPPINGS: [(&'static str, GpxXmlTag); 8] = [
     ("trkpt", GpxXmlTag::TrackPoint),
     ("ele", GpxXmlTag::Elevation),
     ("time", GpxXmlTag::Time),
+    ("wpt", GpxXmlTag::Waypoint),
+    ("rte", GpxXmlTag::Route),
+    ("rtept", GpxXmlTag::RoutePoint),
+    ("desc", GpxXmlTag::Description),
+    ("sym", GpxXmlTag::Symbol),
+    ("type", GpxXmlTag::Type),
+    ("cmt", GpxXmlTag::Comment),
+    ("src", GpxXmlTag::Source),
+    ("link", GpxXmlTag::Link),
+    ("text", GpxXmlTag::LinkText),
+    ("number", GpxXmlTag::Number),
+    ("author", GpxXmlTag::Author),
+    ("email", GpxXmlTag::Email),
+    ("copyright", GpxXmlTag::Copyright),
+    ("year", GpxXmlTag::Year),
+    ("license", GpxXmlTag::License),
+    ("keywords", GpxXmlTag::Keywords),
+    ("bounds", GpxXmlTag::Bounds),
+    // Not a part of the schema. Strava and Stryd put bare power into `<extensions>`
+    // which inherits the default GPX namespace
+    ("power", GpxXmlTag::ExtPower),
+];
+
+/// Elements which exist only in GPX 1.0 and were moved to extensions in GPX 1.1
+const TOPOGRAFIX_GPX_1_0_MAPPINGS: [(&str, GpxXmlTag); 4] = [
+    ("speed", GpxXmlTag::Speed),
+    ("course", GpxXmlTag::Course),
+    ("url", GpxXmlTag::Url),
+    ("urlname", GpxXmlTag::UrlName),
 ];
 
-const GARMIN_TRACK_POINT_EXT_MAPPINGS: [(&'static str, GpxXmlTag); 2] = [
+const GARMIN_TRACK_POINT_EXT_MAPPINGS: [(&str, GpxXmlTag); 5] = [
     ("hr", GpxXmlTag::ExtHeartRate),
     ("cad", GpxXmlTag::ExtCadence),
+    ("atemp", GpxXmlTag::ExtAirTemperature),
+    ("wtemp", GpxXmlTag::ExtWaterTemperature),
+    ("depth", GpxXmlTag::ExtDepth),
+];
+
+/// Elements added in TrackPointExtension v2 on top of v1 ones
+const GARMIN_TRACK_POINT_EXT_V2_MAPPINGS: [(&str, GpxXmlTag); 2] = [
+    ("speed", GpxXmlTag::Speed),
+    ("course", GpxXmlTag::Course),
 ];
 
+const GARMIN_POWER_EXT_MAPPINGS: [(&str, GpxXmlTag); 1] = [("PowerInWatts", GpxXmlTag::ExtPower)];
+
+/// Elements written without any namespace
+const NO_NAMESPACE_MAPPINGS: [(&str, GpxXmlTag); 1] = [("power", GpxXmlTag::ExtPower)];
+
 fn find_tag_in_mapping(tag: &str, mapping: &[(&'static str, GpxXmlTag)]) -> Option<GpxXmlTag> {
-    let found = mapping.iter().find(|&&(mapped_tag, _)| mapped_tag == tag);
-    match found {
-        Some((_, value)) => Some(*value),
-        None => None,
-    }
+    mapping
+        .iter()
+        .find(|&&(mapped_tag, _)| mapped_tag == tag)
+        .map(|&(_, value)| value)
 }
 
 fn parse_gpx_xml_tag(name: &OwnedName) -> Option<GpxXmlTag> {
-    if name.namespace.is_none() {
-        return None;
-    }
-
-    let namespace = name.namespace.as_ref().unwrap().as_str();
     let tag = name.local_name.as_str();
+    let namespace = match name.namespace.as_ref() {
+        Some(namespace) => namespace.as_str(),
+        None => return find_tag_in_mapping(tag, &NO_NAMESPACE_MAPPINGS),
+    };
     match namespace {
         TOPOGRAFIX_GPX_SCHEMA => find_tag_in_mapping(tag, &TOPOGRAFIX_GPX_MAPPINGS),
+        TOPOGRAFIX_GPX_1_0_SCHEMA => find_tag_in_mapping(tag, &TOPOGRAFIX_GPX_MAPPINGS)
+            .or_else(|| find_tag_in_mapping(tag, &TOPOGRAFIX_GPX_1_0_MAPPINGS)),
         GARMIN_TRACK_POINT_EXT_SCHEMA => find_tag_in_mapping(tag, &GARMIN_TRACK_POINT_EXT_MAPPINGS),
+        GARMIN_TRACK_POINT_EXT_V2_SCHEMA => find_tag_in_mapping(tag, &GARMIN_TRACK_POINT_EXT_MAPPINGS)
+            .or_else(|| find_tag_in_mapping(tag, &GARMIN_TRACK_POINT_EXT_V2_MAPPINGS)),
+        GARMIN_POWER_EXT_SCHEMA => find_tag_in_mapping(tag, &GARMIN_POWER_EXT_MAPPINGS),
         _ => None,
     }
 }
 
+/// Converts zero-based xml-rs position into the one-based `Location`
+pub(crate) fn to_location(position: TextPosition) -> Location {
+    Location {
+        line: position.row + 1,
+        column: position.column + 1,
+    }
+}
+
+pub(crate) fn convert_xml_error(err: XmlError) -> ParseError {
+    match err.kind() {
+        // xml-rs doesn't give the I/O error away, so it stays reachable through its wrapper
+        XmlErrorKind::Io(io_err) => ParseError::Io(std::io::Error::new(io_err.kind(), err)),
+        _ => ParseError::Syntax {
+            location: to_location(err.position()),
+            message: err.msg().to_string(),
+        },
+    }
+}
+
+fn unexpected_element(tag: GpxXmlTag, location: Location) -> ParseError {
+    ParseError::UnexpectedElement {
+        element: tag.name().to_string(),
+        location,
+    }
+}
+
+fn missing_attribute(tag: GpxXmlTag, attribute: &str, location: Location) -> ParseError {
+    ParseError::MissingAttribute {
+        element: tag.name().to_string(),
+        attribute: attribute.to_string(),
+        location,
+    }
+}
+
+pub(crate) fn invalid_value(field: &str, value: &str, location: Location) -> ParseError {
+    ParseError::InvalidValue {
+        field: field.to_string(),
+        value: value.to_string(),
+        location,
+    }
+}
+
+/// Reads mandatory `lat` and `lon` attributes of point elements
+fn parse_coordinates(
+    tag: GpxXmlTag,
+    attributes: &[OwnedAttribute],
+    location: Location,
+) -> Result<(f64, f64), ParseError> {
+    let mut latitude = None;
+    let mut longitude = None;
+
+    for attr in attributes {
+        let coordinate = match attr.name.local_name.as_str() {
+            "lat" => &mut latitude,
+            "lon" => &mut longitude,
+            _ => continue,
+        };
+
+        match attr.value.parse::<f64>() {
+            Ok(parsed) => *coordinate = Some(parsed),
+            Err(_) => {
+                let field = format!("{}@{}", tag.name(), attr.name.local_name);
+                return Err(invalid_value(&field, &attr.value, location));
+            }
+        }
+    }
+
+    match (latitude, longitude) {
+        (Some(latitude), Some(longitude)) => Ok((latitude, longitude)),
+        (None, _) => Err(missing_attribute(tag, "lat", location)),
+        (_, None) => Err(missing_attribute(tag, "lon", location)),
+    }
+}
+
+fn find_attribute<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attr| attr.name.local_name == name)
+        .map(|attr| attr.value.as_str())
+}
+
+/// Reads mandatory `minlat`, `minlon`, `maxlat` and `maxlon` attributes of `<bounds>`
+fn parse_bounds(attributes: &[OwnedAttribute], location: Location) -> Result<Bounds, ParseError> {
+    let tag = GpxXmlTag::Bounds;
+    let parse = |name: &str| {
+        let value = find_attribute(attributes, name)
+            .ok_or_else(|| missing_attribute(tag, name, location))?;
+        value.parse::<f64>().map_err(|_| {
+            let field = format!("{}@{}", tag.name(), name);
+            invalid_value(&field, value, location)
+        })
+    };
+
+    Ok(Bounds {
+        min_latitude: parse("minlat")?,
+        min_longitude: parse("minlon")?,
+        max_latitude: parse("maxlat")?,
+        max_longitude: parse("maxlon")?,
+    })
+}
+
+/// Links of the element being read. GPX 1.0 `<url>` and GPX 1.1 `<link>` of waypoints,
+/// routes and track points are not kept.
+fn current_links<'a>(gpx: &'a mut Gpx, context: &'a mut ParserContext) -> Option<&'a mut Vec<Link>> {
+    if context.is_at_track_level() {
+        Some(&mut context.current_track.links)
+    } else if context.is_at_document_level() {
+        Some(&mut gpx.metadata.links)
+    } else {
+        None
+    }
+}
+
 fn parse_start_xml_element(
     tag: GpxXmlTag,
-    attributes: &Vec<OwnedAttribute>,
+    attributes: &[OwnedAttribute],
+    location: Location,
+    gpx: &mut Gpx,
     context: &mut ParserContext,
 ) -> Result<(), ParseError> {
     context.current_tag = Some(tag);
 
     match tag {
-        GpxXmlTag::Gpx => context.in_gpx = true,
+        GpxXmlTag::Gpx => {
+            context.in_gpx = true;
+            gpx.creator = find_attribute(attributes, "creator").map(String::from);
+        }
         GpxXmlTag::Metadata => {
             if !context.in_gpx {
-                return Err(ParseError::XmlError);
+                return Err(unexpected_element(tag, location));
             }
 
             context.in_metadata = true;
         }
         GpxXmlTag::Time => {
             if !context.in_gpx {
-                return Err(ParseError::XmlError);
+                return Err(unexpected_element(tag, location));
             }
         }
-        GpxXmlTag::Track => context.in_track = true,
-        GpxXmlTag::Name => {
-            if !context.in_gpx || !context.in_track {
-                return Err(ParseError::XmlError);
+        GpxXmlTag::Track => {
+            if !context.in_gpx || context.in_track || context.in_route {
+                return Err(unexpected_element(tag, location));
+            }
+
+            context.in_track = true;
+            context.current_track = Track::new();
+        }
+        GpxXmlTag::Name
+        | GpxXmlTag::Description
+        | GpxXmlTag::Symbol
+        | GpxXmlTag::Type
+        | GpxXmlTag::Comment
+        | GpxXmlTag::Source
+        | GpxXmlTag::Number
+        | GpxXmlTag::Url
+        | GpxXmlTag::UrlName => {
+            if !context.in_gpx {
+                return Err(unexpected_element(tag, location));
+            }
+        }
+        GpxXmlTag::Link => {
+            if !context.in_gpx || context.in_link {
+                return Err(unexpected_element(tag, location));
+            }
+
+            let href = find_attribute(attributes, "href")
+                .ok_or_else(|| missing_attribute(tag, "href", location))?;
+            context.in_link = true;
+            context.current_link = Link::new(href);
+        }
+        GpxXmlTag::LinkText => {
+            if !context.in_link {
+                return Err(unexpected_element(tag, location));
+            }
+        }
+        GpxXmlTag::Author => {
+            if !context.is_at_document_level() || context.in_author {
+                return Err(unexpected_element(tag, location));
+            }
+
+            context.in_author = true;
+            gpx.metadata.author = Some(Person::default());
+        }
+        GpxXmlTag::Email => {
+            if !context.is_at_document_level() {
+                return Err(unexpected_element(tag, location));
+            }
+
+            // GPX 1.1 splits the address to hide it from crawlers, GPX 1.0 keeps it as text
+            let id = find_attribute(attributes, "id");
+            let domain = find_attribute(attributes, "domain");
+            if let (Some(id), Some(domain)) = (id, domain) {
+                let author = gpx.metadata.author.get_or_insert_with(Person::default);
+                author.email = Some(format!("{}@{}", id, domain));
+            }
+        }
+        GpxXmlTag::Copyright => {
+            if !context.in_metadata {
+                return Err(unexpected_element(tag, location));
+            }
+
+            let author = find_attribute(attributes, "author")
+                .ok_or_else(|| missing_attribute(tag, "author", location))?;
+            gpx.metadata.copyright = Some(Copyright {
+                author: author.to_string(),
+                year: None,
+                license: None,
+            });
+        }
+        GpxXmlTag::Year | GpxXmlTag::License => {
+            if gpx.metadata.copyright.is_none() {
+                return Err(unexpected_element(tag, location));
+            }
+        }
+        GpxXmlTag::Keywords => {
+            if !context.is_at_document_level() {
+                return Err(unexpected_element(tag, location));
             }
         }
+        GpxXmlTag::Bounds => {
+            if !context.is_at_document_level() {
+                return Err(unexpected_element(tag, location));
+            }
+
+            gpx.metadata.bounds = Some(parse_bounds(attributes, location)?);
+        }
         GpxXmlTag::TrackSegment => context.in_track_segment = true,
         GpxXmlTag::TrackPoint => {
-            if !context.in_gpx
-                || !context.in_track
-                || !context.in_track_segment
-                || attributes.len() < 2
-            {
-                return Err(ParseError::XmlError);
+            if !context.in_gpx || !context.in_track || !context.in_track_segment {
+                return Err(unexpected_element(tag, location));
             }
 
             context.in_track_point = true;
+            context.point_count += 1;
+            context.skip_current_point = false;
             context.current_track_point = TrackPoint::new();
 
-            let mut latitude_found = false;
-            let mut longitude_found = false;
-
-            for attr in attributes {
-                if attr.name.local_name == "lat" {
-                    latitude_found = true;
-                    match attr.value.parse::<f64>() {
-                        Ok(parsed) => context.current_track_point.latitude = parsed,
-                        Err(_) => return Err(ParseError::XmlError),
-                    }
-                } else if attr.name.local_name == "lon" {
-                    longitude_found = true;
-                    match attr.value.parse::<f64>() {
-                        Ok(parsed) => context.current_track_point.longitude = parsed,
-                        Err(_) => return Err(ParseError::XmlError),
-                    }
-                }
+            let (latitude, longitude) = parse_coordinates(tag, attributes, location)?;
+            context.current_track_point.latitude = latitude;
+            context.current_track_point.longitude = longitude;
+        }
+        GpxXmlTag::Route => {
+            if !context.in_gpx || context.in_track || context.in_route {
+                return Err(unexpected_element(tag, location));
             }
 
-            if !latitude_found || !longitude_found {
-                return Err(ParseError::XmlError);
-            }
+            context.in_route = true;
+            context.current_route = Route::new();
         }
-        GpxXmlTag::Elevation => {
-            if !context.in_gpx
-                || !context.in_track
-                || !context.in_track_segment
-                || !context.in_track_point
-            {
-                return Err(ParseError::XmlError);
+        GpxXmlTag::Waypoint | GpxXmlTag::RoutePoint => {
+            let in_expected_parent = if tag == GpxXmlTag::Waypoint {
+                !context.in_track && !context.in_route
+            } else {
+                context.in_route
+            };
+            if !context.in_gpx || !in_expected_parent || context.in_waypoint {
+                return Err(unexpected_element(tag, location));
             }
+
+            let (latitude, longitude) = parse_coordinates(tag, attributes, location)?;
+            context.in_waypoint = true;
+            context.current_waypoint = Waypoint::new(latitude, longitude);
         }
-        GpxXmlTag::ExtHeartRate => {
-            if !context.in_gpx
-                || !context.in_track
-                || !context.in_track_segment
-                || !context.in_track_point
-            {
-                return Err(ParseError::XmlError);
+        GpxXmlTag::Elevation | GpxXmlTag::Speed | GpxXmlTag::Course => {
+            if !context.in_gpx || !(context.in_track_point || context.in_waypoint) {
+                return Err(unexpected_element(tag, location));
             }
         }
-        GpxXmlTag::ExtCadence => {
+        GpxXmlTag::ExtHeartRate
+        | GpxXmlTag::ExtCadence
+        | GpxXmlTag::ExtAirTemperature
+        | GpxXmlTag::ExtWaterTemperature
+        | GpxXmlTag::ExtDepth
+        | GpxXmlTag::ExtPower => {
             if !context.in_gpx
                 || !context.in_track
                 || !context.in_track_segment
                 || !context.in_track_point
             {
-                return Err(ParseError::XmlError);
+                return Err(unexpected_element(tag, location));
             }
         }
     }
@@ -207,54 +582,161 @@ fn parse_start_xml_element(
 
 fn parse_xml_characters(
     characters: String,
-    track: &mut Track,
+    location: Location,
+    gpx: &mut Gpx,
     context: &mut ParserContext,
 ) -> Result<(), ParseError> {
-    if context.current_tag.is_none() {
-        return Ok(());
-    }
+    let tag = match context.current_tag {
+        Some(tag) => tag,
+        None => return Ok(()),
+    };
 
-    match context.current_tag.unwrap() {
+    match tag {
         GpxXmlTag::Time => {
-            let start_time = DateTime::parse_from_rfc3339(&characters);
-            match start_time {
-                Err(_) => return Err(ParseError::XmlError),
-                _ => {}
-            }
-            let start_time = DateTime::<Utc>::from(start_time.unwrap());
-
-            if context.in_metadata {
-                track.creation_time = Some(start_time);
+            let start_time = DateTime::parse_from_rfc3339(&characters)
+                .map_err(|_| invalid_value(tag.name(), &characters, location))?;
+            let start_time = DateTime::<Utc>::from(start_time);
+
+            if context.in_metadata || context.is_at_document_level() {
+                gpx.metadata.time = Some(start_time);
+            } else if context.in_waypoint {
+                context.current_waypoint.time = Some(start_time);
             } else if context.in_track_point {
-                context.current_track_point.time = start_time;
+                context.current_track_point.time = Some(start_time);
 
                 // Check whether current track point comes after the latest point.
                 // If not, it should sort track later
-                if (context.current_segment.points.len() > 0)
-                    && (!context.should_sort_track_segment)
-                {
-                    let latest_point =
-                        &context.current_segment.points[context.current_segment.points.len() - 1];
-                    if latest_point.time.gt(&context.current_track_point.time) {
-                        context.should_sort_track_segment = true;
+                if !context.should_sort_track_segment {
+                    if let Some(latest_point) = context.current_segment.points.last() {
+                        if latest_point.time.gt(&context.current_track_point.time) {
+                            context.should_sort_track_segment = true;
+                        }
                     }
                 }
             }
         }
+        GpxXmlTag::Name if context.in_author => {
+            if let Some(author) = gpx.metadata.author.as_mut() {
+                author.name = Some(characters);
+            }
+        }
         GpxXmlTag::Name => {
-            track.name = characters;
+            if context.in_metadata || context.is_at_document_level() {
+                gpx.metadata.name = Some(characters);
+            } else if context.in_waypoint {
+                context.current_waypoint.name = Some(characters);
+            } else if context.in_route {
+                context.current_route.name = Some(characters);
+            } else if context.in_track && !context.in_track_point {
+                context.current_track.name = characters;
+            }
+        }
+        GpxXmlTag::Description => {
+            if context.in_waypoint {
+                context.current_waypoint.description = Some(characters);
+            } else if context.in_route {
+                context.current_route.description = Some(characters);
+            } else if context.is_at_track_level() {
+                context.current_track.description = Some(characters);
+            } else if context.is_at_document_level() {
+                gpx.metadata.description = Some(characters);
+            }
+        }
+        GpxXmlTag::Type if context.in_link => context.current_link.mime_type = Some(characters),
+        GpxXmlTag::Type if context.is_at_track_level() => {
+            context.current_track.activity_type = Some(ActivityType::from_name(&characters));
+        }
+        GpxXmlTag::Comment if context.is_at_track_level() => {
+            context.current_track.comment = Some(characters);
+        }
+        GpxXmlTag::Source if context.is_at_track_level() => {
+            context.current_track.source = Some(characters);
+        }
+        GpxXmlTag::Number if context.is_at_track_level() => match characters.trim().parse::<u32>() {
+            Ok(parsed) => context.current_track.number = Some(parsed),
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
+        },
+        GpxXmlTag::LinkText => context.current_link.text = Some(characters),
+        // GPX 1.0 keeps author and email as text
+        GpxXmlTag::Author => {
+            if let Some(author) = gpx.metadata.author.as_mut() {
+                author.name = Some(characters);
+            }
+        }
+        GpxXmlTag::Email => {
+            let author = gpx.metadata.author.get_or_insert_with(Person::default);
+            author.email = Some(characters);
+        }
+        GpxXmlTag::Year => match characters.trim().parse::<i32>() {
+            Ok(parsed) => {
+                if let Some(copyright) = gpx.metadata.copyright.as_mut() {
+                    copyright.year = Some(parsed);
+                }
+            }
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
+        },
+        GpxXmlTag::License => {
+            if let Some(copyright) = gpx.metadata.copyright.as_mut() {
+                copyright.license = Some(characters);
+            }
+        }
+        GpxXmlTag::Keywords => {
+            gpx.metadata.keywords = characters
+                .split(',')
+                .map(str::trim)
+                .filter(|keyword| !keyword.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        GpxXmlTag::Url => {
+            if let Some(links) = current_links(gpx, context) {
+                links.push(Link::new(characters.trim()));
+            }
+        }
+        GpxXmlTag::UrlName => {
+            if let Some(link) = current_links(gpx, context).and_then(|links| links.last_mut()) {
+                link.text = Some(characters);
+            }
+        }
+        GpxXmlTag::Symbol if context.in_waypoint => {
+            context.current_waypoint.symbol = Some(characters);
         }
         GpxXmlTag::Elevation => match characters.parse::<f64>() {
-            Ok(parsed) => context.current_track_point.elevation = parsed,
-            Err(_) => return Err(ParseError::XmlError),
+            Ok(parsed) if context.in_waypoint => context.current_waypoint.elevation = Some(parsed),
+            Ok(parsed) => context.current_track_point.elevation = Some(parsed),
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
+        },
+        GpxXmlTag::Speed if context.in_track_point => match characters.parse::<f64>() {
+            Ok(parsed) => context.current_track_point.speed = Some(parsed),
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
+        },
+        GpxXmlTag::Course if context.in_track_point => match characters.parse::<f64>() {
+            Ok(parsed) => context.current_track_point.course = Some(parsed),
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
         },
         GpxXmlTag::ExtHeartRate => match characters.parse::<u8>() {
-            Ok(parsed) => context.current_track_point.heart_rate = parsed,
-            Err(_) => return Err(ParseError::XmlError),
+            Ok(parsed) => context.current_track_point.heart_rate = Some(parsed),
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
         },
         GpxXmlTag::ExtCadence => match characters.parse::<u8>() {
-            Ok(parsed) => context.current_track_point.cadence = parsed,
-            Err(_) => return Err(ParseError::XmlError),
+            Ok(parsed) => context.current_track_point.cadence = Some(parsed),
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
+        },
+        GpxXmlTag::ExtAirTemperature => match characters.parse::<f64>() {
+            Ok(parsed) => context.current_track_point.air_temperature = Some(parsed),
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
+        },
+        GpxXmlTag::ExtWaterTemperature => match characters.parse::<f64>() {
+            Ok(parsed) => context.current_track_point.water_temperature = Some(parsed),
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
+        },
+        GpxXmlTag::ExtDepth => match characters.parse::<f64>() {
+            Ok(parsed) => context.current_track_point.depth = Some(parsed),
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
+        },
+        GpxXmlTag::ExtPower => match characters.parse::<u16>() {
+            Ok(parsed) => context.current_track_point.power = Some(parsed),
+            Err(_) => return Err(invalid_value(tag.name(), &characters, location)),
         },
         _ => {}
     }
@@ -262,83 +744,332 @@ fn parse_xml_characters(
     Ok(())
 }
 
-fn parse_end_xml_element(tag: GpxXmlTag, track: &mut Track, context: &mut ParserContext) {
+fn parse_end_xml_element(tag: GpxXmlTag, gpx: &mut Gpx, context: &mut ParserContext) {
     context.current_tag = None;
 
     match tag {
         GpxXmlTag::Gpx => context.in_gpx = false,
         GpxXmlTag::Metadata => context.in_metadata = false,
-        GpxXmlTag::Track => context.in_track = false,
+        GpxXmlTag::Author => context.in_author = false,
+        GpxXmlTag::Link => {
+            context.in_link = false;
+            let link = mem::replace(&mut context.current_link, Link::new(""));
+            if context.in_author {
+                if let Some(author) = gpx.metadata.author.as_mut() {
+                    author.link = Some(link);
+                }
+            } else if let Some(links) = current_links(gpx, context) {
+                links.push(link);
+            }
+        }
+        GpxXmlTag::Track => {
+            context.in_track = false;
+            context.track_index += 1;
+            context.segment_index = 0;
+            let mut track = mem::take(&mut context.current_track);
+            track.creation_time = gpx.metadata.time;
+            track.device = gpx.creator.clone();
+            if !context.streaming {
+                gpx.tracks.push(track);
+            }
+        }
         GpxXmlTag::TrackSegment => {
             context.in_track_segment = false;
+            context.segment_index += 1;
             if context.should_sort_track_segment {
+                context.current_segment.points.sort_by_key(|point| point.time);
+            }
+            context.should_sort_track_segment = false;
+            let current_segment = mem::take(&mut context.current_segment);
+            if !context.streaming {
+                context.current_track.route.push(current_segment);
+            }
+        }
+        GpxXmlTag::TrackPoint => {
+            context.in_track_point = false;
+            if context.skip_current_point {
+                context.skip_current_point = false;
+            } else if context.streaming {
+                context.completed_point = Some(context.current_track_point);
+            } else {
                 context
                     .current_segment
                     .points
-                    .sort_by(|a, b| a.time.cmp(&b.time));
+                    .push(context.current_track_point)
             }
-            context.should_sort_track_segment = false;
-            let current_segment = mem::replace(&mut context.current_segment, TrackSegment::new());
-            track.route.push(current_segment);
         }
-        GpxXmlTag::TrackPoint => context
-            .current_segment
-            .points
-            .push(context.current_track_point),
+        GpxXmlTag::Route => {
+            context.in_route = false;
+            let route = mem::take(&mut context.current_route);
+            gpx.routes.push(route);
+        }
+        GpxXmlTag::Waypoint => {
+            context.in_waypoint = false;
+            let waypoint = mem::replace(&mut context.current_waypoint, Waypoint::new(0.0, 0.0));
+            gpx.waypoints.push(waypoint);
+        }
+        GpxXmlTag::RoutePoint => {
+            context.in_waypoint = false;
+            let waypoint = mem::replace(&mut context.current_waypoint, Waypoint::new(0.0, 0.0));
+            context.current_route.points.push(waypoint);
+        }
         _ => {}
     }
 }
 
-fn read_gpx_from<R: Read>(reader: BufReader<R>) -> Result<Track, ParseError> {
-    let parser = EventReader::new(reader);
-    let mut track = Track::new();
-    let mut context = ParserContext::new();
+/// In lenient mode turns bad values of a track point into warnings. A point without valid
+/// coordinates or time is dropped, other bad values are left empty.
+fn recover_point_error(
+    result: Result<(), ParseError>,
+    context: &mut ParserContext,
+) -> Result<(), ParseError> {
+    let err = match result {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+
+    let recoverable = matches!(
+        err,
+        ParseError::InvalidValue { .. } | ParseError::MissingAttribute { .. }
+    );
+    if !context.options.lenient || !context.in_track_point || !recoverable {
+        return Err(err);
+    }
 
-    for e in parser {
-        match e {
-            Ok(XmlEvent::StartElement {
-                name, attributes, ..
-            }) => {
-                let tag = parse_gpx_xml_tag(&name);
-                if tag.is_none() {
-                    continue;
-                }
+    if let Some(GpxXmlTag::TrackPoint) | Some(GpxXmlTag::Time) = context.current_tag {
+        context.skip_current_point = true;
+    }
 
-                let tag = tag.unwrap();
-                if let Err(err) = parse_start_xml_element(tag, &attributes, &mut context) {
-                    return Err(err);
-                }
-            }
-            Ok(XmlEvent::EndElement { name, .. }) => {
-                let tag = parse_gpx_xml_tag(&name);
-                if tag.is_none() {
-                    continue;
-                }
+    context.warnings.push(ParseWarning {
+        point_index: context.point_count - 1,
+        error: err,
+    });
+    Ok(())
+}
 
-                let tag = tag.unwrap();
-                parse_end_xml_element(tag, &mut track, &mut context);
+/// Pulls a single event out of XML parser and applies it to the document.
+/// Returns `false` once the document ends.
+fn parse_next_xml_event<R: Read>(
+    parser: &mut EventReader<R>,
+    gpx: &mut Gpx,
+    context: &mut ParserContext,
+) -> Result<bool, ParseError> {
+    let event = parser.next();
+    let location = to_location(parser.position());
+
+    match event {
+        Ok(XmlEvent::StartElement {
+            name, attributes, ..
+        }) => {
+            if let Some(tag) = parse_gpx_xml_tag(&name) {
+                let result = parse_start_xml_element(tag, &attributes, location, gpx, context);
+                recover_point_error(result, context)?;
+            }
+        }
+        Ok(XmlEvent::EndElement { name }) => {
+            if let Some(tag) = parse_gpx_xml_tag(&name) {
+                parse_end_xml_element(tag, gpx, context);
             }
-            Ok(XmlEvent::Characters(characters)) => {
-                if let Err(err) = parse_xml_characters(characters, &mut track, &mut context) {
-                    return Err(err);
+        }
+        Ok(XmlEvent::Characters(characters)) => {
+            let result = parse_xml_characters(characters, location, gpx, context);
+            recover_point_error(result, context)?;
+        }
+        Ok(XmlEvent::EndDocument) => return Ok(false),
+        Err(err) => return Err(convert_xml_error(err)),
+        _ => {}
+    }
+
+    Ok(true)
+}
+
+pub(crate) fn read_gpx_document_from<R: Read>(
+    reader: BufReader<R>,
+    options: &ParseOptions,
+) -> Result<(Gpx, Vec<ParseWarning>), ParseError> {
+    let mut parser = EventReader::new(reader);
+    let mut gpx = Gpx::new();
+    let mut context = ParserContext::with_options(options);
+
+    while parse_next_xml_event(&mut parser, &mut gpx, &mut context)? {}
+
+    Ok((gpx, context.warnings))
+}
+
+/// Track point together with its position in the GPX document
+#[derive(Debug, Copy, Clone)]
+pub struct SegmentPoint {
+    /// Index of `<trk>` within the document
+    pub track_index: usize,
+    /// Index of `<trkseg>` within its track
+    pub segment_index: usize,
+    pub point: TrackPoint,
+}
+
+/// Pull-based reader which yields track points one by one without building the whole `Track`.
+/// Memory use doesn't depend on number of points, so it suits multi-day recordings.
+///
+/// Unlike `read_gpx`, points are produced in the order of the file, even if their time
+/// goes backwards.
+pub struct GpxPointReader<R: Read> {
+    parser: EventReader<BufReader<R>>,
+    gpx: Gpx,
+    context: ParserContext,
+    finished: bool,
+}
+
+impl GpxPointReader<File> {
+    pub fn open(path: &str) -> Result<GpxPointReader<File>, ParseError> {
+        let file = File::open(path)?;
+        Ok(GpxPointReader::new(file))
+    }
+}
+
+impl<R: Read> GpxPointReader<R> {
+    pub fn new(reader: R) -> GpxPointReader<R> {
+        GpxPointReader {
+            parser: EventReader::new(BufReader::new(reader)),
+            gpx: Gpx::new(),
+            context: ParserContext::new_streaming(),
+            finished: false,
+        }
+    }
+
+    /// Document metadata read so far. It's complete once the first point is produced,
+    /// since `<metadata>` precedes tracks.
+    pub fn metadata(&self) -> &Metadata {
+        &self.gpx.metadata
+    }
+}
+
+impl<R: Read> Iterator for GpxPointReader<R> {
+    type Item = Result<SegmentPoint, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            match parse_next_xml_event(&mut self.parser, &mut self.gpx, &mut self.context) {
+                Ok(true) => {}
+                Ok(false) => self.finished = true,
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
                 }
             }
-            Err(e) => {
-                println!("Error: {}", e);
-                return Err(ParseError::XmlError);
+
+            if let Some(point) = self.context.completed_point.take() {
+                return Some(Ok(SegmentPoint {
+                    track_index: self.context.track_index,
+                    segment_index: self.context.segment_index,
+                    point,
+                }));
             }
-            _ => {}
         }
+
+        None
     }
+}
+
+/// Picks the first track of the document. Documents without tracks give an empty one.
+fn first_track(gpx: Gpx) -> Track {
+    let creation_time = gpx.metadata.time;
+    let device = gpx.creator;
+    gpx.tracks.into_iter().next().unwrap_or_else(|| {
+        let mut track = Track::new();
+        track.creation_time = creation_time;
+        track.device = device;
+        track
+    })
+}
+
+pub(crate) fn read_gpx_from<R: Read>(reader: BufReader<R>) -> Result<Track, ParseError> {
+    read_first_gpx_document_from_stream(reader, &ParseOptions::new())
+        .map(|(gpx, _)| first_track(gpx))
+}
+
+/// Parses the whole GPX document with all its tracks. Gzip and zip files are decompressed,
+/// zip gives its first GPX entry.
+pub fn read_gpx_document(path: &str) -> Result<Gpx, ParseError> {
+    let file = File::open(path)?;
+
+    read_first_gpx_document(file, &ParseOptions::new()).map(|(gpx, _)| gpx)
+}
+
+/// Parses the whole GPX document from an arbitrary source. Gzip and zip input is
+/// decompressed the same way as by `read_gpx_document`.
+pub fn read_gpx_document_from_reader<R: Read>(reader: R) -> Result<Gpx, ParseError> {
+    read_gpx_document_from_reader_with_options(reader, &ParseOptions::new()).map(|(gpx, _)| gpx)
+}
+
+/// Parses the whole GPX document. In lenient mode bad points are reported as warnings.
+pub fn read_gpx_document_from_reader_with_options<R: Read>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<(Gpx, Vec<ParseWarning>), ParseError> {
+    read_first_gpx_document_from_stream(reader, options)
+}
+
+pub fn read_gpx_document_from_bytes(bytes: &[u8]) -> Result<Gpx, ParseError> {
+    read_gpx_document_from_reader(bytes)
+}
+
+pub fn read_gpx_document_from_str(gpx: &str) -> Result<Gpx, ParseError> {
+    read_gpx_document_from_bytes(gpx.as_bytes())
+}
 
-    Ok(track)
+impl FromStr for Gpx {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        read_gpx_document_from_str(s)
+    }
 }
 
+/// Parses the first track of GPX document. Gzip and zip files are decompressed,
+/// zip gives its first GPX entry.
 pub fn read_gpx(path: &str) -> Result<Track, ParseError> {
-    let file = File::open(path).unwrap();
-    let file = BufReader::new(file);
+    read_gpx_with_options(path, &ParseOptions::new()).map(|(track, _)| track)
+}
+
+/// Parses the first track of GPX document. In lenient mode bad points are reported as warnings.
+pub fn read_gpx_with_options(
+    path: &str,
+    options: &ParseOptions,
+) -> Result<(Track, Vec<ParseWarning>), ParseError> {
+    let file = File::open(path)?;
+
+    read_first_gpx_document(file, options).map(|(gpx, warnings)| (first_track(gpx), warnings))
+}
+
+pub fn read_gpx_from_reader_with_options<R: Read>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<(Track, Vec<ParseWarning>), ParseError> {
+    read_gpx_document_from_reader_with_options(reader, options)
+        .map(|(gpx, warnings)| (first_track(gpx), warnings))
+}
+
+/// Parses GPX document from an arbitrary source, e.g. network stream or archive entry.
+/// Gzip and zip input is decompressed the same way as by `read_gpx`.
+pub fn read_gpx_from_reader<R: Read>(reader: R) -> Result<Track, ParseError> {
+    read_gpx_from(BufReader::new(reader))
+}
+
+/// Parses GPX document held in memory
+pub fn read_gpx_from_bytes(bytes: &[u8]) -> Result<Track, ParseError> {
+    read_gpx_from_reader(bytes)
+}
+
+/// Parses GPX document held in a string
+pub fn read_gpx_from_str(gpx: &str) -> Result<Track, ParseError> {
+    read_gpx_from_bytes(gpx.as_bytes())
+}
+
+impl FromStr for Track {
+    type Err = ParseError;
 
-    read_gpx_from(file)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        read_gpx_from_str(s)
+    }
 }
 
 #[cfg(test)]
@@ -355,6 +1086,25 @@ mod tests {
     #[test_case("trkpt", GpxXmlTag::TrackPoint; "trkpt tag")]
     #[test_case("ele", GpxXmlTag::Elevation; "ele tag")]
     #[test_case("time", GpxXmlTag::Time; "time tag")]
+    #[test_case("wpt", GpxXmlTag::Waypoint; "wpt tag")]
+    #[test_case("rte", GpxXmlTag::Route; "rte tag")]
+    #[test_case("rtept", GpxXmlTag::RoutePoint; "rtept tag")]
+    #[test_case("desc", GpxXmlTag::Description; "desc tag")]
+    #[test_case("sym", GpxXmlTag::Symbol; "sym tag")]
+    #[test_case("type", GpxXmlTag::Type; "type tag")]
+    #[test_case("cmt", GpxXmlTag::Comment; "cmt tag")]
+    #[test_case("src", GpxXmlTag::Source; "src tag")]
+    #[test_case("link", GpxXmlTag::Link; "link tag")]
+    #[test_case("text", GpxXmlTag::LinkText; "text tag")]
+    #[test_case("number", GpxXmlTag::Number; "number tag")]
+    #[test_case("author", GpxXmlTag::Author; "author tag")]
+    #[test_case("email", GpxXmlTag::Email; "email tag")]
+    #[test_case("copyright", GpxXmlTag::Copyright; "copyright tag")]
+    #[test_case("year", GpxXmlTag::Year; "year tag")]
+    #[test_case("license", GpxXmlTag::License; "license tag")]
+    #[test_case("keywords", GpxXmlTag::Keywords; "keywords tag")]
+    #[test_case("bounds", GpxXmlTag::Bounds; "bounds tag")]
+    #[test_case("power", GpxXmlTag::ExtPower; "power tag")]
     fn test_topografix_gpx_mapping(tag: &str, expected: GpxXmlTag) {
         let name = OwnedName {
             local_name: String::from(tag),
@@ -366,8 +1116,39 @@ mod tests {
         assert_eq!(parsed, Some(expected));
     }
 
+    #[test_case("trkpt", GpxXmlTag::TrackPoint; "trkpt tag")]
+    #[test_case("speed", GpxXmlTag::Speed; "speed tag")]
+    #[test_case("course", GpxXmlTag::Course; "course tag")]
+    #[test_case("url", GpxXmlTag::Url; "url tag")]
+    #[test_case("urlname", GpxXmlTag::UrlName; "urlname tag")]
+    fn test_topografix_gpx_1_0_mapping(tag: &str, expected: GpxXmlTag) {
+        let name = OwnedName {
+            local_name: String::from(tag),
+            namespace: Some(String::from(TOPOGRAFIX_GPX_1_0_SCHEMA)),
+            prefix: None,
+        };
+
+        let parsed = parse_gpx_xml_tag(&name);
+        assert_eq!(parsed, Some(expected));
+    }
+
+    #[test]
+    fn test_gpx_1_0_only_tags_in_gpx_1_1_mapping() {
+        let name = OwnedName {
+            local_name: String::from("speed"),
+            namespace: Some(String::from(TOPOGRAFIX_GPX_SCHEMA)),
+            prefix: None,
+        };
+
+        let parsed = parse_gpx_xml_tag(&name);
+        assert_eq!(parsed, None);
+    }
+
     #[test_case("hr", GpxXmlTag::ExtHeartRate; "hr tag")]
     #[test_case("cad", GpxXmlTag::ExtCadence; "cad tag")]
+    #[test_case("atemp", GpxXmlTag::ExtAirTemperature; "atemp tag")]
+    #[test_case("wtemp", GpxXmlTag::ExtWaterTemperature; "wtemp tag")]
+    #[test_case("depth", GpxXmlTag::ExtDepth; "depth tag")]
     fn test_garmin_track_point_ext_gpx_mapping(tag: &str, expected: GpxXmlTag) {
         let name = OwnedName {
             local_name: String::from(tag),
@@ -379,6 +1160,53 @@ mod tests {
         assert_eq!(parsed, Some(expected));
     }
 
+    #[test_case("hr", GpxXmlTag::ExtHeartRate; "hr tag")]
+    #[test_case("cad", GpxXmlTag::ExtCadence; "cad tag")]
+    #[test_case("atemp", GpxXmlTag::ExtAirTemperature; "atemp tag")]
+    #[test_case("wtemp", GpxXmlTag::ExtWaterTemperature; "wtemp tag")]
+    #[test_case("depth", GpxXmlTag::ExtDepth; "depth tag")]
+    #[test_case("speed", GpxXmlTag::Speed; "speed tag")]
+    #[test_case("course", GpxXmlTag::Course; "course tag")]
+    fn test_garmin_track_point_ext_v2_gpx_mapping(tag: &str, expected: GpxXmlTag) {
+        let name = OwnedName {
+            local_name: String::from(tag),
+            namespace: Some(String::from(GARMIN_TRACK_POINT_EXT_V2_SCHEMA)),
+            prefix: None,
+        };
+
+        let parsed = parse_gpx_xml_tag(&name);
+        assert_eq!(parsed, Some(expected));
+    }
+
+    #[test]
+    fn test_garmin_power_ext_gpx_mapping() {
+        let name = OwnedName {
+            local_name: String::from("PowerInWatts"),
+            namespace: Some(String::from(GARMIN_POWER_EXT_SCHEMA)),
+            prefix: None,
+        };
+
+        let parsed = parse_gpx_xml_tag(&name);
+        assert_eq!(parsed, Some(GpxXmlTag::ExtPower));
+    }
+
+    #[test]
+    fn test_no_namespace_gpx_mapping() {
+        let power = OwnedName {
+            local_name: String::from("power"),
+            namespace: None,
+            prefix: None,
+        };
+        assert_eq!(parse_gpx_xml_tag(&power), Some(GpxXmlTag::ExtPower));
+
+        let gpx = OwnedName {
+            local_name: String::from("gpx"),
+            namespace: None,
+            prefix: None,
+        };
+        assert_eq!(parse_gpx_xml_tag(&gpx), None);
+    }
+
     #[test]
     fn test_unknown_namespace_gpx_mapping() {
         let name = OwnedName {
@@ -446,7 +1274,7 @@ xmlns:gpxx=\"http://www.garmin.com/xmlschemas/GpxExtensions/v3\">
         assert_eq!(track.name, "");
         assert_eq!(track.route.len(), 0);
 
-        let expected_time = Utc.ymd(2020, 4, 22).and_hms(16, 01, 58);
+        let expected_time = Utc.ymd(2020, 4, 22).and_hms(16, 1, 58);
         assert_eq!(track.creation_time, Some(expected_time));
     }
 
@@ -498,24 +1326,24 @@ xmlns:gpxx=\"http://www.garmin.com/xmlschemas/GpxExtensions/v3\">
         assert_eq!(track.route.len(), 1);
         assert_eq!(track.route[0].points.len(), 2);
 
-        let expected_time = Utc.ymd(2020, 4, 22).and_hms(16, 01, 58);
+        let expected_time = Utc.ymd(2020, 4, 22).and_hms(16, 1, 58);
         assert_eq!(track.creation_time, Some(expected_time));
 
-        let point_0_time = Utc.ymd(2020, 4, 22).and_hms(16, 01, 58);
+        let point_0_time = Utc.ymd(2020, 4, 22).and_hms(16, 1, 58);
         assert_eq!(track.route[0].points[0].latitude, 10.1025420);
         assert_eq!(track.route[0].points[0].longitude, 15.1583540);
-        assert_eq!(track.route[0].points[0].elevation, 478.2);
-        assert_eq!(track.route[0].points[0].time, point_0_time);
-        assert_eq!(track.route[0].points[0].heart_rate, 95);
-        assert_eq!(track.route[0].points[0].cadence, 79);
+        assert_eq!(track.route[0].points[0].elevation, Some(478.2));
+        assert_eq!(track.route[0].points[0].time, Some(point_0_time));
+        assert_eq!(track.route[0].points[0].heart_rate, Some(95));
+        assert_eq!(track.route[0].points[0].cadence, Some(79));
 
-        let point_1_time = Utc.ymd(2020, 4, 22).and_hms(16, 02, 04);
+        let point_1_time = Utc.ymd(2020, 4, 22).and_hms(16, 2, 4);
         assert_eq!(track.route[0].points[1].latitude, 10.1025432);
         assert_eq!(track.route[0].points[1].longitude, 15.1583542);
-        assert_eq!(track.route[0].points[1].elevation, 480.3);
-        assert_eq!(track.route[0].points[1].time, point_1_time);
-        assert_eq!(track.route[0].points[1].heart_rate, 98);
-        assert_eq!(track.route[0].points[1].cadence, 80);
+        assert_eq!(track.route[0].points[1].elevation, Some(480.3));
+        assert_eq!(track.route[0].points[1].time, Some(point_1_time));
+        assert_eq!(track.route[0].points[1].heart_rate, Some(98));
+        assert_eq!(track.route[0].points[1].cadence, Some(80));
     }
 
     #[test]
@@ -566,24 +1394,24 @@ xmlns:gpxx=\"http://www.garmin.com/xmlschemas/GpxExtensions/v3\">
         assert_eq!(track.route.len(), 1);
         assert_eq!(track.route[0].points.len(), 2);
 
-        let expected_time = Utc.ymd(2020, 4, 22).and_hms(16, 01, 58);
+        let expected_time = Utc.ymd(2020, 4, 22).and_hms(16, 1, 58);
         assert_eq!(track.creation_time, Some(expected_time));
 
-        let point_0_time = Utc.ymd(2020, 4, 22).and_hms(16, 01, 58);
+        let point_0_time = Utc.ymd(2020, 4, 22).and_hms(16, 1, 58);
         assert_eq!(track.route[0].points[0].latitude, 10.1025420);
         assert_eq!(track.route[0].points[0].longitude, 15.1583540);
-        assert_eq!(track.route[0].points[0].elevation, 478.2);
-        assert_eq!(track.route[0].points[0].time, point_0_time);
-        assert_eq!(track.route[0].points[0].heart_rate, 95);
-        assert_eq!(track.route[0].points[0].cadence, 79);
+        assert_eq!(track.route[0].points[0].elevation, Some(478.2));
+        assert_eq!(track.route[0].points[0].time, Some(point_0_time));
+        assert_eq!(track.route[0].points[0].heart_rate, Some(95));
+        assert_eq!(track.route[0].points[0].cadence, Some(79));
 
-        let point_1_time = Utc.ymd(2020, 4, 22).and_hms(16, 02, 04);
+        let point_1_time = Utc.ymd(2020, 4, 22).and_hms(16, 2, 4);
         assert_eq!(track.route[0].points[1].latitude, 10.1025432);
         assert_eq!(track.route[0].points[1].longitude, 15.1583542);
-        assert_eq!(track.route[0].points[1].elevation, 480.3);
-        assert_eq!(track.route[0].points[1].time, point_1_time);
-        assert_eq!(track.route[0].points[1].heart_rate, 98);
-        assert_eq!(track.route[0].points[1].cadence, 80);
+        assert_eq!(track.route[0].points[1].elevation, Some(480.3));
+        assert_eq!(track.route[0].points[1].time, Some(point_1_time));
+        assert_eq!(track.route[0].points[1].heart_rate, Some(98));
+        assert_eq!(track.route[0].points[1].cadence, Some(80));
     }
 
     #[test]
@@ -658,39 +1486,849 @@ xmlns:gpxx=\"http://www.garmin.com/xmlschemas/GpxExtensions/v3\">
         assert_eq!(track.route[0].points.len(), 2);
         assert_eq!(track.route[1].points.len(), 2);
 
-        let expected_time = Utc.ymd(2020, 4, 22).and_hms(16, 01, 58);
+        let expected_time = Utc.ymd(2020, 4, 22).and_hms(16, 1, 58);
         assert_eq!(track.creation_time, Some(expected_time));
 
-        let point_0_time = Utc.ymd(2020, 4, 22).and_hms(16, 01, 58);
+        let point_0_time = Utc.ymd(2020, 4, 22).and_hms(16, 1, 58);
         assert_eq!(track.route[0].points[0].latitude, 10.1025420);
         assert_eq!(track.route[0].points[0].longitude, 15.1583540);
-        assert_eq!(track.route[0].points[0].elevation, 478.2);
-        assert_eq!(track.route[0].points[0].time, point_0_time);
-        assert_eq!(track.route[0].points[0].heart_rate, 95);
-        assert_eq!(track.route[0].points[0].cadence, 79);
+        assert_eq!(track.route[0].points[0].elevation, Some(478.2));
+        assert_eq!(track.route[0].points[0].time, Some(point_0_time));
+        assert_eq!(track.route[0].points[0].heart_rate, Some(95));
+        assert_eq!(track.route[0].points[0].cadence, Some(79));
 
-        let point_1_time = Utc.ymd(2020, 4, 22).and_hms(16, 02, 04);
+        let point_1_time = Utc.ymd(2020, 4, 22).and_hms(16, 2, 4);
         assert_eq!(track.route[0].points[1].latitude, 10.1025432);
         assert_eq!(track.route[0].points[1].longitude, 15.1583542);
-        assert_eq!(track.route[0].points[1].elevation, 480.3);
-        assert_eq!(track.route[0].points[1].time, point_1_time);
-        assert_eq!(track.route[0].points[1].heart_rate, 98);
-        assert_eq!(track.route[0].points[1].cadence, 80);
+        assert_eq!(track.route[0].points[1].elevation, Some(480.3));
+        assert_eq!(track.route[0].points[1].time, Some(point_1_time));
+        assert_eq!(track.route[0].points[1].heart_rate, Some(98));
+        assert_eq!(track.route[0].points[1].cadence, Some(80));
 
-        let point_2_time = Utc.ymd(2020, 4, 22).and_hms(16, 02, 30);
+        let point_2_time = Utc.ymd(2020, 4, 22).and_hms(16, 2, 30);
         assert_eq!(track.route[1].points[0].latitude, 10.1025452);
         assert_eq!(track.route[1].points[0].longitude, 15.1583552);
-        assert_eq!(track.route[1].points[0].elevation, 488.5);
-        assert_eq!(track.route[1].points[0].time, point_2_time);
-        assert_eq!(track.route[1].points[0].heart_rate, 100);
-        assert_eq!(track.route[1].points[0].cadence, 88);
+        assert_eq!(track.route[1].points[0].elevation, Some(488.5));
+        assert_eq!(track.route[1].points[0].time, Some(point_2_time));
+        assert_eq!(track.route[1].points[0].heart_rate, Some(100));
+        assert_eq!(track.route[1].points[0].cadence, Some(88));
 
-        let point_3_time = Utc.ymd(2020, 4, 22).and_hms(16, 02, 36);
+        let point_3_time = Utc.ymd(2020, 4, 22).and_hms(16, 2, 36);
         assert_eq!(track.route[1].points[1].latitude, 10.1025472);
         assert_eq!(track.route[1].points[1].longitude, 15.1583572);
-        assert_eq!(track.route[1].points[1].elevation, 489.4);
-        assert_eq!(track.route[1].points[1].time, point_3_time);
-        assert_eq!(track.route[1].points[1].heart_rate, 102);
-        assert_eq!(track.route[1].points[1].cadence, 75);
+        assert_eq!(track.route[1].points[1].elevation, Some(489.4));
+        assert_eq!(track.route[1].points[1].time, Some(point_3_time));
+        assert_eq!(track.route[1].points[1].heart_rate, Some(102));
+        assert_eq!(track.route[1].points[1].cadence, Some(75));
+    }
+
+    #[test]
+    fn test_reading_missing_file() {
+        let result = read_gpx("/nonexistent/path/to/track.gpx");
+        assert!(matches!(result, Err(ParseError::Io(_))));
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "peer is gone"))
+        }
+    }
+
+    #[test]
+    fn test_reading_failing_reader() {
+        match read_gpx_document_from(BufReader::new(FailingReader), &ParseOptions::new()) {
+            Err(ParseError::Io(err)) => {
+                assert_eq!(err.kind(), std::io::ErrorKind::ConnectionReset);
+                let xml_err = err.get_ref().unwrap().downcast_ref::<XmlError>().unwrap();
+                match xml_err.kind() {
+                    XmlErrorKind::Io(source) => assert_eq!(source.to_string(), "peer is gone"),
+                    other => panic!("Unexpected error kind: {:?}", other),
+                }
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parsing_malformed_xml() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <trk>
+</gpx>".as_bytes();
+        let reader = BufReader::new(gpx_str);
+
+        match read_gpx_from(reader) {
+            Err(ParseError::Syntax { location, .. }) => assert_eq!(location.line, 4),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parsing_track_point_outside_segment() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <trk>
+        <trkpt lat=\"10.1025420\" lon=\"15.1583540\"></trkpt>
+    </trk>
+</gpx>".as_bytes();
+        let reader = BufReader::new(gpx_str);
+
+        match read_gpx_from(reader) {
+            Err(ParseError::UnexpectedElement { element, location }) => {
+                assert_eq!(element, "trkpt");
+                assert_eq!(location, Location { line: 4, column: 9 });
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parsing_track_point_without_longitude() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <trk><trkseg><trkpt lat=\"10.1025420\"></trkpt></trkseg></trk>
+</gpx>".as_bytes();
+        let reader = BufReader::new(gpx_str);
+
+        match read_gpx_from(reader) {
+            Err(ParseError::MissingAttribute {
+                element, attribute, ..
+            }) => {
+                assert_eq!(element, "trkpt");
+                assert_eq!(attribute, "lon");
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test_case("<trkpt lat=\"north\" lon=\"15.1583540\"></trkpt>", "trkpt@lat", "north"; "latitude")]
+    #[test_case("<trkpt lat=\"10.1\" lon=\"15.1\"><ele>high</ele></trkpt>", "ele", "high"; "elevation")]
+    #[test_case("<trkpt lat=\"10.1\" lon=\"15.1\"><time>yesterday</time></trkpt>", "time", "yesterday"; "time")]
+    fn test_parsing_invalid_value(point: &str, expected_field: &str, expected_value: &str) {
+        let gpx_str = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <trk><trkseg>{}</trkseg></trk>
+</gpx>", point);
+        let reader = BufReader::new(gpx_str.as_bytes());
+
+        match read_gpx_from(reader) {
+            Err(ParseError::InvalidValue {
+                field,
+                value,
+                location,
+            }) => {
+                assert_eq!(field, expected_field);
+                assert_eq!(value, expected_value);
+                assert_eq!(location.line, 3);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_error_display() {
+        let err = ParseError::InvalidValue {
+            field: String::from("ele"),
+            value: String::from("high"),
+            location: Location { line: 12, column: 22 },
+        };
+        assert_eq!(err.to_string(), "12:22: invalid value of ele: \"high\"");
+    }
+
+    const TWO_POINTS_GPX: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <trk>
+        <name>In memory run</name>
+        <trkseg>
+            <trkpt lat=\"10.1025420\" lon=\"15.1583540\"><time>2020-04-22T16:01:58Z</time></trkpt>
+            <trkpt lat=\"10.1025432\" lon=\"15.1583542\"><time>2020-04-22T16:02:04Z</time></trkpt>
+        </trkseg>
+    </trk>
+</gpx>";
+
+    fn assert_two_points_track(track: &Track) {
+        assert_eq!(track.name, "In memory run");
+        assert_eq!(track.route.len(), 1);
+        assert_eq!(track.route[0].points.len(), 2);
+        assert_eq!(track.route[0].points[1].latitude, 10.1025432);
+        assert_eq!(track.route[0].points[1].longitude, 15.1583542);
+    }
+
+    #[test]
+    fn test_read_gpx_from_reader() {
+        let reader = std::io::Cursor::new(TWO_POINTS_GPX.as_bytes().to_vec());
+        let track = read_gpx_from_reader(reader).unwrap();
+        assert_two_points_track(&track);
+    }
+
+    #[test]
+    fn test_read_gpx_from_bytes() {
+        let track = read_gpx_from_bytes(TWO_POINTS_GPX.as_bytes()).unwrap();
+        assert_two_points_track(&track);
+    }
+
+    #[test]
+    fn test_read_gzipped_gpx_from_reader() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(TWO_POINTS_GPX.as_bytes()).unwrap();
+        let data = encoder.finish().unwrap();
+
+        assert_two_points_track(&read_gpx_from_reader(&data[..]).unwrap());
+        let gpx = read_gpx_document_from_reader(&data[..]).unwrap();
+        assert_two_points_track(&gpx.tracks[0]);
+    }
+
+    #[test]
+    fn test_read_gpx_from_str() {
+        let track = read_gpx_from_str(TWO_POINTS_GPX).unwrap();
+        assert_two_points_track(&track);
+    }
+
+    #[test]
+    fn test_track_from_str() {
+        let track: Track = TWO_POINTS_GPX.parse().unwrap();
+        assert_two_points_track(&track);
+
+        let result = "<gpx".parse::<Track>();
+        assert!(matches!(result, Err(ParseError::Syntax { .. })));
+    }
+
+    #[test]
+    fn test_parsing_track_points_without_time() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <trk><trkseg>
+        <trkpt lat=\"10.1025420\" lon=\"15.1583540\"></trkpt>
+        <trkpt lat=\"10.1026420\" lon=\"15.1583540\"></trkpt>
+    </trkseg></trk>
+</gpx>";
+
+        let track = read_gpx_from_str(gpx_str).unwrap();
+        assert_eq!(track.route[0].points[0].time, None);
+        assert_eq!(track.route[0].points[1].time, None);
+        assert_eq!(track.distance(), 11);
+        assert_eq!(track.duration(), std::time::Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_parsing_multiple_tracks() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <metadata>
+        <name>Weekend</name>
+        <time>2020-04-22T16:01:58Z</time>
+    </metadata>
+    <trk>
+        <name>Morning run</name>
+        <trkseg>
+            <trkpt lat=\"10.1025420\" lon=\"15.1583540\"><time>2020-04-22T16:01:58Z</time></trkpt>
+        </trkseg>
+        <trkseg>
+            <trkpt lat=\"10.1025432\" lon=\"15.1583542\"><time>2020-04-22T16:02:04Z</time></trkpt>
+        </trkseg>
+    </trk>
+    <trk>
+        <name>Evening run</name>
+        <trkseg>
+            <trkpt lat=\"11.1025420\" lon=\"16.1583540\">
+                <name>Start</name>
+                <time>2020-04-22T19:01:58Z</time>
+            </trkpt>
+        </trkseg>
+    </trk>
+</gpx>";
+
+        let gpx: Gpx = gpx_str.parse().unwrap();
+        let expected_time = Utc.ymd(2020, 4, 22).and_hms(16, 1, 58);
+        assert_eq!(gpx.metadata.name, Some(String::from("Weekend")));
+        assert_eq!(gpx.metadata.time, Some(expected_time));
+        assert_eq!(gpx.tracks.len(), 2);
+
+        assert_eq!(gpx.tracks[0].name, "Morning run");
+        assert_eq!(gpx.tracks[0].creation_time, Some(expected_time));
+        assert_eq!(gpx.tracks[0].route.len(), 2);
+
+        assert_eq!(gpx.tracks[1].name, "Evening run");
+        assert_eq!(gpx.tracks[1].route.len(), 1);
+        assert_eq!(gpx.tracks[1].route[0].points[0].latitude, 11.1025420);
+
+        let track = read_gpx_from_str(gpx_str).unwrap();
+        assert_eq!(track.name, "Morning run");
+    }
+
+    #[test]
+    fn test_parsing_waypoints_and_routes() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <wpt lat=\"46.5714\" lon=\"7.6821\">
+        <ele>1235.5</ele>
+        <time>2020-07-11T06:00:00Z</time>
+        <name>Aid station 1</name>
+        <desc>Water and gels</desc>
+        <sym>Drinking Water</sym>
+    </wpt>
+    <rte>
+        <name>Planned course</name>
+        <desc>Long run</desc>
+        <rtept lat=\"46.5700\" lon=\"7.6800\"><name>Start</name></rtept>
+        <rtept lat=\"46.5800\" lon=\"7.6900\"><ele>1300</ele></rtept>
+    </rte>
+    <trk>
+        <name>Actual run</name>
+        <trkseg>
+            <trkpt lat=\"46.5700\" lon=\"7.6800\"><time>2020-07-11T06:00:00Z</time></trkpt>
+        </trkseg>
+    </trk>
+</gpx>";
+
+        let gpx: Gpx = gpx_str.parse().unwrap();
+        assert_eq!(gpx.waypoints.len(), 1);
+        let waypoint = &gpx.waypoints[0];
+        assert_eq!(waypoint.latitude, 46.5714);
+        assert_eq!(waypoint.longitude, 7.6821);
+        assert_eq!(waypoint.elevation, Some(1235.5));
+        assert_eq!(waypoint.time, Some(Utc.ymd(2020, 7, 11).and_hms(6, 0, 0)));
+        assert_eq!(waypoint.name.as_deref(), Some("Aid station 1"));
+        assert_eq!(waypoint.description.as_deref(), Some("Water and gels"));
+        assert_eq!(waypoint.symbol.as_deref(), Some("Drinking Water"));
+
+        assert_eq!(gpx.routes.len(), 1);
+        let route = &gpx.routes[0];
+        assert_eq!(route.name.as_deref(), Some("Planned course"));
+        assert_eq!(route.description.as_deref(), Some("Long run"));
+        assert_eq!(route.points.len(), 2);
+        assert_eq!(route.points[0].name.as_deref(), Some("Start"));
+        assert_eq!(route.points[0].elevation, None);
+        assert_eq!(route.points[1].latitude, 46.58);
+        assert_eq!(route.points[1].elevation, Some(1300.0));
+
+        assert_eq!(gpx.tracks.len(), 1);
+        assert_eq!(gpx.tracks[0].name, "Actual run");
+    }
+
+    #[test]
+    fn test_parsing_route_point_outside_route() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <rtept lat=\"46.5700\" lon=\"7.6800\"></rtept>
+</gpx>";
+
+        match read_gpx_document_from_str(gpx_str) {
+            Err(ParseError::UnexpectedElement { element, .. }) => assert_eq!(element, "rtept"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parsing_gpx_1_0() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx
+  version=\"1.0\"
+  creator=\"GPSBabel - http://www.gpsbabel.org\"
+  xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\"
+  xmlns=\"http://www.topografix.com/GPX/1/0\"
+  xsi:schemaLocation=\"http://www.topografix.com/GPX/1/0 http://www.topografix.com/GPX/1/0/gpx.xsd\">
+<name>Lunch run</name>
+<author>Jane Doe</author>
+<email>jane@example.com</email>
+<url>https://example.com/runs</url>
+<urlname>All runs</urlname>
+<time>2019-08-03T10:15:02Z</time>
+<bounds minlat=\"52.516100\" minlon=\"13.377600\" maxlat=\"52.516400\" maxlon=\"13.378100\"/>
+<wpt lat=\"52.516275\" lon=\"13.377704\">
+  <ele>34.000000</ele>
+  <name>GATE</name>
+  <sym>Waypoint</sym>
+</wpt>
+<trk>
+  <name>ACTIVE LOG</name>
+  <url>https://example.com/runs/1</url>
+  <number>1</number>
+<trkseg>
+<trkpt lat=\"52.516100\" lon=\"13.377600\">
+  <ele>35.200000</ele>
+  <time>2019-08-03T10:15:02Z</time>
+  <course>87.300003</course>
+  <speed>2.940000</speed>
+</trkpt>
+<trkpt lat=\"52.516400\" lon=\"13.378100\">
+  <ele>35.700000</ele>
+  <time>2019-08-03T10:15:14Z</time>
+  <course>90.099998</course>
+  <speed>3.120000</speed>
+</trkpt>
+</trkseg>
+</trk>
+</gpx>";
+
+        let gpx: Gpx = gpx_str.parse().unwrap();
+        let expected_time = Utc.ymd(2019, 8, 3).and_hms(10, 15, 2);
+        assert_eq!(gpx.metadata.name.as_deref(), Some("Lunch run"));
+        assert_eq!(gpx.metadata.time, Some(expected_time));
+        assert_eq!(gpx.creator.as_deref(), Some("GPSBabel - http://www.gpsbabel.org"));
+        let author = gpx.metadata.author.as_ref().unwrap();
+        assert_eq!(author.name.as_deref(), Some("Jane Doe"));
+        assert_eq!(author.email.as_deref(), Some("jane@example.com"));
+        assert_eq!(gpx.metadata.links.len(), 1);
+        assert_eq!(gpx.metadata.links[0].href, "https://example.com/runs");
+        assert_eq!(gpx.metadata.links[0].text.as_deref(), Some("All runs"));
+        let bounds = gpx.metadata.bounds.unwrap();
+        assert_eq!(bounds.min_latitude, 52.5161);
+        assert_eq!(bounds.max_longitude, 13.3781);
+
+        assert_eq!(gpx.waypoints.len(), 1);
+        assert_eq!(gpx.waypoints[0].name.as_deref(), Some("GATE"));
+        assert_eq!(gpx.waypoints[0].elevation, Some(34.0));
+
+        assert_eq!(gpx.tracks.len(), 1);
+        let track = &gpx.tracks[0];
+        assert_eq!(track.name, "ACTIVE LOG");
+        assert_eq!(track.creation_time, Some(expected_time));
+        assert_eq!(track.number, Some(1));
+        assert_eq!(track.links, vec![Link::new("https://example.com/runs/1")]);
+        assert_eq!(track.device, gpx.creator);
+        assert_eq!(track.route.len(), 1);
+        assert_eq!(track.route[0].points.len(), 2);
+
+        let point = &track.route[0].points[0];
+        assert_eq!(point.latitude, 52.5161);
+        assert_eq!(point.longitude, 13.3776);
+        assert_eq!(point.elevation, Some(35.2));
+        assert_eq!(point.time, Some(expected_time));
+        assert_eq!(point.course, Some(87.300003));
+        assert_eq!(point.speed, Some(2.94));
+
+        let point = &track.route[0].points[1];
+        assert_eq!(point.time, Some(Utc.ymd(2019, 8, 3).and_hms(10, 15, 14)));
+        assert_eq!(point.course, Some(90.099998));
+        assert_eq!(point.speed, Some(3.12));
+    }
+
+    #[test]
+    fn test_parsing_descriptive_metadata() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx creator=\"Garmin Forerunner 245\" version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+  <metadata>
+    <name>Weekend</name>
+    <desc>Runs of the weekend</desc>
+    <author>
+      <name>Jane Doe</name>
+      <email id=\"jane\" domain=\"example.com\"/>
+      <link href=\"https://example.com/jane\"><text>Homepage</text></link>
+    </author>
+    <copyright author=\"Jane Doe\">
+      <year>2020</year>
+      <license>https://creativecommons.org/licenses/by/4.0/</license>
+    </copyright>
+    <link href=\"https://example.com/weekend.jpg\">
+      <text>Photo</text>
+      <type>image/jpeg</type>
+    </link>
+    <time>2020-04-25T08:00:00Z</time>
+    <keywords>running, long run,,park</keywords>
+    <bounds minlat=\"52.1\" minlon=\"13.2\" maxlat=\"52.3\" maxlon=\"13.4\"/>
+  </metadata>
+  <wpt lat=\"52.2\" lon=\"13.3\"><type>Water</type></wpt>
+  <trk>
+    <name>Long run</name>
+    <cmt>Felt good</cmt>
+    <desc>Easy pace along the river</desc>
+    <src>Forerunner 245</src>
+    <link href=\"https://example.com/activity/1\"/>
+    <number>2</number>
+    <type>trail_running</type>
+    <trkseg>
+      <trkpt lat=\"52.1\" lon=\"13.2\"><type>ignored</type></trkpt>
+    </trkseg>
+  </trk>
+  <trk>
+    <type>Kayaking</type>
+  </trk>
+</gpx>";
+
+        let gpx: Gpx = gpx_str.parse().unwrap();
+        assert_eq!(gpx.creator.as_deref(), Some("Garmin Forerunner 245"));
+
+        let metadata = &gpx.metadata;
+        assert_eq!(metadata.name.as_deref(), Some("Weekend"));
+        assert_eq!(metadata.description.as_deref(), Some("Runs of the weekend"));
+        assert_eq!(metadata.keywords, vec!["running", "long run", "park"]);
+        assert_eq!(
+            metadata.bounds,
+            Some(Bounds {
+                min_latitude: 52.1,
+                min_longitude: 13.2,
+                max_latitude: 52.3,
+                max_longitude: 13.4,
+            })
+        );
+
+        let author = metadata.author.as_ref().unwrap();
+        assert_eq!(author.name.as_deref(), Some("Jane Doe"));
+        assert_eq!(author.email.as_deref(), Some("jane@example.com"));
+        let author_link = author.link.as_ref().unwrap();
+        assert_eq!(author_link.href, "https://example.com/jane");
+        assert_eq!(author_link.text.as_deref(), Some("Homepage"));
+
+        let copyright = metadata.copyright.as_ref().unwrap();
+        assert_eq!(copyright.author, "Jane Doe");
+        assert_eq!(copyright.year, Some(2020));
+        assert_eq!(
+            copyright.license.as_deref(),
+            Some("https://creativecommons.org/licenses/by/4.0/")
+        );
+
+        assert_eq!(metadata.links.len(), 1);
+        assert_eq!(metadata.links[0].text.as_deref(), Some("Photo"));
+        assert_eq!(metadata.links[0].mime_type.as_deref(), Some("image/jpeg"));
+
+        assert_eq!(gpx.tracks.len(), 2);
+        let track = &gpx.tracks[0];
+        assert_eq!(track.name, "Long run");
+        assert_eq!(track.activity_type, Some(ActivityType::Running));
+        assert_eq!(track.comment.as_deref(), Some("Felt good"));
+        assert_eq!(track.description.as_deref(), Some("Easy pace along the river"));
+        assert_eq!(track.source.as_deref(), Some("Forerunner 245"));
+        assert_eq!(track.links, vec![Link::new("https://example.com/activity/1")]);
+        assert_eq!(track.number, Some(2));
+        assert_eq!(track.device.as_deref(), Some("Garmin Forerunner 245"));
+
+        let track = &gpx.tracks[1];
+        assert_eq!(
+            track.activity_type,
+            Some(ActivityType::Other(String::from("Kayaking")))
+        );
+        assert_eq!(track.description, None);
+        assert!(track.links.is_empty());
+    }
+
+    #[test_case("running", ActivityType::Running; "garmin running")]
+    #[test_case("Trail Running", ActivityType::Running; "spaces")]
+    #[test_case("9", ActivityType::Running; "strava running code")]
+    #[test_case("Ride", ActivityType::Cycling; "strava ride")]
+    #[test_case("road-biking", ActivityType::Cycling; "dashes")]
+    #[test_case("Biking", ActivityType::Cycling; "tcx biking")]
+    #[test_case("hike", ActivityType::Hiking; "hike")]
+    #[test_case("walking", ActivityType::Walking; "walking")]
+    #[test_case("open_water_swimming", ActivityType::Swimming; "swimming")]
+    #[test_case("NordicSki", ActivityType::Skiing; "skiing")]
+    #[test_case(" Rowing ", ActivityType::Other(String::from("Rowing")); "unknown")]
+    fn test_activity_type_from_name(name: &str, expected: ActivityType) {
+        assert_eq!(ActivityType::from_name(name), expected);
+    }
+
+    #[test_case("<bounds minlat=\"52.1\" minlon=\"west\" maxlat=\"52.3\" maxlon=\"13.4\"/>", "bounds@minlon", "west"; "bounds")]
+    #[test_case("<trk><number>first</number></trk>", "number", "first"; "track number")]
+    #[test_case("<metadata><copyright author=\"me\"><year>MMXX</year></copyright></metadata>", "year", "MMXX"; "copyright year")]
+    fn test_parsing_invalid_descriptive_metadata(element: &str, expected_field: &str, expected_value: &str) {
+        let gpx = format!(
+            "<gpx xmlns=\"http://www.topografix.com/GPX/1/1\">{}</gpx>",
+            element
+        );
+
+        match read_gpx_document_from_str(&gpx) {
+            Err(ParseError::InvalidValue { field, value, .. }) => {
+                assert_eq!(field, expected_field);
+                assert_eq!(value, expected_value);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parsing_link_without_href() {
+        let gpx = "<gpx xmlns=\"http://www.topografix.com/GPX/1/1\"><trk><link/></trk></gpx>";
+
+        match read_gpx_document_from_str(gpx) {
+            Err(ParseError::MissingAttribute {
+                element, attribute, ..
+            }) => {
+                assert_eq!(element, "link");
+                assert_eq!(attribute, "href");
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parsing_gpx_1_0_without_tracks() {
+        let gpx_str = "<?xml version=\"1.0\"?>
+<gpx version=\"1.0\" creator=\"ExpertGPS 1.1 - http://www.topografix.com\" xmlns=\"http://www.topografix.com/GPX/1/0\">
+<time>2002-02-27T17:18:33Z</time>
+<rte>
+<name>BELLEVUE</name>
+<rtept lat=\"42.430950\" lon=\"-71.107628\"><ele>23.469600</ele><name>BELLEVUE</name></rtept>
+<rtept lat=\"42.431240\" lon=\"-71.109236\"><ele>26.561890</ele><name>GATE6</name></rtept>
+</rte>
+</gpx>";
+
+        let track = read_gpx_from_str(gpx_str).unwrap();
+        assert_eq!(track.creation_time, Some(Utc.ymd(2002, 2, 27).and_hms(17, 18, 33)));
+        assert_eq!(track.route.len(), 0);
+
+        let gpx: Gpx = gpx_str.parse().unwrap();
+        assert_eq!(gpx.metadata.name, None);
+        assert_eq!(gpx.routes.len(), 1);
+        assert_eq!(gpx.routes[0].name.as_deref(), Some("BELLEVUE"));
+        assert_eq!(gpx.routes[0].points[1].name.as_deref(), Some("GATE6"));
+    }
+
+    #[test]
+    fn test_parsing_garmin_track_point_extension_v2() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx creator=\"Garmin Connect\" version=\"1.1\"
+  xmlns=\"http://www.topografix.com/GPX/1/1\"
+  xmlns:ns3=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v2\"
+  xmlns:ns2=\"http://www.garmin.com/xmlschemas/GpxExtensions/v3\">
+  <trk>
+    <name>Open water swim</name>
+    <trkseg>
+      <trkpt lat=\"45.8120\" lon=\"9.0850\">
+        <ele>198.2</ele>
+        <time>2020-07-18T07:30:00Z</time>
+        <extensions>
+          <ns3:TrackPointExtension>
+            <ns3:atemp>31.0</ns3:atemp>
+            <ns3:wtemp>22.5</ns3:wtemp>
+            <ns3:depth>0.4</ns3:depth>
+            <ns3:hr>132</ns3:hr>
+            <ns3:cad>28</ns3:cad>
+            <ns3:speed>1.05</ns3:speed>
+            <ns3:course>271.5</ns3:course>
+          </ns3:TrackPointExtension>
+        </extensions>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>";
+
+        let track = read_gpx_from_str(gpx_str).unwrap();
+        let point = &track.route[0].points[0];
+        assert_eq!(point.air_temperature, Some(31.0));
+        assert_eq!(point.water_temperature, Some(22.5));
+        assert_eq!(point.depth, Some(0.4));
+        assert_eq!(point.heart_rate, Some(132));
+        assert_eq!(point.cadence, Some(28));
+        assert_eq!(point.speed, Some(1.05));
+        assert_eq!(point.course, Some(271.5));
+    }
+
+    #[test]
+    fn test_parsing_garmin_track_point_extension_v1_temperature() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\"
+  xmlns=\"http://www.topografix.com/GPX/1/1\"
+  xmlns:gpxtpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v1\">
+  <trk>
+    <trkseg>
+      <trkpt lat=\"10.1025420\" lon=\"15.1583540\">
+        <time>2020-04-22T16:01:58Z</time>
+        <extensions>
+          <gpxtpx:TrackPointExtension>
+            <gpxtpx:atemp>36.5</gpxtpx:atemp>
+            <gpxtpx:hr>150</gpxtpx:hr>
+          </gpxtpx:TrackPointExtension>
+        </extensions>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>";
+
+        let track = read_gpx_from_str(gpx_str).unwrap();
+        let point = &track.route[0].points[0];
+        assert_eq!(point.air_temperature, Some(36.5));
+        assert_eq!(point.water_temperature, None);
+        assert_eq!(point.depth, None);
+        assert_eq!(point.heart_rate, Some(150));
+        assert_eq!(point.speed, None);
+    }
+
+    #[test]
+    fn test_parsing_power_extensions() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\"
+  xmlns=\"http://www.topografix.com/GPX/1/1\"
+  xmlns:pwr=\"http://www.garmin.com/xmlschemas/PowerExtension/v1\">
+  <trk>
+    <trkseg>
+      <trkpt lat=\"10.1025420\" lon=\"15.1583540\">
+        <time>2020-04-22T16:01:58Z</time>
+        <extensions><power>251</power></extensions>
+      </trkpt>
+      <trkpt lat=\"10.1025432\" lon=\"15.1583542\">
+        <time>2020-04-22T16:02:04Z</time>
+        <extensions>
+          <pwr:PowerExtension><pwr:PowerInWatts>263</pwr:PowerInWatts></pwr:PowerExtension>
+        </extensions>
+      </trkpt>
+      <trkpt lat=\"10.1025452\" lon=\"15.1583552\">
+        <time>2020-04-22T16:02:10Z</time>
+        <extensions><power xmlns=\"\">0</power></extensions>
+      </trkpt>
+      <trkpt lat=\"10.1025472\" lon=\"15.1583572\">
+        <time>2020-04-22T16:02:16Z</time>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>";
+
+        let track = read_gpx_from_str(gpx_str).unwrap();
+        let points = &track.route[0].points;
+        assert_eq!(points[0].power, Some(251));
+        assert_eq!(points[1].power, Some(263));
+        assert_eq!(points[2].power, Some(0));
+        assert_eq!(points[3].power, None);
+        assert_eq!(track.max_power(), Some(263));
+    }
+
+    #[test]
+    fn test_streaming_track_points() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <metadata><time>2020-04-22T16:01:58Z</time></metadata>
+    <trk>
+        <trkseg>
+            <trkpt lat=\"10.0\" lon=\"15.0\"><time>2020-04-22T16:02:04Z</time></trkpt>
+            <trkpt lat=\"10.1\" lon=\"15.1\"><time>2020-04-22T16:01:58Z</time></trkpt>
+        </trkseg>
+        <trkseg>
+            <trkpt lat=\"10.2\" lon=\"15.2\"><time>2020-04-22T16:02:30Z</time></trkpt>
+        </trkseg>
+    </trk>
+    <trk>
+        <trkseg>
+            <trkpt lat=\"11.0\" lon=\"16.0\"><time>2020-04-22T19:00:00Z</time></trkpt>
+        </trkseg>
+    </trk>
+</gpx>";
+
+        let mut reader = GpxPointReader::new(gpx_str.as_bytes());
+        let mut points = Vec::new();
+        while let Some(point) = reader.next() {
+            points.push(point.unwrap());
+
+            // Nothing should pile up while streaming
+            assert!(reader.gpx.tracks.is_empty());
+            assert!(reader.context.current_track.route.is_empty());
+            assert!(reader.context.current_segment.points.is_empty());
+        }
+
+        assert_eq!(
+            reader.metadata().time,
+            Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58))
+        );
+
+        let indices: Vec<(usize, usize)> = points
+            .iter()
+            .map(|p| (p.track_index, p.segment_index))
+            .collect();
+        assert_eq!(indices, vec![(0, 0), (0, 0), (0, 1), (1, 0)]);
+
+        let latitudes: Vec<f64> = points.iter().map(|p| p.point.latitude).collect();
+        assert_eq!(latitudes, vec![10.0, 10.1, 10.2, 11.0]);
+    }
+
+    #[test]
+    fn test_streaming_stops_after_error() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <trk><trkseg>
+        <trkpt lat=\"10.0\" lon=\"15.0\"></trkpt>
+        <trkpt lat=\"10.1\" lon=\"15.1\"><ele>high</ele></trkpt>
+        <trkpt lat=\"10.2\" lon=\"15.2\"></trkpt>
+    </trkseg></trk>
+</gpx>";
+
+        let mut reader = GpxPointReader::new(gpx_str.as_bytes());
+        assert!(matches!(reader.next(), Some(Ok(_))));
+        assert!(matches!(reader.next(), Some(Err(ParseError::InvalidValue { .. }))));
+        assert!(reader.next().is_none());
+    }
+
+    const BAD_POINTS_GPX: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\"
+  xmlns=\"http://www.topografix.com/GPX/1/1\"
+  xmlns:gpxtpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v1\">
+  <trk>
+    <trkseg>
+      <trkpt lat=\"10.0\" lon=\"15.0\">
+        <ele>n/a</ele>
+        <time>2020-04-22T16:01:58Z</time>
+      </trkpt>
+      <trkpt lat=\"10.1\" lon=\"15.1\">
+        <ele>480.3</ele>
+        <time>2020-04-22T16:02:04Z</time>
+        <extensions>
+          <gpxtpx:TrackPointExtension><gpxtpx:hr>300</gpxtpx:hr></gpxtpx:TrackPointExtension>
+        </extensions>
+      </trkpt>
+      <trkpt lat=\"10.2\" lon=\"15.2\">
+        <time>2020-04-22 16:02:10</time>
+      </trkpt>
+      <trkpt lat=\"\" lon=\"15.3\">
+        <time>2020-04-22T16:02:16Z</time>
+      </trkpt>
+      <trkpt lat=\"10.4\" lon=\"15.4\">
+        <time>2020-04-22T16:02:22Z</time>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>";
+
+    #[test]
+    fn test_strict_parsing_fails_on_bad_point() {
+        assert!(read_gpx_from_str(BAD_POINTS_GPX).is_err());
+
+        let result = read_gpx_from_reader_with_options(BAD_POINTS_GPX.as_bytes(), &ParseOptions::new());
+        assert!(matches!(result, Err(ParseError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn test_lenient_parsing() {
+        let (track, warnings) =
+            read_gpx_from_reader_with_options(BAD_POINTS_GPX.as_bytes(), &ParseOptions::lenient())
+                .unwrap();
+
+        // Points with broken elevation and heart rate are repaired, ones with broken
+        // time and coordinates are skipped
+        let points = &track.route[0].points;
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].latitude, 10.0);
+        assert_eq!(points[0].elevation, None);
+        assert_eq!(points[1].latitude, 10.1);
+        assert_eq!(points[1].elevation, Some(480.3));
+        assert_eq!(points[1].heart_rate, None);
+        assert_eq!(points[2].latitude, 10.4);
+
+        let indices: Vec<usize> = warnings.iter().map(|w| w.point_index).collect();
+        assert_eq!(indices, vec![0, 1, 2, 3]);
+
+        match &warnings[1].error {
+            ParseError::InvalidValue { field, value, .. } => {
+                assert_eq!(field, "hr");
+                assert_eq!(value, "300");
+            }
+            other => panic!("Unexpected warning: {:?}", other),
+        }
+        assert_eq!(
+            warnings[3].to_string(),
+            "point #3: 21:7: invalid value of trkpt@lat: \"\""
+        );
+    }
+
+    #[test]
+    fn test_lenient_parsing_keeps_structural_errors() {
+        let gpx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\">
+    <trk><trkpt lat=\"10.0\" lon=\"15.0\"></trkpt></trk>
+</gpx>";
+
+        let result = read_gpx_from_reader_with_options(gpx_str.as_bytes(), &ParseOptions::lenient());
+        assert!(matches!(result, Err(ParseError::UnexpectedElement { .. })));
     }
 }
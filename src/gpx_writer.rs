@@ -0,0 +1,339 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use xml::writer::{EmitterConfig, Error as EmitterError, EventWriter, XmlEvent};
+
+use super::{Track, TrackPoint};
+
+const TOPOGRAFIX_GPX_SCHEMA: &str = "http://www.topografix.com/GPX/1/1";
+const GARMIN_TRACK_POINT_EXT_SCHEMA: &str =
+    "http://www.garmin.com/xmlschemas/TrackPointExtension/v1";
+const SCHEMA_LOCATION: &str = "http://www.topografix.com/GPX/1/1 \
+http://www.topografix.com/GPX/1/1/gpx.xsd \
+http://www.garmin.com/xmlschemas/TrackPointExtension/v1 \
+http://www.garmin.com/xmlschemas/TrackPointExtensionv1.xsd";
+
+pub(crate) fn convert_emitter_error(err: EmitterError) -> io::Error {
+    match err {
+        EmitterError::Io(err) => err,
+        other => io::Error::new(io::ErrorKind::InvalidData, other.to_string()),
+    }
+}
+
+pub(crate) fn format_xml_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+pub(crate) fn write_text_element<W: Write>(
+    writer: &mut EventWriter<W>,
+    name: &str,
+    text: &str,
+) -> Result<(), EmitterError> {
+    writer.write(XmlEvent::start_element(name))?;
+    writer.write(XmlEvent::characters(text))?;
+    writer.write(XmlEvent::end_element())
+}
+
+/// Serializes a track as GPX 1.1. Heart rate and cadence go to Garmin TrackPointExtension.
+/// Every document has a single root, so the writer is used up by writing one.
+pub struct GpxWriter<W: Write> {
+    writer: EventWriter<W>,
+}
+
+impl<W: Write> GpxWriter<W> {
+    pub fn new(writer: W) -> GpxWriter<W> {
+        let writer = EmitterConfig::new()
+            .perform_indent(true)
+            .create_writer(writer);
+
+        GpxWriter { writer }
+    }
+
+    /// Writes a complete GPX document holding the track and gives back the underlying
+    /// writer. Track creation time becomes document `<metadata><time>`, track device
+    /// becomes `creator` of the document.
+    pub fn write(mut self, track: &Track) -> io::Result<W> {
+        self.write_track(track).map_err(convert_emitter_error)?;
+        Ok(self.writer.into_inner())
+    }
+
+    /// Descriptive children of `<trk>` in the order required by the schema
+    fn write_track_description(&mut self, track: &Track) -> Result<(), EmitterError> {
+        let texts = [
+            ("cmt", &track.comment),
+            ("desc", &track.description),
+            ("src", &track.source),
+        ];
+        for (name, text) in texts.iter() {
+            if let Some(text) = text {
+                write_text_element(&mut self.writer, name, text)?;
+            }
+        }
+
+        for link in &track.links {
+            self.writer
+                .write(XmlEvent::start_element("link").attr("href", &link.href))?;
+            if let Some(text) = &link.text {
+                write_text_element(&mut self.writer, "text", text)?;
+            }
+            if let Some(mime_type) = &link.mime_type {
+                write_text_element(&mut self.writer, "type", mime_type)?;
+            }
+            self.writer.write(XmlEvent::end_element())?;
+        }
+
+        if let Some(number) = track.number {
+            write_text_element(&mut self.writer, "number", &number.to_string())?;
+        }
+        if let Some(activity_type) = &track.activity_type {
+            write_text_element(&mut self.writer, "type", &activity_type.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn write_track(&mut self, track: &Track) -> Result<(), EmitterError> {
+        self.writer.write(
+            XmlEvent::start_element("gpx")
+                .attr("version", "1.1")
+                .attr("creator", track.device.as_deref().unwrap_or("runstats"))
+                .attr("xsi:schemaLocation", SCHEMA_LOCATION)
+                .default_ns(TOPOGRAFIX_GPX_SCHEMA)
+                .ns("xsi", "http://www.w3.org/2001/XMLSchema-instance")
+                .ns("gpxtpx", GARMIN_TRACK_POINT_EXT_SCHEMA),
+        )?;
+
+        if let Some(creation_time) = &track.creation_time {
+            self.writer.write(XmlEvent::start_element("metadata"))?;
+            write_text_element(&mut self.writer, "time", &format_xml_time(creation_time))?;
+            self.writer.write(XmlEvent::end_element())?;
+        }
+
+        self.writer.write(XmlEvent::start_element("trk"))?;
+        if !track.name.is_empty() {
+            write_text_element(&mut self.writer, "name", &track.name)?;
+        }
+        self.write_track_description(track)?;
+        for segment in &track.route {
+            self.writer.write(XmlEvent::start_element("trkseg"))?;
+            for point in &segment.points {
+                self.write_point(point)?;
+            }
+            self.writer.write(XmlEvent::end_element())?;
+        }
+        self.writer.write(XmlEvent::end_element())?;
+
+        self.writer.write(XmlEvent::end_element())?;
+        self.writer.inner_mut().flush()?;
+        Ok(())
+    }
+
+    fn write_point(&mut self, point: &TrackPoint) -> Result<(), EmitterError> {
+        let latitude = point.latitude.to_string();
+        let longitude = point.longitude.to_string();
+        self.writer.write(
+            XmlEvent::start_element("trkpt")
+                .attr("lat", &latitude)
+                .attr("lon", &longitude),
+        )?;
+
+        if let Some(elevation) = point.elevation {
+            write_text_element(&mut self.writer, "ele", &elevation.to_string())?;
+        }
+        if let Some(time) = &point.time {
+            write_text_element(&mut self.writer, "time", &format_xml_time(time))?;
+        }
+
+        if point.heart_rate.is_some() || point.cadence.is_some() {
+            self.writer.write(XmlEvent::start_element("extensions"))?;
+            self.writer
+                .write(XmlEvent::start_element("gpxtpx:TrackPointExtension"))?;
+            if let Some(heart_rate) = point.heart_rate {
+                write_text_element(&mut self.writer, "gpxtpx:hr", &heart_rate.to_string())?;
+            }
+            if let Some(cadence) = point.cadence {
+                write_text_element(&mut self.writer, "gpxtpx:cad", &cadence.to_string())?;
+            }
+            self.writer.write(XmlEvent::end_element())?;
+            self.writer.write(XmlEvent::end_element())?;
+        }
+
+        self.writer.write(XmlEvent::end_element())
+    }
+}
+
+/// Writes the track as GPX 1.1 document
+pub fn write_gpx<W: Write>(track: &Track, writer: W) -> io::Result<()> {
+    GpxWriter::new(writer).write(track).map(|_| ())
+}
+
+/// Writes the track as GPX 1.1 file, replacing the existing one
+pub fn write_gpx_file(track: &Track, path: &str) -> io::Result<()> {
+    let file = File::create(path)?;
+
+    write_gpx(track, BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::BufReader;
+
+    use chrono::TimeZone;
+
+    use super::super::gpx_parser::read_gpx_from;
+    use super::super::{ActivityType, Link, TrackSegment};
+
+    fn write_to_string(track: &Track) -> String {
+        let mut buffer = Vec::new();
+        write_gpx(track, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    fn read_back(gpx: &str) -> Track {
+        read_gpx_from(BufReader::new(gpx.as_bytes())).unwrap()
+    }
+
+    fn assert_points_eq(actual: &TrackPoint, expected: &TrackPoint) {
+        assert_eq!(actual.latitude, expected.latitude);
+        assert_eq!(actual.longitude, expected.longitude);
+        assert_eq!(actual.elevation, expected.elevation);
+        assert_eq!(actual.time, expected.time);
+        assert_eq!(actual.heart_rate, expected.heart_rate);
+        assert_eq!(actual.cadence, expected.cadence);
+    }
+
+    fn new_track() -> Track {
+        let mut first = TrackPoint::from_coordinates(10.1025420, 15.1583540);
+        first.elevation = Some(478.2);
+        first.time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58));
+        first.heart_rate = Some(95);
+        first.cadence = Some(79);
+
+        let mut second = TrackPoint::from_coordinates(10.1026420, 15.1583542);
+        second.time = Some(Utc.ymd(2020, 4, 22).and_hms_milli(16, 2, 4, 250));
+        second.heart_rate = Some(97);
+
+        let mut third = TrackPoint::from_coordinates(-10.1027420, -15.1583544);
+        third.elevation = Some(-2.5);
+        third.time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 3, 0));
+
+        let mut track = Track::new();
+        track.name = String::from("Evening <run> & walk");
+        track.creation_time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58));
+        track.route.push(TrackSegment {
+            points: vec![first, second],
+        });
+        track.route.push(TrackSegment {
+            points: vec![third],
+        });
+        track
+    }
+
+    #[test]
+    fn test_writing_gpx() {
+        let gpx = write_to_string(&new_track());
+
+        // Older xml-rs versions spell the encoding in lower case, newer ones in upper case
+        let declaration = gpx.lines().next().unwrap().to_lowercase();
+        assert_eq!(declaration, "<?xml version=\"1.0\" encoding=\"utf-8\"?>");
+        assert!(gpx.contains("xmlns=\"http://www.topografix.com/GPX/1/1\""));
+        assert!(gpx.contains("<time>2020-04-22T16:01:58Z</time>"));
+        // Escaping of `>` is optional and also differs between xml-rs versions
+        assert!(gpx.contains("<name>Evening &lt;run"));
+        assert!(gpx.contains(" &amp; walk</name>"));
+        assert_eq!(read_back(&gpx).name, "Evening <run> & walk");
+        assert!(gpx.contains("<trkpt lat=\"10.102542\" lon=\"15.158354\">"));
+        assert!(gpx.contains("<time>2020-04-22T16:02:04.250Z</time>"));
+        assert!(gpx.contains("<gpxtpx:hr>95</gpxtpx:hr>"));
+        assert!(gpx.contains("<gpxtpx:cad>79</gpxtpx:cad>"));
+        assert_eq!(gpx.matches("<extensions>").count(), 2);
+    }
+
+    #[test]
+    fn test_gpx_round_trip() {
+        let expected = new_track();
+        let track = read_back(&write_to_string(&expected));
+
+        assert_eq!(track.name, expected.name);
+        assert_eq!(track.creation_time, expected.creation_time);
+        assert_eq!(track.route.len(), expected.route.len());
+        for (segment, expected_segment) in track.route.iter().zip(&expected.route) {
+            assert_eq!(segment.points.len(), expected_segment.points.len());
+            for (point, expected_point) in segment.points.iter().zip(&expected_segment.points) {
+                assert_points_eq(point, expected_point);
+            }
+        }
+        assert_eq!(track.distance(), expected.distance());
+        assert_eq!(track.duration(), expected.duration());
+    }
+
+    #[test]
+    fn test_gpx_round_trip_without_optional_values() {
+        let mut expected = Track::new();
+        expected.route.push(TrackSegment {
+            points: vec![TrackPoint::from_coordinates(10.0, 15.0)],
+        });
+
+        let gpx = write_to_string(&expected);
+        assert!(!gpx.contains("<metadata>"));
+        assert!(!gpx.contains("<name>"));
+        assert!(!gpx.contains("<extensions>"));
+        assert!(gpx.contains(r#"creator="runstats""#));
+
+        let track = read_back(&gpx);
+        assert_eq!(track.name, "");
+        assert_eq!(track.creation_time, None);
+        assert_eq!(track.route.len(), 1);
+        assert_points_eq(&track.route[0].points[0], &expected.route[0].points[0]);
+    }
+
+    #[test]
+    fn test_gpx_round_trip_of_track_description() {
+        let mut expected = new_track();
+        expected.comment = Some(String::from("Felt good"));
+        expected.description = Some(String::from("Along the river"));
+        expected.source = Some(String::from("Forerunner 245"));
+        expected.links.push(Link {
+            href: String::from("https://example.com/activity/1?units=metric&lang=en"),
+            text: Some(String::from("Activity")),
+            mime_type: Some(String::from("text/html")),
+        });
+        expected.number = Some(3);
+        expected.activity_type = Some(ActivityType::Hiking);
+        expected.device = Some(String::from("Garmin Connect"));
+
+        let track = read_back(&write_to_string(&expected));
+        assert_eq!(track.comment, expected.comment);
+        assert_eq!(track.description, expected.description);
+        assert_eq!(track.source, expected.source);
+        assert_eq!(track.links, expected.links);
+        assert_eq!(track.number, expected.number);
+        assert_eq!(track.activity_type, expected.activity_type);
+        assert_eq!(track.device, expected.device);
+    }
+
+    #[test]
+    fn test_gpx_writer_gives_back_inner_writer() {
+        let buffer = GpxWriter::new(Vec::new()).write(&new_track()).unwrap();
+
+        let gpx = String::from_utf8(buffer).unwrap();
+        assert_eq!(read_back(&gpx).route.len(), 2);
+    }
+}
@@ -0,0 +1,282 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+use chrono::{Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+
+use super::gpx_parser::invalid_value;
+use super::{Location, ParseError, Track, TrackPoint, TrackSegment};
+
+/// Altitude of B-record which becomes `TrackPoint::elevation`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IgcAltitude {
+    /// Barometric altitude relative to ISA 1013.25 hPa. Smooth, good for climb rates.
+    Pressure,
+    /// Altitude above the WGS84 ellipsoid. Missing for points without 3D fix.
+    Gnss,
+}
+
+struct ParserContext {
+    altitude: IgcAltitude,
+    date: Option<NaiveDate>,
+    previous_time: Option<NaiveTime>,
+    segment: TrackSegment,
+}
+
+/// `HFDTEddmmyy` or `HFDTEDATE:ddmmyy,nn` as written since IGC specification 2016
+fn parse_date_header(line: &str, location: Location) -> Result<NaiveDate, ParseError> {
+    let value = &line[5..];
+    let value = value.strip_prefix("DATE:").unwrap_or(value);
+
+    let parse = || {
+        let day = value.get(0..2)?.parse().ok()?;
+        let month = value.get(2..4)?.parse().ok()?;
+        let year: i32 = value.get(4..6)?.parse().ok()?;
+        let year = if year < 80 { 2000 + year } else { 1900 + year };
+        NaiveDate::from_ymd_opt(year, month, day)
+    };
+    parse().ok_or_else(|| invalid_value("HFDTE", value, location))
+}
+
+/// `DDMMmmmN` for latitude, `DDDMMmmmE` for longitude: degrees, minutes and thousandths
+/// of minute followed by hemisphere
+fn parse_coordinate(
+    value: &str,
+    degree_digits: usize,
+    field: &str,
+    location: Location,
+) -> Result<f64, ParseError> {
+    let parse = || {
+        let degrees: u32 = value.get(0..degree_digits)?.parse().ok()?;
+        let minutes: u32 = value.get(degree_digits..degree_digits + 5)?.parse().ok()?;
+        let coordinate = f64::from(degrees) + f64::from(minutes) / 60_000.0;
+        match value.get(degree_digits + 5..)? {
+            "N" | "E" => Some(coordinate),
+            "S" | "W" => Some(-coordinate),
+            _ => None,
+        }
+    };
+    parse().ok_or_else(|| invalid_value(field, value, location))
+}
+
+fn parse_altitude(value: &str, field: &str, location: Location) -> Result<f64, ParseError> {
+    value
+        .parse::<i32>()
+        .map(f64::from)
+        .map_err(|_| invalid_value(field, value, location))
+}
+
+/// `BHHMMSSDDMMmmmNDDDMMmmmEVPPPPPGGGGG`. Extensions defined by I-record follow
+/// the fixed part and are ignored.
+fn parse_fix(line: &str, location: Location, context: &mut ParserContext) -> Result<(), ParseError> {
+    let field = |range: std::ops::Range<usize>| {
+        line.get(range)
+            .ok_or_else(|| invalid_value("B-record", line, location))
+    };
+
+    let time = field(1..7)?;
+    let time = NaiveTime::parse_from_str(time, "%H%M%S")
+        .map_err(|_| invalid_value("time", time, location))?;
+    let latitude = parse_coordinate(field(7..15)?, 2, "latitude", location)?;
+    let longitude = parse_coordinate(field(15..24)?, 3, "longitude", location)?;
+    let validity = field(24..25)?;
+    let pressure_altitude = parse_altitude(field(25..30)?, "pressure altitude", location)?;
+    let gnss_altitude = parse_altitude(field(30..35)?, "GNSS altitude", location)?;
+
+    // Flights passing midnight UTC keep the date of take-off in the header
+    if let (Some(date), Some(previous_time)) = (context.date, context.previous_time) {
+        if time < previous_time {
+            context.date = Some(date + Duration::days(1));
+        }
+    }
+    context.previous_time = Some(time);
+
+    let mut point = TrackPoint::from_coordinates(latitude, longitude);
+    point.time = context
+        .date
+        .map(|date| Utc.from_utc_datetime(&date.and_time(time)));
+    point.elevation = match context.altitude {
+        IgcAltitude::Pressure => Some(pressure_altitude),
+        IgcAltitude::Gnss if validity == "A" => Some(gnss_altitude),
+        IgcAltitude::Gnss => None,
+    };
+    context.segment.points.push(point);
+
+    Ok(())
+}
+
+fn read_igc_from<R: Read>(
+    mut reader: BufReader<R>,
+    altitude: IgcAltitude,
+) -> Result<Track, ParseError> {
+    let mut context = ParserContext {
+        altitude,
+        date: None,
+        previous_time: None,
+        segment: TrackSegment::new(),
+    };
+    let mut buffer = Vec::new();
+    let mut line_number = 0;
+
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        let line = String::from_utf8_lossy(&buffer);
+        let line = line.trim_end();
+        let location = Location {
+            line: line_number,
+            column: 1,
+        };
+
+        if line.starts_with('B') {
+            parse_fix(line, location, &mut context)?;
+        } else if line.starts_with('H') && line.get(2..5) == Some("DTE") {
+            context.date = Some(parse_date_header(line, location)?);
+        }
+    }
+
+    let mut track = Track::new();
+    track.creation_time = context
+        .segment
+        .points
+        .first()
+        .and_then(|point| point.time);
+    if !context.segment.points.is_empty() {
+        track.route.push(context.segment);
+    }
+    Ok(track)
+}
+
+/// Reads IGC flight log. Every B-record becomes a track point with elevation taken
+/// from the chosen altitude source.
+pub fn read_igc_from_reader<R: Read>(reader: R, altitude: IgcAltitude) -> Result<Track, ParseError> {
+    read_igc_from(BufReader::new(reader), altitude)
+}
+
+pub fn read_igc_from_str(igc: &str, altitude: IgcAltitude) -> Result<Track, ParseError> {
+    read_igc_from_reader(igc.as_bytes(), altitude)
+}
+
+pub fn read_igc(path: &str, altitude: IgcAltitude) -> Result<Track, ParseError> {
+    let file = File::open(path)?;
+
+    read_igc_from_reader(file, altitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    const IGC_STR: &str = "AXXX001 Flight recorder\r
+HFDTE220420\r
+HFPLTPILOTINCHARGE:Jane Doe\r
+I023638FXA3940SIU\r
+B1101355206343N00006198WA0058700558\r
+B1101455206300N00006100WA0060000570035\r
+B1101555206250S00006000EV0061500000\r
+LXXXcomment\r
+GXXXXXXXXXXXXXXXXXXX\r
+";
+
+    #[test]
+    fn test_reading_igc_pressure_altitude() {
+        let track = read_igc_from_str(IGC_STR, IgcAltitude::Pressure).unwrap();
+
+        assert_eq!(track.creation_time, Some(Utc.ymd(2020, 4, 22).and_hms(11, 1, 35)));
+        assert_eq!(track.route.len(), 1);
+
+        let points = &track.route[0].points;
+        assert_eq!(points.len(), 3);
+        assert!((points[0].latitude - (52.0 + 6.343 / 60.0)).abs() < 1e-9);
+        assert!((points[0].longitude + (6.198 / 60.0)).abs() < 1e-9);
+        assert_eq!(points[0].elevation, Some(587.0));
+        assert!(points[2].latitude < 0.0);
+        assert!(points[2].longitude > 0.0);
+        assert_eq!(points[2].elevation, Some(615.0));
+        assert_eq!(points[2].time, Some(Utc.ymd(2020, 4, 22).and_hms(11, 1, 55)));
+
+        let elevation_stats = track.elevation_stats().unwrap();
+        assert_eq!(elevation_stats.gain, 28);
+    }
+
+    #[test]
+    fn test_reading_igc_gnss_altitude() {
+        let track = read_igc_from_str(IGC_STR, IgcAltitude::Gnss).unwrap();
+
+        let points = &track.route[0].points;
+        assert_eq!(points[0].elevation, Some(558.0));
+        assert_eq!(points[1].elevation, Some(570.0));
+        // No 3D fix, GNSS altitude is meaningless
+        assert_eq!(points[2].elevation, None);
+    }
+
+    #[test]
+    fn test_reading_igc_over_midnight() {
+        let igc = "HFDTEDATE:311220,01\nB2359595206343N00006198WA0058700558\n\
+B0000095206343N00006198WA-001200558\n";
+
+        let track = read_igc_from_str(igc, IgcAltitude::Pressure).unwrap();
+        let points = &track.route[0].points;
+        assert_eq!(points[0].time, Some(Utc.ymd(2020, 12, 31).and_hms(23, 59, 59)));
+        assert_eq!(points[1].time, Some(Utc.ymd(2021, 1, 1).and_hms(0, 0, 9)));
+        assert_eq!(points[1].elevation, Some(-12.0));
+        assert_eq!(track.duration().as_secs(), 10);
+    }
+
+    #[test]
+    fn test_reading_igc_without_date() {
+        let track =
+            read_igc_from_str("B1101355206343N00006198WA0058700558\n", IgcAltitude::Gnss).unwrap();
+
+        assert_eq!(track.creation_time, None);
+        assert_eq!(track.route[0].points[0].time, None);
+    }
+
+    #[test_case("HFDTE320420", "HFDTE", "320420"; "bad date")]
+    #[test_case("B2501355206343N00006198WA0058700558", "time", "250135"; "bad time")]
+    #[test_case("B1101355206343X00006198WA0058700558", "latitude", "5206343X"; "bad hemisphere")]
+    #[test_case("B1101355206343N00006198WA00587", "B-record", "B1101355206343N00006198WA00587"; "short record")]
+    #[test_case("B1101355206343N00006198WA0058700x58", "GNSS altitude", "00x58"; "bad altitude")]
+    fn test_reading_invalid_igc(line: &str, expected_field: &str, expected_value: &str) {
+        let igc = format!("AXXX001\n{}\n", line);
+
+        match read_igc_from_str(&igc, IgcAltitude::Pressure) {
+            Err(ParseError::InvalidValue {
+                field,
+                value,
+                location,
+            }) => {
+                assert_eq!(field, expected_field);
+                assert_eq!(value, expected_value);
+                assert_eq!(location.line, 2);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reading_missing_igc_file() {
+        let result = read_igc("/nonexistent/path/to/flight.igc", IgcAltitude::Pressure);
+        assert!(matches!(result, Err(ParseError::Io(_))));
+    }
+}
@@ -0,0 +1,674 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::mem;
+
+use xml::attribute::OwnedAttribute;
+use xml::common::Position;
+use xml::name::OwnedName;
+use xml::reader::XmlEvent;
+use xml::EventReader;
+
+use chrono::prelude::*;
+use zip::ZipArchive;
+
+use super::archive::{detect_compression, Compression};
+use super::gpx_parser::{convert_xml_error, invalid_value, to_location};
+use super::{Location, ParseError, Track, TrackPoint, TrackSegment};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum KmlXmlTag {
+    Placemark,
+    Name,
+    LineString,
+    Coordinates,
+    Track,
+    When,
+    Coord,
+    SimpleArrayData,
+    Value,
+}
+
+impl KmlXmlTag {
+    fn name(self) -> &'static str {
+        match self {
+            KmlXmlTag::Placemark => "Placemark",
+            KmlXmlTag::Name => "name",
+            KmlXmlTag::LineString => "LineString",
+            KmlXmlTag::Coordinates => "coordinates",
+            KmlXmlTag::Track => "gx:Track",
+            KmlXmlTag::When => "when",
+            KmlXmlTag::Coord => "gx:coord",
+            KmlXmlTag::SimpleArrayData => "gx:SimpleArrayData",
+            KmlXmlTag::Value => "gx:value",
+        }
+    }
+}
+
+/// Per-point sensor data stored by `<gx:SimpleArrayData>` of `<gx:Track>`
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum KmlArray {
+    HeartRate,
+    Cadence,
+    Power,
+}
+
+struct ParserContext {
+    in_placemark: bool,
+    in_line_string: bool,
+    in_track: bool,
+    current_tag: Option<KmlXmlTag>,
+    current_track: Track,
+    current_array: Option<KmlArray>,
+    when: Vec<DateTime<Utc>>,
+    points: Vec<TrackPoint>,
+    heart_rates: Vec<Option<u8>>,
+    cadences: Vec<Option<u8>>,
+    powers: Vec<Option<u16>>,
+}
+
+impl ParserContext {
+    fn new() -> ParserContext {
+        ParserContext {
+            in_placemark: false,
+            in_line_string: false,
+            in_track: false,
+            current_tag: None,
+            current_track: Track::new(),
+            current_array: None,
+            when: Vec::new(),
+            points: Vec::new(),
+            heart_rates: Vec::new(),
+            cadences: Vec::new(),
+            powers: Vec::new(),
+        }
+    }
+}
+
+const OPENGIS_KML_SCHEMA: &str = "http://www.opengis.net/kml/2.2";
+const GOOGLE_KML_2_1_SCHEMA: &str = "http://earth.google.com/kml/2.1";
+const GOOGLE_KML_2_2_SCHEMA: &str = "http://earth.google.com/kml/2.2";
+const GOOGLE_KML_EXT_SCHEMA: &str = "http://www.google.com/kml/ext/2.2";
+
+const KML_MAPPINGS: [(&str, KmlXmlTag); 5] = [
+    ("Placemark", KmlXmlTag::Placemark),
+    ("name", KmlXmlTag::Name),
+    ("LineString", KmlXmlTag::LineString),
+    ("coordinates", KmlXmlTag::Coordinates),
+    ("when", KmlXmlTag::When),
+];
+
+const GOOGLE_KML_EXT_MAPPINGS: [(&str, KmlXmlTag); 4] = [
+    ("Track", KmlXmlTag::Track),
+    ("coord", KmlXmlTag::Coord),
+    ("SimpleArrayData", KmlXmlTag::SimpleArrayData),
+    ("value", KmlXmlTag::Value),
+];
+
+fn find_tag_in_mapping(tag: &str, mapping: &[(&'static str, KmlXmlTag)]) -> Option<KmlXmlTag> {
+    mapping
+        .iter()
+        .find(|&&(mapped_tag, _)| mapped_tag == tag)
+        .map(|&(_, value)| value)
+}
+
+fn parse_kml_xml_tag(name: &OwnedName) -> Option<KmlXmlTag> {
+    let tag = name.local_name.as_str();
+    match name.namespace.as_deref() {
+        Some(OPENGIS_KML_SCHEMA) | Some(GOOGLE_KML_2_1_SCHEMA) | Some(GOOGLE_KML_2_2_SCHEMA) => {
+            find_tag_in_mapping(tag, &KML_MAPPINGS)
+        }
+        Some(GOOGLE_KML_EXT_SCHEMA) => find_tag_in_mapping(tag, &GOOGLE_KML_EXT_MAPPINGS),
+        _ => None,
+    }
+}
+
+fn unexpected_element(tag: KmlXmlTag, location: Location) -> ParseError {
+    ParseError::UnexpectedElement {
+        element: tag.name().to_string(),
+        location,
+    }
+}
+
+/// Parses `longitude,latitude[,altitude]` tuple of `<coordinates>` or space separated
+/// `longitude latitude altitude` of `<gx:coord>`
+fn parse_coordinates<'a, I>(
+    tag: KmlXmlTag,
+    mut values: I,
+    text: &str,
+    location: Location,
+) -> Result<TrackPoint, ParseError>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut next_value = || -> Result<Option<f64>, ParseError> {
+        values
+            .next()
+            .map(|value| value.parse::<f64>())
+            .transpose()
+            .map_err(|_| invalid_value(tag.name(), text, location))
+    };
+
+    let longitude = next_value()?;
+    let latitude = next_value()?;
+    let altitude = next_value()?;
+    match (longitude, latitude) {
+        (Some(longitude), Some(latitude)) => {
+            let mut point = TrackPoint::from_coordinates(latitude, longitude);
+            point.elevation = altitude;
+            Ok(point)
+        }
+        _ => Err(invalid_value(tag.name(), text, location)),
+    }
+}
+
+fn parse_start_xml_element(
+    tag: KmlXmlTag,
+    attributes: &[OwnedAttribute],
+    location: Location,
+    context: &mut ParserContext,
+) -> Result<(), ParseError> {
+    context.current_tag = Some(tag);
+
+    match tag {
+        KmlXmlTag::Placemark => {
+            context.in_placemark = true;
+            context.current_track = Track::new();
+        }
+        KmlXmlTag::LineString => {
+            if !context.in_placemark || context.in_track {
+                return Err(unexpected_element(tag, location));
+            }
+
+            context.in_line_string = true;
+        }
+        KmlXmlTag::Track => {
+            if !context.in_placemark || context.in_line_string {
+                return Err(unexpected_element(tag, location));
+            }
+
+            context.in_track = true;
+        }
+        KmlXmlTag::Coord | KmlXmlTag::SimpleArrayData if !context.in_track => {
+            return Err(unexpected_element(tag, location));
+        }
+        KmlXmlTag::SimpleArrayData => {
+            let name = attributes
+                .iter()
+                .find(|attr| attr.name.local_name == "name")
+                .map(|attr| attr.value.to_lowercase());
+            context.current_array = match name.as_deref() {
+                Some("heartrate") | Some("heart_rate") => Some(KmlArray::HeartRate),
+                Some("cadence") => Some(KmlArray::Cadence),
+                Some("power") => Some(KmlArray::Power),
+                _ => None,
+            };
+        }
+        // Empty `<gx:value/>` keeps the place of a point without data
+        KmlXmlTag::Value => match context.current_array {
+            Some(KmlArray::HeartRate) => context.heart_rates.push(None),
+            Some(KmlArray::Cadence) => context.cadences.push(None),
+            Some(KmlArray::Power) => context.powers.push(None),
+            None => {}
+        },
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn parse_xml_characters(
+    characters: String,
+    location: Location,
+    context: &mut ParserContext,
+) -> Result<(), ParseError> {
+    let tag = match context.current_tag {
+        Some(tag) => tag,
+        None => return Ok(()),
+    };
+
+    match tag {
+        KmlXmlTag::Name if context.in_placemark && !context.in_track && !context.in_line_string => {
+            context.current_track.name = characters;
+        }
+        KmlXmlTag::Coordinates if context.in_line_string => {
+            let mut segment = TrackSegment::new();
+            for tuple in characters.split_whitespace() {
+                let point = parse_coordinates(tag, tuple.split(','), tuple, location)?;
+                segment.points.push(point);
+            }
+            if !segment.points.is_empty() {
+                context.current_track.route.push(segment);
+            }
+        }
+        // `<when>` of `<TimeStamp>` outside of tracks dates the whole placemark
+        KmlXmlTag::When if context.in_track => {
+            let time = DateTime::parse_from_rfc3339(characters.trim())
+                .map_err(|_| invalid_value(tag.name(), &characters, location))?;
+            context.when.push(DateTime::<Utc>::from(time));
+        }
+        KmlXmlTag::Coord => {
+            let point = parse_coordinates(tag, characters.split_whitespace(), &characters, location)?;
+            context.points.push(point);
+        }
+        KmlXmlTag::Value => {
+            let value = characters.trim();
+            let invalid = || invalid_value(tag.name(), value, location);
+            match context.current_array {
+                Some(KmlArray::HeartRate) => {
+                    let parsed = value.parse().map_err(|_| invalid())?;
+                    context.heart_rates.pop();
+                    context.heart_rates.push(Some(parsed));
+                }
+                Some(KmlArray::Cadence) => {
+                    let parsed = value.parse().map_err(|_| invalid())?;
+                    context.cadences.pop();
+                    context.cadences.push(Some(parsed));
+                }
+                Some(KmlArray::Power) => {
+                    let parsed = value.parse().map_err(|_| invalid())?;
+                    context.powers.pop();
+                    context.powers.push(Some(parsed));
+                }
+                None => {}
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Turns parallel lists of `<gx:Track>` into a track segment
+fn finish_gx_track(location: Location, context: &mut ParserContext) -> Result<(), ParseError> {
+    let when = mem::take(&mut context.when);
+    let mut points = mem::take(&mut context.points);
+    let heart_rates = mem::take(&mut context.heart_rates);
+    let cadences = mem::take(&mut context.cadences);
+    let powers = mem::take(&mut context.powers);
+
+    // Track without any `<when>` is untimed, otherwise every coordinate needs its time
+    if !when.is_empty() && when.len() != points.len() {
+        let counts = format!("{} <when> for {} <gx:coord>", when.len(), points.len());
+        return Err(invalid_value(KmlXmlTag::Track.name(), &counts, location));
+    }
+
+    for (i, point) in points.iter_mut().enumerate() {
+        point.time = when.get(i).copied();
+        point.heart_rate = heart_rates.get(i).copied().flatten();
+        point.cadence = cadences.get(i).copied().flatten();
+        point.power = powers.get(i).copied().flatten();
+    }
+
+    if !points.is_empty() {
+        context.current_track.route.push(TrackSegment { points });
+    }
+    Ok(())
+}
+
+fn parse_end_xml_element(
+    tag: KmlXmlTag,
+    location: Location,
+    tracks: &mut Vec<Track>,
+    context: &mut ParserContext,
+) -> Result<(), ParseError> {
+    context.current_tag = None;
+
+    match tag {
+        KmlXmlTag::Placemark => {
+            context.in_placemark = false;
+            // Placemarks of single points or polygons carry no track
+            let track = mem::take(&mut context.current_track);
+            if !track.route.is_empty() {
+                tracks.push(track);
+            }
+        }
+        KmlXmlTag::LineString => context.in_line_string = false,
+        KmlXmlTag::Track => {
+            context.in_track = false;
+            finish_gx_track(location, context)?;
+        }
+        KmlXmlTag::SimpleArrayData => context.current_array = None,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn read_kml_tracks_from<R: Read>(reader: BufReader<R>) -> Result<Vec<Track>, ParseError> {
+    let mut parser = EventReader::new(reader);
+    let mut tracks = Vec::new();
+    let mut context = ParserContext::new();
+
+    loop {
+        let event = parser.next();
+        let location = to_location(parser.position());
+
+        match event {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                if let Some(tag) = parse_kml_xml_tag(&name) {
+                    parse_start_xml_element(tag, &attributes, location, &mut context)?;
+                }
+            }
+            Ok(XmlEvent::EndElement { name }) => {
+                if let Some(tag) = parse_kml_xml_tag(&name) {
+                    parse_end_xml_element(tag, location, &mut tracks, &mut context)?;
+                }
+            }
+            Ok(XmlEvent::Characters(characters)) => {
+                parse_xml_characters(characters, location, &mut context)?;
+            }
+            Ok(XmlEvent::EndDocument) => break,
+            Err(err) => return Err(convert_xml_error(err)),
+            _ => {}
+        }
+    }
+
+    for track in &mut tracks {
+        track.sort_points_by_time();
+    }
+    Ok(tracks)
+}
+
+/// Parses KML document. Each placemark with `<gx:Track>` or `<LineString>` geometry becomes
+/// a track, each of those geometries becomes a track segment. Points of `<LineString>`
+/// have no time.
+pub fn read_kml_tracks_from_reader<R: Read>(reader: R) -> Result<Vec<Track>, ParseError> {
+    read_kml_tracks_from(BufReader::new(reader))
+}
+
+pub fn read_kml_tracks_from_str(kml: &str) -> Result<Vec<Track>, ParseError> {
+    read_kml_tracks_from_reader(kml.as_bytes())
+}
+
+/// Parses the first KML document of KMZ archive, usually `doc.kml`
+pub fn read_kmz_tracks_from_reader<R: Read + Seek>(reader: R) -> Result<Vec<Track>, ParseError> {
+    let mut archive = ZipArchive::new(reader)?;
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        if entry.is_file() && entry.name().to_lowercase().ends_with(".kml") {
+            return read_kml_tracks_from_reader(entry);
+        }
+    }
+
+    Err(ParseError::InvalidArchive(String::from("archive contains no KML files")))
+}
+
+/// Parses KML or KMZ file, whichever the contents are
+pub fn read_kml_tracks(path: &str) -> Result<Vec<Track>, ParseError> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    match detect_compression(reader.fill_buf()?) {
+        Compression::Zip => read_kmz_tracks_from_reader(reader),
+        _ => read_kml_tracks_from(reader),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Cursor, Write};
+    use std::time::Duration;
+
+    use test_case::test_case;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    const GX_TRACK_KML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">
+  <Document>
+    <name>Export</name>
+    <Placemark>
+      <name>Evening run</name>
+      <gx:MultiTrack>
+        <gx:Track>
+          <when>2020-04-22T16:01:58Z</when>
+          <when>2020-04-22T16:02:04Z</when>
+          <gx:coord>15.1583540 10.1025420 478.2</gx:coord>
+          <gx:coord>15.1583542 10.1026420 480.3</gx:coord>
+          <ExtendedData>
+            <SchemaData schemaUrl=\"#schema\">
+              <gx:SimpleArrayData name=\"heartrate\">
+                <gx:value>95</gx:value>
+                <gx:value/>
+              </gx:SimpleArrayData>
+              <gx:SimpleArrayData name=\"cadence\">
+                <gx:value>79</gx:value>
+                <gx:value>80</gx:value>
+              </gx:SimpleArrayData>
+              <gx:SimpleArrayData name=\"speed\">
+                <gx:value>2.5</gx:value>
+                <gx:value>2.6</gx:value>
+              </gx:SimpleArrayData>
+            </SchemaData>
+          </ExtendedData>
+        </gx:Track>
+        <gx:Track>
+          <when>2020-04-22T16:03:00Z</when>
+          <gx:coord>15.1583544 10.1027420</gx:coord>
+        </gx:Track>
+      </gx:MultiTrack>
+    </Placemark>
+    <Placemark>
+      <name>Start</name>
+      <Point><coordinates>15.1583540,10.1025420,0</coordinates></Point>
+    </Placemark>
+  </Document>
+</kml>";
+
+    const LINE_STRING_KML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<kml xmlns=\"http://earth.google.com/kml/2.1\">
+  <Placemark>
+    <name>Planned route</name>
+    <MultiGeometry>
+      <LineString>
+        <coordinates>
+          15.1583540,10.1025420,478.2
+          15.1583542,10.1026420,478.2
+          15.1583544,10.1027420,478.2
+        </coordinates>
+      </LineString>
+      <LineString>
+        <coordinates>15.1583544,10.1027420 15.1583546,10.1028420</coordinates>
+      </LineString>
+    </MultiGeometry>
+  </Placemark>
+</kml>";
+
+    #[test_case("Placemark", KmlXmlTag::Placemark; "Placemark tag")]
+    #[test_case("name", KmlXmlTag::Name; "name tag")]
+    #[test_case("LineString", KmlXmlTag::LineString; "LineString tag")]
+    #[test_case("coordinates", KmlXmlTag::Coordinates; "coordinates tag")]
+    #[test_case("when", KmlXmlTag::When; "when tag")]
+    fn test_kml_mapping(tag: &str, expected: KmlXmlTag) {
+        for namespace in &[OPENGIS_KML_SCHEMA, GOOGLE_KML_2_1_SCHEMA, GOOGLE_KML_2_2_SCHEMA] {
+            let name = OwnedName {
+                local_name: String::from(tag),
+                namespace: Some(String::from(*namespace)),
+                prefix: None,
+            };
+
+            let parsed = parse_kml_xml_tag(&name);
+            assert_eq!(parsed, Some(expected));
+        }
+    }
+
+    #[test_case("Track", KmlXmlTag::Track; "Track tag")]
+    #[test_case("coord", KmlXmlTag::Coord; "coord tag")]
+    #[test_case("SimpleArrayData", KmlXmlTag::SimpleArrayData; "SimpleArrayData tag")]
+    #[test_case("value", KmlXmlTag::Value; "value tag")]
+    fn test_google_kml_ext_mapping(tag: &str, expected: KmlXmlTag) {
+        let name = OwnedName {
+            local_name: String::from(tag),
+            namespace: Some(String::from(GOOGLE_KML_EXT_SCHEMA)),
+            prefix: None,
+        };
+
+        let parsed = parse_kml_xml_tag(&name);
+        assert_eq!(parsed, Some(expected));
+    }
+
+    #[test]
+    fn test_parsing_gx_track() {
+        let tracks = read_kml_tracks_from_str(GX_TRACK_KML).unwrap();
+        assert_eq!(tracks.len(), 1);
+
+        let track = &tracks[0];
+        assert_eq!(track.name, "Evening run");
+        assert_eq!(track.route.len(), 2);
+        assert_eq!(track.route[0].points.len(), 2);
+        assert_eq!(track.route[1].points.len(), 1);
+
+        let point = &track.route[0].points[0];
+        assert_eq!(point.latitude, 10.1025420);
+        assert_eq!(point.longitude, 15.1583540);
+        assert_eq!(point.elevation, Some(478.2));
+        assert_eq!(point.time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58)));
+        assert_eq!(point.heart_rate, Some(95));
+        assert_eq!(point.cadence, Some(79));
+
+        let point = &track.route[0].points[1];
+        assert_eq!(point.time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 2, 4)));
+        assert_eq!(point.heart_rate, None);
+        assert_eq!(point.cadence, Some(80));
+
+        let point = &track.route[1].points[0];
+        assert_eq!(point.elevation, None);
+        assert_eq!(point.time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 3, 0)));
+        assert_eq!(point.heart_rate, None);
+
+        assert_eq!(track.duration(), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn test_parsing_line_string() {
+        let tracks = read_kml_tracks_from_str(LINE_STRING_KML).unwrap();
+        assert_eq!(tracks.len(), 1);
+
+        let track = &tracks[0];
+        assert_eq!(track.name, "Planned route");
+        assert_eq!(track.route.len(), 2);
+        assert_eq!(track.route[0].points.len(), 3);
+        assert_eq!(track.route[1].points.len(), 2);
+        assert!(track
+            .route
+            .iter()
+            .flat_map(|segment| segment.points.iter())
+            .all(|point| point.time.is_none()));
+        assert_eq!(track.route[0].points[0].elevation, Some(478.2));
+        assert_eq!(track.route[1].points[0].elevation, None);
+
+        assert_eq!(track.distance(), 33);
+        assert_eq!(track.duration(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_parsing_gx_track_with_mismatching_when() {
+        let kml_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">
+  <Placemark>
+    <gx:Track>
+      <when>2020-04-22T16:01:58Z</when>
+      <gx:coord>15.1583540 10.1025420 478.2</gx:coord>
+      <gx:coord>15.1583542 10.1026420 480.3</gx:coord>
+    </gx:Track>
+  </Placemark>
+</kml>";
+
+        match read_kml_tracks_from_str(kml_str) {
+            Err(ParseError::InvalidValue { field, value, .. }) => {
+                assert_eq!(field, "gx:Track");
+                assert_eq!(value, "1 <when> for 2 <gx:coord>");
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test_case("<LineString><coordinates>15.15,north</coordinates></LineString>", "coordinates", "15.15,north"; "bad latitude")]
+    #[test_case("<LineString><coordinates>15.15</coordinates></LineString>", "coordinates", "15.15"; "missing latitude")]
+    #[test_case("<gx:Track><gx:coord>15.15</gx:coord></gx:Track>", "gx:coord", "15.15"; "short coord")]
+    #[test_case("<gx:Track><when>today</when></gx:Track>", "when", "today"; "bad when")]
+    fn test_parsing_invalid_value(geometry: &str, expected_field: &str, expected_value: &str) {
+        let kml_str = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">
+  <Placemark>{}</Placemark>
+</kml>",
+            geometry
+        );
+
+        match read_kml_tracks_from_str(&kml_str) {
+            Err(ParseError::InvalidValue { field, value, .. }) => {
+                assert_eq!(field, expected_field);
+                assert_eq!(value, expected_value);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parsing_line_string_outside_placemark() {
+        let kml_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<kml xmlns=\"http://www.opengis.net/kml/2.2\">
+  <LineString><coordinates>15.15,10.10</coordinates></LineString>
+</kml>";
+
+        match read_kml_tracks_from_str(kml_str) {
+            Err(ParseError::UnexpectedElement { element, location }) => {
+                assert_eq!(element, "LineString");
+                assert_eq!(location, Location { line: 3, column: 3 });
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reading_kmz() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("files/icon.png", FileOptions::default()).unwrap();
+        writer.write_all(b"not an image").unwrap();
+        writer.start_file("doc.kml", FileOptions::default()).unwrap();
+        writer.write_all(LINE_STRING_KML.as_bytes()).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        let tracks = read_kmz_tracks_from_reader(Cursor::new(data)).unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].name, "Planned route");
+    }
+
+    #[test]
+    fn test_reading_kmz_without_kml() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("notes.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"nothing here").unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        let result = read_kmz_tracks_from_reader(Cursor::new(data));
+        assert!(matches!(result, Err(ParseError::InvalidArchive(_))));
+    }
+
+    #[test]
+    fn test_reading_missing_kml_file() {
+        let result = read_kml_tracks("/nonexistent/path/to/track.kml");
+        assert!(matches!(result, Err(ParseError::Io(_))));
+    }
+}
@@ -13,25 +13,132 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
+mod archive;
+mod csv_parser;
+mod csv_writer;
+mod fit_parser;
 mod gpx_parser;
+mod gpx_writer;
+mod igc_parser;
+mod kml_parser;
+mod nmea_parser;
 mod formatter;
+#[cfg(feature = "geojson")]
+mod geojson;
+#[cfg(feature = "serde")]
+mod serialization;
+mod speed;
 mod stats;
+mod tcx_parser;
+mod tcx_writer;
 
+pub use archive::read_gpx_entries;
+pub use archive::read_gpx_entries_from_reader;
+pub use archive::GpxEntry;
+pub use csv_parser::read_csv;
+pub use csv_parser::read_csv_from_reader;
+pub use csv_parser::read_csv_from_str;
+pub use csv_parser::CsvColumn;
+pub use csv_parser::CsvOptions;
+pub use csv_parser::CsvTimeFormat;
+pub use csv_writer::write_csv;
+pub use csv_writer::write_csv_file;
+pub use fit_parser::read_fit;
+pub use fit_parser::read_fit_from_bytes;
+pub use fit_parser::read_fit_from_reader;
+#[cfg(feature = "geojson")]
+pub use geojson::read_geojson_tracks;
+#[cfg(feature = "geojson")]
+pub use geojson::read_geojson_tracks_from_reader;
+#[cfg(feature = "geojson")]
+pub use geojson::read_geojson_tracks_from_str;
+#[cfg(feature = "geojson")]
+pub use geojson::tracks_to_geojson;
+#[cfg(feature = "geojson")]
+pub use geojson::write_geojson;
 pub use gpx_parser::read_gpx;
+pub use gpx_parser::read_gpx_document;
+pub use gpx_parser::read_gpx_document_from_bytes;
+pub use gpx_parser::read_gpx_document_from_reader;
+pub use gpx_parser::read_gpx_document_from_reader_with_options;
+pub use gpx_parser::read_gpx_document_from_str;
+pub use gpx_parser::read_gpx_from_bytes;
+pub use gpx_parser::read_gpx_from_reader;
+pub use gpx_parser::read_gpx_from_reader_with_options;
+pub use gpx_parser::read_gpx_from_str;
+pub use gpx_parser::read_gpx_with_options;
+pub use gpx_parser::GpxPointReader;
+pub use gpx_parser::SegmentPoint;
+pub use gpx_writer::write_gpx;
+pub use gpx_writer::write_gpx_file;
+pub use gpx_writer::GpxWriter;
+pub use tcx_parser::read_tcx;
+pub use tcx_parser::read_tcx_activities;
+pub use tcx_parser::read_tcx_activities_from_reader;
+pub use tcx_parser::read_tcx_from_bytes;
+pub use tcx_parser::read_tcx_from_reader;
+pub use tcx_parser::read_tcx_from_str;
+pub use tcx_writer::write_tcx;
+pub use tcx_writer::write_tcx_file;
+pub use tcx_writer::TcxLaps;
+pub use igc_parser::read_igc;
+pub use igc_parser::read_igc_from_reader;
+pub use igc_parser::read_igc_from_str;
+pub use igc_parser::IgcAltitude;
+pub use kml_parser::read_kml_tracks;
+pub use kml_parser::read_kml_tracks_from_reader;
+pub use kml_parser::read_kml_tracks_from_str;
+pub use kml_parser::read_kmz_tracks_from_reader;
+pub use nmea_parser::read_nmea;
+pub use nmea_parser::read_nmea_from_reader;
+pub use nmea_parser::read_nmea_from_reader_with_options;
+pub use nmea_parser::read_nmea_from_str;
 pub use formatter::format_time;
 pub use formatter::format_duration;
 
 use chrono::{DateTime, Utc};
+use std::error::Error;
+use std::fmt;
+use std::io;
 use std::time::Duration;
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TrackPoint {
     pub latitude: f64,
     pub longitude: f64,
-    pub elevation: f64,
-    pub time: DateTime<Utc>,
-    pub heart_rate: u8,
-    pub cadence: u8,
+    /// Meters above the sea level
+    pub elevation: Option<f64>,
+    /// Empty for untimed inputs, e.g. KML `<LineString>`
+    pub time: Option<DateTime<Utc>>,
+    /// Beats per minute
+    pub heart_rate: Option<u8>,
+    /// Revolutions per minute
+    pub cadence: Option<u8>,
+    /// Speed reported by the device, meters per second
+    pub speed: Option<f64>,
+    /// Heading reported by the device, degrees clockwise from true north
+    pub course: Option<f64>,
+    /// Degrees Celsius
+    pub air_temperature: Option<f64>,
+    /// Degrees Celsius
+    pub water_temperature: Option<f64>,
+    /// Meters below the water surface
+    pub depth: Option<f64>,
+    /// Watts
+    pub power: Option<u16>,
+    /// Distance covered since the start of the activity as reported by the device, meters
+    pub distance: Option<f64>,
+    /// Horizontal dilution of precision of the fix, lower is better
+    pub hdop: Option<f64>,
+    /// Number of satellites used for the fix
+    pub satellites: Option<u8>,
+}
+
+impl Default for TrackPoint {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TrackPoint {
@@ -39,10 +146,19 @@ impl TrackPoint {
         TrackPoint {
             latitude: 0.0,
             longitude: 0.0,
-            elevation: 0.0,
-            time: Utc::now(),
-            heart_rate: 0,
-            cadence: 0,
+            elevation: None,
+            time: None,
+            heart_rate: None,
+            cadence: None,
+            speed: None,
+            course: None,
+            air_temperature: None,
+            water_temperature: None,
+            depth: None,
+            power: None,
+            distance: None,
+            hdop: None,
+            satellites: None,
         }
     }
 
@@ -50,30 +166,148 @@ impl TrackPoint {
         TrackPoint {
             latitude,
             longitude,
-            elevation: 0.0,
-            time: Utc::now(),
-            heart_rate: 0,
-            cadence: 0,
+            elevation: None,
+            time: None,
+            heart_rate: None,
+            cadence: None,
+            speed: None,
+            course: None,
+            air_temperature: None,
+            water_temperature: None,
+            depth: None,
+            power: None,
+            distance: None,
+            hdop: None,
+            satellites: None,
         }
     }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TrackSegment {
     pub points: Vec<TrackPoint>,
 }
 
+impl Default for TrackSegment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TrackSegment {
     pub fn new() ->TrackSegment {
         TrackSegment { points: Vec::new() }
     }
+
+    /// Orders points by time. Segment with untimed points is left as it is.
+    pub(crate) fn sort_by_time(&mut self) {
+        if self.points.iter().all(|point| point.time.is_some()) {
+            self.points.sort_by_key(|point| point.time);
+        }
+    }
+}
+
+/// Sport the track was recorded for. Statistics worth showing depend on it, e.g. pace
+/// for running and speed for cycling.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ActivityType {
+    Running,
+    Cycling,
+    Hiking,
+    Walking,
+    Swimming,
+    Skiing,
+    /// Type which isn't recognized, kept as written in the input
+    Other(String),
+}
+
+impl ActivityType {
+    /// Interprets the type as written by devices and services, e.g. `trail_running` of Garmin,
+    /// `Ride` of Strava or `Biking` of TCX. Case, spaces and dashes don't matter.
+    pub fn from_name(name: &str) -> ActivityType {
+        let name = name.trim();
+        let normalized = name.to_lowercase().replace([' ', '-'], "_");
+        match normalized.as_str() {
+            // Older Strava exports put numeric codes into GPX `<type>`
+            "running" | "run" | "trail_running" | "treadmill_running" | "track_running"
+            | "street_running" | "indoor_running" | "virtual_run" | "virtualrun" | "9" => {
+                ActivityType::Running
+            }
+            "cycling" | "biking" | "ride" | "road_biking" | "mountain_biking" | "gravel_cycling"
+            | "indoor_cycling" | "cyclocross" | "virtual_ride" | "virtualride" | "1" => {
+                ActivityType::Cycling
+            }
+            "hiking" | "hike" => ActivityType::Hiking,
+            "walking" | "walk" => ActivityType::Walking,
+            "swimming" | "swim" | "open_water_swimming" | "lap_swimming" => ActivityType::Swimming,
+            "skiing" | "cross_country_skiing" | "backcountry_skiing" | "resort_skiing"
+            | "alpine_skiing" | "nordic_ski" | "nordicski" | "alpine_ski" | "alpineski"
+            | "backcountry_ski" | "backcountryski" => ActivityType::Skiing,
+            _ => ActivityType::Other(name.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ActivityType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ActivityType::Running => write!(f, "running"),
+            ActivityType::Cycling => write!(f, "cycling"),
+            ActivityType::Hiking => write!(f, "hiking"),
+            ActivityType::Walking => write!(f, "walking"),
+            ActivityType::Swimming => write!(f, "swimming"),
+            ActivityType::Skiing => write!(f, "skiing"),
+            ActivityType::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Link to an external resource, e.g. GPX `<link>`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Link {
+    pub href: String,
+    pub text: Option<String>,
+    /// MIME type of the content, e.g. `image/jpeg`
+    pub mime_type: Option<String>,
+}
+
+impl Link {
+    pub fn new(href: &str) -> Link {
+        Link {
+            href: href.to_string(),
+            text: None,
+            mime_type: None,
+        }
+    }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Track {
     pub name: String,
     pub creation_time: Option<DateTime<Utc>>,
     pub route: Vec<TrackSegment>,
+    /// Laps recorded by the device. Empty for formats without laps, e.g. GPX.
+    pub laps: Vec<Lap>,
+    pub activity_type: Option<ActivityType>,
+    pub description: Option<String>,
+    pub comment: Option<String>,
+    /// Where the data came from, e.g. GPX `<src>`
+    pub source: Option<String>,
+    pub links: Vec<Link>,
+    /// Position of the track within the document as numbered by its author
+    pub number: Option<u32>,
+    /// Device or application which recorded the track, e.g. GPX `creator` attribute
+    pub device: Option<String>,
+}
+
+impl Default for Track {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Track {
@@ -82,6 +316,22 @@ impl Track {
             name: String::new(),
             creation_time: None,
             route: Vec::new(),
+            laps: Vec::new(),
+            activity_type: None,
+            description: None,
+            comment: None,
+            source: None,
+            links: Vec::new(),
+            number: None,
+            device: None,
+        }
+    }
+
+    /// Readers of formats without guaranteed point order use it, so statistics never see
+    /// time going backwards
+    pub(crate) fn sort_points_by_time(&mut self) {
+        for segment in &mut self.route {
+            segment.sort_by_time();
         }
     }
 
@@ -93,32 +343,559 @@ impl Track {
         stats::calc_track_duration(self)
     }
 
-    pub fn avg_heart_rate(&self) -> u8 {
-        stats::calc_track_average_heart_rate(&self)
+    /// Duration without pauses detected with default `PauseOptions`
+    pub fn moving_time(&self) -> Duration {
+        self.moving_time_with_options(&PauseOptions::new())
+    }
+
+    pub fn moving_time_with_options(&self, options: &PauseOptions) -> Duration {
+        stats::calc_track_moving_time(self, options)
+    }
+
+    /// Periods of standing still detected with default `PauseOptions`
+    pub fn pauses(&self) -> Vec<Pause> {
+        self.pauses_with_options(&PauseOptions::new())
+    }
+
+    pub fn pauses_with_options(&self, options: &PauseOptions) -> Vec<Pause> {
+        stats::calc_track_pauses(self, options)
+    }
+
+    pub fn avg_heart_rate(&self) -> Option<u8> {
+        stats::calc_track_average_heart_rate(self)
+    }
+
+    pub fn avg_power(&self) -> Option<u16> {
+        stats::calc_track_average_power(self)
+    }
+
+    pub fn max_power(&self) -> Option<u16> {
+        stats::calc_track_max_power(self)
+    }
+
+    /// Speed of every point since the previous one, meters per second, grouped by segment
+    pub fn point_speeds(&self) -> Vec<Vec<Option<f64>>> {
+        speed::calc_track_point_speeds(self)
+    }
+
+    /// Speed of every point averaged over the `window` before it, grouped by segment
+    pub fn smoothed_speeds(&self, window: Duration) -> Vec<Vec<Option<f64>>> {
+        speed::calc_track_smoothed_speeds(self, window)
     }
 
+    pub fn speed_stats(&self) -> Option<SpeedStats> {
+        self.speed_stats_with_options(&SpeedOptions::new())
+    }
+
+    pub fn speed_stats_with_options(&self, options: &SpeedOptions) -> Option<SpeedStats> {
+        speed::calc_track_speed_stats(self, options)
+    }
+
+    /// Kilometer splits
     pub fn splits(&self) -> Vec<Split> {
-        stats::calc_track_splits(self)
+        self.splits_with_options(&SplitOptions::new())
+    }
+
+    pub fn splits_with_options(&self, options: &SplitOptions) -> Vec<Split> {
+        stats::calc_track_splits(self, options)
     }
 
-    pub fn elevation_stats(&self) -> ElevationStats {
+    /// Kilometer splits which count moving time only, so stops don't slow down the pace
+    pub fn moving_splits(&self, options: &PauseOptions) -> Vec<Split> {
+        self.splits_with_options(&SplitOptions {
+            pauses: Some(*options),
+            ..SplitOptions::new()
+        })
+    }
+
+    pub fn elevation_stats(&self) -> Option<ElevationStats> {
         stats::calc_track_elevation_stats(self)
     }
+
+    /// GeoJSON `FeatureCollection` holding this track only
+    #[cfg(feature = "geojson")]
+    pub fn to_geojson(&self) -> String {
+        geojson::tracks_to_geojson(std::slice::from_ref(self))
+    }
+}
+
+/// Lap as recorded by the device, e.g. TCX `<Lap>`. Points of the lap are kept in the
+/// track segments `first_segment..first_segment + segment_count`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lap {
+    pub start_time: DateTime<Utc>,
+    #[cfg_attr(feature = "serde", serde(with = "serialization::duration_seconds"))]
+    pub total_time: Duration,
+    /// Meters
+    pub distance: f64,
+    pub calories: Option<u16>,
+    /// Beats per minute
+    pub avg_heart_rate: Option<u8>,
+    /// Beats per minute
+    pub max_heart_rate: Option<u8>,
+    pub first_segment: usize,
+    pub segment_count: usize,
+}
+
+impl Lap {
+    pub fn new(start_time: DateTime<Utc>) -> Lap {
+        Lap {
+            start_time,
+            total_time: Duration::from_secs(0),
+            distance: 0.0,
+            calories: None,
+            avg_heart_rate: None,
+            max_heart_rate: None,
+            first_segment: 0,
+            segment_count: 0,
+        }
+    }
+}
+
+/// Single named location, e.g. an aid station or a route point
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Waypoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub elevation: Option<f64>,
+    pub time: Option<DateTime<Utc>>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub symbol: Option<String>,
+}
+
+impl Waypoint {
+    pub fn new(latitude: f64, longitude: f64) -> Waypoint {
+        Waypoint {
+            latitude,
+            longitude,
+            elevation: None,
+            time: None,
+            name: None,
+            description: None,
+            symbol: None,
+        }
+    }
+}
+
+/// Planned course as ordered list of waypoints
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Route {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub points: Vec<Waypoint>,
+}
+
+impl Default for Route {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Route {
+    pub fn new() -> Route {
+        Route {
+            name: None,
+            description: None,
+            points: Vec::new(),
+        }
+    }
+}
+
+/// Person or organization, e.g. author of GPX document
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Person {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub link: Option<Link>,
+}
+
+/// Copyright holder and license of the document
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Copyright {
+    pub author: String,
+    pub year: Option<i32>,
+    /// Link to the license text
+    pub license: Option<String>,
+}
+
+/// Area covered by the document, degrees
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bounds {
+    pub min_latitude: f64,
+    pub min_longitude: f64,
+    pub max_latitude: f64,
+    pub max_longitude: f64,
+}
+
+/// Document-wide information from GPX `<metadata>`
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub time: Option<DateTime<Utc>>,
+    pub description: Option<String>,
+    pub author: Option<Person>,
+    pub copyright: Option<Copyright>,
+    pub links: Vec<Link>,
+    pub keywords: Vec<String>,
+    pub bounds: Option<Bounds>,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metadata {
+    pub fn new() -> Metadata {
+        Metadata {
+            name: None,
+            time: None,
+            description: None,
+            author: None,
+            copyright: None,
+            links: Vec::new(),
+            keywords: Vec::new(),
+            bounds: None,
+        }
+    }
 }
 
+/// Whole GPX document. Each `<trk>` element becomes a separate track.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Gpx {
+    /// Device or application which wrote the document
+    pub creator: Option<String>,
+    pub metadata: Metadata,
+    pub waypoints: Vec<Waypoint>,
+    pub routes: Vec<Route>,
+    pub tracks: Vec<Track>,
+}
+
+impl Default for Gpx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gpx {
+    pub fn new() -> Gpx {
+        Gpx {
+            creator: None,
+            metadata: Metadata::new(),
+            waypoints: Vec::new(),
+            routes: Vec::new(),
+            tracks: Vec::new(),
+        }
+    }
+}
+
+/// Unit of split paces
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnitSystem {
+    /// Pace per kilometer
+    Metric,
+    /// Pace per mile
+    Imperial,
+}
+
+impl UnitSystem {
+    /// Meters in a kilometer or in a mile
+    pub fn meters(self) -> f64 {
+        match self {
+            UnitSystem::Metric => 1000.0,
+            UnitSystem::Imperial => 1609.344,
+        }
+    }
+
+    /// Short name of the distance unit
+    pub fn name(self) -> &'static str {
+        match self {
+            UnitSystem::Metric => "km",
+            UnitSystem::Imperial => "mi",
+        }
+    }
+}
+
+/// How the track is divided into splits
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SplitOptions {
+    /// Length of a split, meters. Non-positive length gives no splits.
+    pub distance: f64,
+    pub units: UnitSystem,
+    /// The last split shorter than the full length is kept only if it's at least this long, meters
+    pub min_partial_distance: f64,
+    /// Count moving time only, leaving out pauses
+    pub pauses: Option<PauseOptions>,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SplitOptions {
+    /// Kilometer splits, the last one counts from 100 meters
+    pub fn new() -> SplitOptions {
+        SplitOptions {
+            distance: 1000.0,
+            units: UnitSystem::Metric,
+            min_partial_distance: 100.0,
+            pauses: None,
+        }
+    }
+
+    /// Mile splits, the last one counts from a tenth of a mile
+    pub fn miles() -> SplitOptions {
+        let mile = UnitSystem::Imperial.meters();
+        SplitOptions {
+            distance: mile,
+            units: UnitSystem::Imperial,
+            min_partial_distance: mile / 10.0,
+            pauses: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Split {
-    pub distance: u16,
+    /// Distance from the start of the track to the start of the split, meters
+    pub start_distance: f64,
+    /// Length of the split, meters. The last split may be shorter than requested.
+    pub distance: f64,
+    /// Distance from the start of the track to the end of the split, meters
+    pub total_distance: f64,
+    #[cfg_attr(feature = "serde", serde(with = "serialization::duration_seconds"))]
+    pub duration: Duration,
+    /// Seconds per kilometer or mile, depending on `SplitOptions::units`. Pace of a short
+    /// last split is extrapolated to the whole unit.
     pub pace: u64,
-    pub elevation_delta: i16,
+    /// Meters climbed (or descended if negative) within the split, if elevation is known
+    pub elevation_delta: Option<i16>,
+}
+
+/// Thresholds telling standing still from moving
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PauseOptions {
+    /// Stretch between two points covered slower than this is a pause, meters per second
+    pub min_speed: f64,
+    /// Stretch between two points taking longer than this is a pause whatever its speed,
+    /// e.g. the device was paused and resumed elsewhere
+    #[cfg_attr(feature = "serde", serde(with = "serialization::duration_seconds"))]
+    pub max_gap: Duration,
+}
+
+impl Default for PauseOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PauseOptions {
+    /// Slower than 0.5 m/s or longer than a minute between points
+    pub fn new() -> PauseOptions {
+        PauseOptions {
+            min_speed: 0.5,
+            max_gap: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Period when the athlete stood still within a track segment
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pause {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Position where the pause started
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Pause {
+    pub fn duration(&self) -> Duration {
+        self.end
+            .signed_duration_since(self.start)
+            .to_std()
+            .unwrap_or_default()
+    }
+}
+
+/// Parameters of speed statistics
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpeedOptions {
+    /// Max speed is the highest average speed kept for at least this long
+    #[cfg_attr(feature = "serde", serde(with = "serialization::duration_seconds"))]
+    pub max_speed_window: Duration,
+    /// Tells stops excluded from the moving average speed
+    pub pauses: PauseOptions,
+}
+
+impl Default for SpeedOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpeedOptions {
+    /// Max speed over 10 seconds, default `PauseOptions`
+    pub fn new() -> SpeedOptions {
+        SpeedOptions {
+            max_speed_window: Duration::from_secs(10),
+            pauses: PauseOptions::new(),
+        }
+    }
+}
+
+/// Speeds in meters per second
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpeedStats {
+    /// Distance over the whole duration
+    pub average: f64,
+    /// Distance covered while moving over the moving time
+    pub moving_average: f64,
+    /// Highest speed kept for `SpeedOptions::max_speed_window`, single-point GPS spikes
+    /// left out. Empty if the track is shorter than the window.
+    pub max: Option<f64>,
+}
+
+/// Position inside a parsed text document. Both line and column count from 1.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Location {
+    pub line: u64,
+    pub column: u64,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
 #[derive(Debug)]
 pub enum ParseError {
-    XmlError,
+    /// Input couldn't be opened or read
+    Io(io::Error),
+    /// Input is not a well-formed document
+    Syntax { location: Location, message: String },
+    /// Element appears where it isn't allowed, e.g. `trkpt` outside of `trkseg`
+    UnexpectedElement { element: String, location: Location },
+    /// Element lacks a mandatory attribute, e.g. `lat` of `trkpt`
+    MissingAttribute {
+        element: String,
+        attribute: String,
+        location: Location,
+    },
+    /// Element or attribute value can't be interpreted
+    InvalidValue {
+        field: String,
+        value: String,
+        location: Location,
+    },
+    /// Compressed input is damaged or has no suitable content
+    InvalidArchive(String),
+    /// Binary input is damaged, e.g. checksum mismatch or truncated record
+    InvalidData { offset: u64, message: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(err) => write!(f, "I/O error: {}", err),
+            ParseError::Syntax { location, message } => {
+                write!(f, "{}: syntax error: {}", location, message)
+            }
+            ParseError::UnexpectedElement { element, location } => {
+                write!(f, "{}: unexpected element <{}>", location, element)
+            }
+            ParseError::MissingAttribute {
+                element,
+                attribute,
+                location,
+            } => write!(
+                f,
+                "{}: element <{}> misses attribute \"{}\"",
+                location, element, attribute
+            ),
+            ParseError::InvalidValue {
+                field,
+                value,
+                location,
+            } => write!(f, "{}: invalid value of {}: \"{}\"", location, field, value),
+            ParseError::InvalidArchive(message) => write!(f, "invalid archive: {}", message),
+            ParseError::InvalidData { offset, message } => {
+                write!(f, "byte {}: invalid data: {}", offset, message)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> Self {
+        ParseError::Io(err)
+    }
+}
+
+/// Controls how strictly input is checked
+#[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseOptions {
+    /// Skip or repair malformed points instead of failing the whole input
+    pub lenient: bool,
+}
+
+impl ParseOptions {
+    pub fn new() -> ParseOptions {
+        ParseOptions { lenient: false }
+    }
+
+    pub fn lenient() -> ParseOptions {
+        ParseOptions { lenient: true }
+    }
+}
+
+/// Problem found and worked around in lenient mode
+#[derive(Debug)]
+pub struct ParseWarning {
+    /// Zero-based index of the point in the input, counting skipped points too
+    pub point_index: usize,
+    pub error: ParseError,
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "point #{}: {}", self.point_index, self.error)
+    }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ElevationStats {
     pub max_elevation: i16,
     pub min_elevation: i16,
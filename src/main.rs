@@ -15,41 +15,178 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use std::env;
 use std::fs;
-
+use std::path::Path;
 use std::process;
 
+const USAGE: &str = "Usage: runstats [--miles] [--split <length in km or mi>] <file>";
+
+/// Command line arguments. Options may go before or after the path.
+struct Arguments {
+    path: String,
+    split_options: runstats::SplitOptions,
+}
+
+fn parse_arguments(args: &[String]) -> Result<Arguments, String> {
+    let mut path = None;
+    let mut miles = false;
+    let mut split_length: Option<f64> = None;
+
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--miles" => miles = true,
+            "--split" => {
+                let value = args.next().ok_or("--split needs a length")?;
+                match value.parse::<f64>() {
+                    Ok(length) if length > 0.0 && length.is_finite() => split_length = Some(length),
+                    _ => return Err(format!("Invalid split length: {}", value)),
+                }
+            }
+            _ if path.is_none() => path = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    let mut split_options = if miles {
+        runstats::SplitOptions::miles()
+    } else {
+        runstats::SplitOptions::new()
+    };
+    if let Some(length) = split_length {
+        split_options.distance = length * split_options.units.meters();
+    }
+
+    Ok(Arguments {
+        path: path.ok_or("Too few arguments")?,
+        split_options,
+    })
+}
+
+type Reader = fn(&str) -> Result<Vec<runstats::Track>, runstats::ParseError>;
+
+/// Readers of formats other than GPX by file extension. GPX, possibly compressed, is the default.
+const READERS: &[(&[&str], Reader)] = &[
+    (&["tcx"], runstats::read_tcx_activities),
+    (&["kml", "kmz"], runstats::read_kml_tracks),
+    #[cfg(feature = "geojson")]
+    (&["geojson"], runstats::read_geojson_tracks),
+    (&["csv"], |path| Ok(vec![runstats::read_csv(path, &runstats::CsvOptions::new())?])),
+    (&["nmea"], |path| Ok(vec![runstats::read_nmea(path)?])),
+    (&["igc"], |path| Ok(vec![runstats::read_igc(path, runstats::IgcAltitude::Pressure)?])),
+    (&["fit"], |path| Ok(vec![runstats::read_fit(path)?])),
+];
+
+fn find_reader(path: &str) -> Option<Reader> {
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+    READERS
+        .iter()
+        .find(|(extensions, _)| extensions.contains(&extension.as_str()))
+        .map(|&(_, reader)| reader)
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() <= 1 {
-        eprintln!("Too few arguments");
-        process::exit(1);
-    }
+    let arguments = match parse_arguments(&args) {
+        Ok(arguments) => arguments,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(1);
+        }
+    };
+    let split_options = &arguments.split_options;
+    let print_track = |track: &runstats::Track| print_track(track, split_options);
 
-    let gpx_path = &args[1];
-    if !fs::metadata(gpx_path).is_ok() {
+    let gpx_path = &arguments.path;
+    if fs::metadata(gpx_path).is_err() {
         eprintln!("File doesn't exist");
         process::exit(2);
     }
 
-    if let Ok(track) = runstats::read_gpx(gpx_path) {
-        println!("Track info:");
-        println!("Distance (meters):\t{}", track.distance());
-        println!("Duration:\t{}", runstats::format_duration(track.duration()));
-        println!("Avg heart rate (bpm):\t{}", track.avg_heart_rate());
-        
-        println!("Splits:");
-        let splits = track.splits();
-        for i in 0..splits.len() {
-            let km = (i as u16 * 1000 + splits[i].distance) as f64 / 1000.0;
-            println!("{} km:\t{} per km\t{} meters", km, runstats::format_time(splits[i].pace), splits[i].elevation_delta);
+    if let Some(read_tracks) = find_reader(gpx_path) {
+        match read_tracks(gpx_path) {
+            Ok(tracks) if tracks.is_empty() => println!("No tracks found"),
+            Ok(tracks) => tracks.iter().for_each(print_track),
+            Err(err) => {
+                eprintln!("Parsing error: {}", err);
+                process::exit(3);
+            }
+        }
+        return;
+    }
+
+    let entries = match runstats::read_gpx_entries(gpx_path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("Parsing error: {}", err);
+            process::exit(3);
+        }
+    };
+
+    for entry in &entries {
+        if entries.len() > 1 {
+            println!("{}:", entry.name);
         }
+        if entry.gpx.tracks.is_empty() {
+            println!("No tracks found");
+        }
+        for track in &entry.gpx.tracks {
+            print_track(track);
+        }
+    }
+}
+
+fn print_track(track: &runstats::Track, split_options: &runstats::SplitOptions) {
+    println!("Track info:");
+    if let Some(activity_type) = &track.activity_type {
+        println!("Activity:\t{}", activity_type);
+    }
+    println!("Distance (meters):\t{}", track.distance());
+    println!("Duration:\t{}", runstats::format_duration(track.duration()));
+    println!("Moving time:\t{}", runstats::format_duration(track.moving_time()));
+    if let Some(speed_stats) = track.speed_stats() {
+        println!("Avg speed (m/s):\t{:.2}", speed_stats.average);
+        println!("Avg moving speed (m/s):\t{:.2}", speed_stats.moving_average);
+        if let Some(max_speed) = speed_stats.max {
+            println!("Max speed (m/s):\t{:.2}", max_speed);
+        }
+    }
+    if let Some(avg_heart_rate) = track.avg_heart_rate() {
+        println!("Avg heart rate (bpm):\t{}", avg_heart_rate);
+    }
+    if let Some(avg_power) = track.avg_power() {
+        println!("Avg power (W):\t{}", avg_power);
+    }
+    if let Some(max_power) = track.max_power() {
+        println!("Max power (W):\t{}", max_power);
+    }
+
+    if !track.laps.is_empty() {
+        println!("Laps:");
+    }
+    for (i, lap) in track.laps.iter().enumerate() {
+        println!(
+            "{}:\t{} meters\t{}",
+            i + 1,
+            lap.distance.round(),
+            runstats::format_duration(lap.total_time)
+        );
+    }
+
+    let unit = split_options.units;
+    println!("Splits:");
+    for split in track.splits_with_options(split_options) {
+        let marker = (split.total_distance / unit.meters() * 100.0).round() / 100.0;
+        let pace = runstats::format_time(split.pace);
+        match split.elevation_delta {
+            Some(delta) => println!("{} {}:\t{} per {}\t{} meters", marker, unit.name(), pace, unit.name(), delta),
+            None => println!("{} {}:\t{} per {}", marker, unit.name(), pace, unit.name()),
+        }
+    }
+    if let Some(elevation_stats) = track.elevation_stats() {
         println!("Elevation:");
-        let elevation_stats = track.elevation_stats();
         println!("Max elevation: {}", elevation_stats.max_elevation);
         println!("Min elevation: {}", elevation_stats.min_elevation);
         println!("Elevation gain: {}", elevation_stats.gain);
-    } else {
-        eprintln!("Parsing error");
     }
 }
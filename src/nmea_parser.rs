@@ -0,0 +1,600 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::mem;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+
+use super::gpx_parser::invalid_value;
+use super::{Location, ParseError, ParseOptions, ParseWarning, Track, TrackPoint, TrackSegment};
+
+/// Meters per second in one knot
+const KNOT: f64 = 1852.0 / 3600.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum SentenceKind {
+    /// Recommended minimum data: time, date, position, speed and course
+    Rmc,
+    /// Fix data: time, position, altitude and fix quality
+    Gga,
+    /// Active satellites and dilution of precision
+    Gsa,
+}
+
+struct Sentence<'a> {
+    kind: SentenceKind,
+    /// Comma separated fields following the address field
+    fields: Vec<&'a str>,
+    location: Location,
+}
+
+impl<'a> Sentence<'a> {
+    fn field(&self, index: usize) -> Option<&'a str> {
+        self.fields.get(index).copied().filter(|field| !field.is_empty())
+    }
+
+    fn parse<T: FromStr>(&self, index: usize, name: &str) -> Result<Option<T>, ParseError> {
+        self.field(index)
+            .map(|field| field.parse().map_err(|_| self.invalid_value(name, field)))
+            .transpose()
+    }
+
+    fn invalid_value(&self, name: &str, value: &str) -> ParseError {
+        invalid_value(name, value, self.location)
+    }
+}
+
+/// Data of all sentences reported for the same moment
+#[derive(Default)]
+struct Epoch {
+    time: Option<NaiveTime>,
+    date: Option<NaiveDate>,
+    position: Option<(f64, f64)>,
+    elevation: Option<f64>,
+    speed: Option<f64>,
+    course: Option<f64>,
+    hdop: Option<f64>,
+    satellites: Option<u8>,
+    /// Any sentence of the epoch reported that the receiver has no fix
+    fix_lost: bool,
+}
+
+struct ParserContext {
+    options: ParseOptions,
+    warnings: Vec<ParseWarning>,
+    track: Track,
+    current_segment: TrackSegment,
+    current_epoch: Epoch,
+    /// GGA carries time of day only, the date comes from the latest RMC
+    date: Option<NaiveDate>,
+    previous_time: Option<NaiveTime>,
+    point_count: usize,
+}
+
+impl ParserContext {
+    fn with_options(options: &ParseOptions) -> ParserContext {
+        ParserContext {
+            options: *options,
+            warnings: Vec::new(),
+            track: Track::new(),
+            current_segment: TrackSegment::new(),
+            current_epoch: Epoch::default(),
+            date: None,
+            previous_time: None,
+            point_count: 0,
+        }
+    }
+
+    fn finish_segment(&mut self) {
+        if !self.current_segment.points.is_empty() {
+            let segment = mem::take(&mut self.current_segment);
+            self.track.route.push(segment);
+        }
+    }
+
+    /// Turns collected epoch into a track point. Epoch without fix ends the current segment.
+    fn finish_epoch(&mut self) {
+        let epoch = mem::take(&mut self.current_epoch);
+        // GGA after midnight may come before RMC with the new date. Time going back by
+        // a few seconds is jitter of the log, only a jump from late evening to early
+        // morning is a new day.
+        if let (None, Some(date), Some(time), Some(previous_time)) =
+            (epoch.date, self.date, epoch.time, self.previous_time)
+        {
+            if previous_time.signed_duration_since(time) > Duration::hours(12) {
+                self.date = Some(date + Duration::days(1));
+            }
+        }
+        self.previous_time = epoch.time.or(self.previous_time);
+
+        let (latitude, longitude) = match epoch.position {
+            Some(position) if !epoch.fix_lost => position,
+            _ => {
+                if epoch.fix_lost || epoch.time.is_some() {
+                    self.finish_segment();
+                }
+                return;
+            }
+        };
+
+        let mut point = TrackPoint::from_coordinates(latitude, longitude);
+        point.time = match (epoch.date.or(self.date), epoch.time) {
+            (Some(date), Some(time)) => Some(Utc.from_utc_datetime(&date.and_time(time))),
+            _ => None,
+        };
+        point.elevation = epoch.elevation;
+        point.speed = epoch.speed;
+        point.course = epoch.course;
+        point.hdop = epoch.hdop;
+        point.satellites = epoch.satellites;
+
+        self.current_segment.points.push(point);
+        self.point_count += 1;
+    }
+
+    /// Sentences with time open a new epoch unless they report the same time
+    fn start_epoch(&mut self, time: Option<NaiveTime>) {
+        if time.is_some() && self.current_epoch.time.is_some() && self.current_epoch.time != time {
+            self.finish_epoch();
+        }
+        self.current_epoch.time = time.or(self.current_epoch.time);
+    }
+}
+
+fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+/// Splits the line into sentence fields and verifies its checksum, if the sentence has one.
+/// Lines which are not RMC, GGA or GSA sentences give `None` without any checks.
+fn parse_sentence(line: &str, location: Location) -> Result<Option<Sentence<'_>>, ParseError> {
+    let line = line.trim();
+    let line = match line.strip_prefix('$') {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+
+    let (body, expected_checksum) = match line.split_once('*') {
+        Some((body, expected_checksum)) => (body, Some(expected_checksum)),
+        None => (line, None),
+    };
+
+    let mut fields = body.split(',');
+    // Talker is the first two letters of the address, e.g. GP for GPS or GN for mixed GNSS
+    let kind = match fields.next().and_then(|address| address.get(2..)) {
+        Some("RMC") => SentenceKind::Rmc,
+        Some("GGA") => SentenceKind::Gga,
+        Some("GSA") => SentenceKind::Gsa,
+        _ => return Ok(None),
+    };
+
+    if let Some(expected_checksum) = expected_checksum {
+        let matches = u8::from_str_radix(expected_checksum, 16)
+            .map(|expected_checksum| expected_checksum == checksum(body))
+            .unwrap_or(false);
+        if !matches {
+            return Err(ParseError::InvalidValue {
+                field: String::from("checksum"),
+                value: expected_checksum.to_string(),
+                location,
+            });
+        }
+    }
+
+    Ok(Some(Sentence {
+        kind,
+        fields: fields.collect(),
+        location,
+    }))
+}
+
+/// `hhmmss.ss`
+fn parse_time(sentence: &Sentence, index: usize) -> Result<Option<NaiveTime>, ParseError> {
+    let field = match sentence.field(index) {
+        Some(field) => field,
+        None => return Ok(None),
+    };
+
+    let parse = || {
+        let hours = field.get(0..2)?.parse().ok()?;
+        let minutes = field.get(2..4)?.parse().ok()?;
+        let seconds: f64 = field.get(4..)?.parse().ok()?;
+        let millis = (seconds.fract() * 1000.0).round() as u32;
+        NaiveTime::from_hms_milli_opt(hours, minutes, seconds.trunc() as u32, millis)
+    };
+    parse()
+        .map(Some)
+        .ok_or_else(|| sentence.invalid_value("time", field))
+}
+
+/// `ddmmyy`
+fn parse_date(sentence: &Sentence, index: usize) -> Result<Option<NaiveDate>, ParseError> {
+    let field = match sentence.field(index) {
+        Some(field) => field,
+        None => return Ok(None),
+    };
+
+    let parse = || {
+        let day = field.get(0..2)?.parse().ok()?;
+        let month = field.get(2..4)?.parse().ok()?;
+        let year: i32 = field.get(4..6)?.parse().ok()?;
+        let year = if year < 80 { 2000 + year } else { 1900 + year };
+        NaiveDate::from_ymd_opt(year, month, day)
+    };
+    parse()
+        .map(Some)
+        .ok_or_else(|| sentence.invalid_value("date", field))
+}
+
+/// `ddmm.mmmm` or `dddmm.mmmm` followed by hemisphere field
+fn parse_coordinate(
+    sentence: &Sentence,
+    index: usize,
+    name: &str,
+    negative_hemisphere: &str,
+) -> Result<Option<f64>, ParseError> {
+    let value: f64 = match sentence.parse(index, name)? {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let degrees = (value / 100.0).trunc();
+    let coordinate = degrees + (value - degrees * 100.0) / 60.0;
+    if sentence.field(index + 1) == Some(negative_hemisphere) {
+        Ok(Some(-coordinate))
+    } else {
+        Ok(Some(coordinate))
+    }
+}
+
+fn parse_position(
+    sentence: &Sentence,
+    latitude_index: usize,
+) -> Result<Option<(f64, f64)>, ParseError> {
+    let latitude = parse_coordinate(sentence, latitude_index, "latitude", "S")?;
+    let longitude = parse_coordinate(sentence, latitude_index + 2, "longitude", "W")?;
+
+    Ok(latitude.zip(longitude))
+}
+
+fn apply_rmc(sentence: &Sentence, context: &mut ParserContext) -> Result<(), ParseError> {
+    let time = parse_time(sentence, 0)?;
+    let position = parse_position(sentence, 2)?;
+    let speed: Option<f64> = sentence.parse(6, "speed")?;
+    let course = sentence.parse(7, "course")?;
+    let date = parse_date(sentence, 8)?;
+
+    context.start_epoch(time);
+    context.date = date.or(context.date);
+    let epoch = &mut context.current_epoch;
+    epoch.date = date.or(epoch.date);
+    if sentence.field(1) != Some("A") {
+        epoch.fix_lost = true;
+        return Ok(());
+    }
+    // GGA position comes along with altitude, so it's preferred
+    epoch.position = epoch.position.or(position);
+    epoch.speed = speed.map(|speed| speed * KNOT);
+    epoch.course = course;
+    Ok(())
+}
+
+fn apply_gga(sentence: &Sentence, context: &mut ParserContext) -> Result<(), ParseError> {
+    let time = parse_time(sentence, 0)?;
+    let position = parse_position(sentence, 1)?;
+    let quality: Option<u8> = sentence.parse(5, "fix quality")?;
+    let satellites = sentence.parse(6, "satellites")?;
+    let hdop = sentence.parse(7, "HDOP")?;
+    let elevation = sentence.parse(8, "altitude")?;
+
+    context.start_epoch(time);
+    let epoch = &mut context.current_epoch;
+    if quality.unwrap_or(0) == 0 {
+        epoch.fix_lost = true;
+        return Ok(());
+    }
+    epoch.position = position.or(epoch.position);
+    epoch.elevation = elevation;
+    epoch.satellites = satellites;
+    epoch.hdop = hdop.or(epoch.hdop);
+    Ok(())
+}
+
+/// GSA has no time, it belongs to the epoch of the preceding sentences
+fn apply_gsa(sentence: &Sentence, context: &mut ParserContext) -> Result<(), ParseError> {
+    let fix_type: Option<u8> = sentence.parse(1, "fix type")?;
+    let hdop = sentence.parse(15, "HDOP")?;
+
+    let epoch = &mut context.current_epoch;
+    if fix_type == Some(1) {
+        epoch.fix_lost = true;
+    }
+    epoch.hdop = epoch.hdop.or(hdop);
+    Ok(())
+}
+
+fn parse_line(line: &str, location: Location, context: &mut ParserContext) -> Result<(), ParseError> {
+    let sentence = match parse_sentence(line, location)? {
+        Some(sentence) => sentence,
+        None => return Ok(()),
+    };
+
+    match sentence.kind {
+        SentenceKind::Rmc => apply_rmc(&sentence, context),
+        SentenceKind::Gga => apply_gga(&sentence, context),
+        SentenceKind::Gsa => apply_gsa(&sentence, context),
+    }
+}
+
+fn read_nmea_from<R: Read>(
+    mut reader: BufReader<R>,
+    options: &ParseOptions,
+) -> Result<(Track, Vec<ParseWarning>), ParseError> {
+    let mut context = ParserContext::with_options(options);
+    let mut buffer = Vec::new();
+    let mut line_number = 0;
+
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        // Garbage bytes of a damaged log fail the checksum instead of the whole input
+        let line = String::from_utf8_lossy(&buffer);
+        let location = Location {
+            line: line_number,
+            column: 1,
+        };
+        if let Err(err) = parse_line(&line, location, &mut context) {
+            if !context.options.lenient {
+                return Err(err);
+            }
+            // Skipped sentence is counted to the point being collected
+            let in_progress = context.current_epoch.time.is_some() as usize;
+            context.warnings.push(ParseWarning {
+                point_index: context.point_count + in_progress,
+                error: err,
+            });
+        }
+    }
+
+    context.finish_epoch();
+    context.finish_segment();
+
+    let mut track = context.track;
+    track.sort_points_by_time();
+    track.creation_time = first_point_time(&track);
+    Ok((track, context.warnings))
+}
+
+fn first_point_time(track: &Track) -> Option<DateTime<Utc>> {
+    track
+        .route
+        .iter()
+        .flat_map(|segment| segment.points.iter())
+        .find_map(|point| point.time)
+}
+
+/// Reads NMEA 0183 log. RMC, GGA and GSA sentences of the same moment make a track point,
+/// loss of fix starts a new segment. In lenient mode sentences with bad checksum or values
+/// are skipped and reported as warnings.
+pub fn read_nmea_from_reader_with_options<R: Read>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<(Track, Vec<ParseWarning>), ParseError> {
+    read_nmea_from(BufReader::new(reader), options)
+}
+
+pub fn read_nmea_from_reader<R: Read>(reader: R) -> Result<Track, ParseError> {
+    read_nmea_from_reader_with_options(reader, &ParseOptions::new()).map(|(track, _)| track)
+}
+
+pub fn read_nmea_from_str(nmea: &str) -> Result<Track, ParseError> {
+    read_nmea_from_reader(nmea.as_bytes())
+}
+
+pub fn read_nmea(path: &str) -> Result<Track, ParseError> {
+    let file = File::open(path)?;
+
+    read_nmea_from_reader(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    /// Sentence bodies, `$` and checksum are added by `nmea_log`
+    const NMEA_SENTENCES: [&str; 10] = [
+        "GPGGA,160158.000,4807.0380,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,",
+        "GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1",
+        "GPRMC,160158.000,A,4807.0380,N,01131.0000,E,5.83,84.4,220420,,,A",
+        "GPGGA,160159.000,4807.0390,N,01131.0100,E,1,07,1.1,545.9,M,46.9,M,,",
+        "GPRMC,160159.000,A,4807.0390,N,01131.0100,E,5.90,84.1,220420,,,A",
+        "GPGGA,160200.000,,,,,0,00,,,M,,M,,",
+        "GPGSA,A,1,,,,,,,,,,,,,,,",
+        "GPRMC,160200.000,V,,,,,,,220420,,,N",
+        "GPGGA,160201.000,4807.0410,S,01131.0300,W,2,09,0.8,546.2,M,46.9,M,,",
+        "GPRMC,160201.000,A,4807.0410,S,01131.0300,W,5.70,83.9,220420,,,D",
+    ];
+
+    fn with_checksum(body: &str) -> String {
+        format!("${}*{:02X}", body, checksum(body))
+    }
+
+    fn nmea_log(sentences: &[&str]) -> String {
+        sentences
+            .iter()
+            .map(|sentence| with_checksum(sentence) + "\r\n")
+            .collect()
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(
+            checksum("GPGGA,092750.000,5321.6802,N,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,"),
+            0x76
+        );
+    }
+
+    #[test]
+    fn test_reading_nmea() {
+        let track = read_nmea_from_str(&nmea_log(&NMEA_SENTENCES)).unwrap();
+
+        assert_eq!(track.creation_time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58)));
+        assert_eq!(track.route.len(), 2);
+        assert_eq!(track.route[0].points.len(), 2);
+        assert_eq!(track.route[1].points.len(), 1);
+
+        let first = &track.route[0].points[0];
+        assert!((first.latitude - 48.1173).abs() < 1e-9);
+        assert!((first.longitude - 11.516_666_666).abs() < 1e-9);
+        assert_eq!(first.elevation, Some(545.4));
+        assert_eq!(first.satellites, Some(8));
+        assert_eq!(first.hdop, Some(0.9));
+        assert_eq!(first.course, Some(84.4));
+        assert!((first.speed.unwrap() - 5.83 * KNOT).abs() < 1e-9);
+
+        let second = &track.route[0].points[1];
+        assert_eq!(second.time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 59)));
+        assert_eq!(second.satellites, Some(7));
+
+        let after_fix_loss = &track.route[1].points[0];
+        assert_eq!(after_fix_loss.time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 2, 1)));
+        assert!(after_fix_loss.latitude < 0.0);
+        assert!(after_fix_loss.longitude < 0.0);
+        assert_eq!(after_fix_loss.hdop, Some(0.8));
+    }
+
+    #[test]
+    fn test_reading_nmea_without_gga() {
+        let log = nmea_log(&[
+            "GNRMC,235959.500,A,4807.0380,N,01131.0000,E,0.0,,311220,,,A",
+            "GNRMC,000000.500,A,4807.0380,N,01131.0000,E,0.0,,010121,,,A",
+        ]);
+
+        let track = read_nmea_from_str(&log).unwrap();
+        let points = &track.route[0].points;
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].elevation, None);
+        assert_eq!(points[0].satellites, None);
+        assert_eq!(
+            points[1].time,
+            Some(Utc.ymd(2021, 1, 1).and_hms_milli(0, 0, 0, 500))
+        );
+        assert_eq!(track.duration().as_secs(), 1);
+    }
+
+    #[test]
+    fn test_reading_nmea_gga_after_midnight() {
+        let log = nmea_log(&[
+            "GNGGA,235959.000,4807.0380,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,",
+            "GNRMC,235959.000,A,4807.0380,N,01131.0000,E,0.0,,311220,,,A",
+            "GNGGA,000000.000,4807.0390,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,",
+            "GNGGA,000001.000,4807.0400,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,",
+            "GNRMC,000001.000,A,4807.0400,N,01131.0000,E,0.0,,010121,,,A",
+        ]);
+
+        let track = read_nmea_from_str(&log).unwrap();
+        let points = &track.route[0].points;
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[1].time, Some(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)));
+        assert_eq!(points[2].time, Some(Utc.ymd(2021, 1, 1).and_hms(0, 0, 1)));
+        assert_eq!(track.duration().as_secs(), 2);
+    }
+
+    #[test]
+    fn test_reading_nmea_gga_going_back() {
+        let log = nmea_log(&[
+            "GNRMC,120005.000,A,4807.0380,N,01131.0000,E,0.0,,311220,,,A",
+            "GNGGA,120005.000,4807.0380,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,",
+            "GNGGA,120004.000,4807.0390,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,",
+        ]);
+
+        let track = read_nmea_from_str(&log).unwrap();
+        let points = &track.route[0].points;
+        assert_eq!(points.len(), 2);
+        // Points are sorted, the late GGA stays on the same day
+        assert_eq!(points[0].time, Some(Utc.ymd(2020, 12, 31).and_hms(12, 0, 4)));
+        assert_eq!(points[1].time, Some(Utc.ymd(2020, 12, 31).and_hms(12, 0, 5)));
+    }
+
+    #[test]
+    fn test_reading_nmea_skips_other_lines() {
+        let log = format!(
+            "Logger v1.2\n$PMTK001,314,3*36\n{}\n$GPGSV,1,1,00*79\n",
+            with_checksum("GPGGA,160158.000,4807.0380,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,")
+        );
+
+        let track = read_nmea_from_str(&log).unwrap();
+        assert_eq!(track.route.len(), 1);
+        // Position without date still makes a point
+        assert_eq!(track.route[0].points[0].time, None);
+        assert_eq!(track.creation_time, None);
+    }
+
+    #[test_case("$GPGGA,160158.000,4807.0380,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,*4D", "checksum", "4D"; "bad checksum")]
+    #[test_case("$GPGGA,160158.000,4807.0380,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,*ZZ", "checksum", "ZZ"; "malformed checksum")]
+    #[test_case("$GPGGA,160158.000,48O7.0380,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,", "latitude", "48O7.0380"; "bad latitude")]
+    #[test_case("$GPRMC,256158.000,A,4807.0380,N,01131.0000,E,5.83,84.4,220420,,,A", "time", "256158.000"; "bad time")]
+    fn test_reading_invalid_nmea(line: &str, expected_field: &str, expected_value: &str) {
+        let log = format!("\n{}\n", line);
+
+        match read_nmea_from_str(&log) {
+            Err(ParseError::InvalidValue {
+                field,
+                value,
+                location,
+            }) => {
+                assert_eq!(field, expected_field);
+                assert_eq!(value, expected_value);
+                assert_eq!(location.line, 2);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lenient_nmea_parsing() {
+        let log = format!(
+            "{}\n$GPGGA,160159.000,4807.0390,N,01131.0100,E,1,07,1.1,545.9,M,46.9,M,,*00\n{}\n",
+            with_checksum("GPGGA,160158.000,4807.0380,N,01131.0000,E,1,08,0.9,545.4,M,46.9,M,,"),
+            with_checksum("GPGGA,160200.000,4807.0400,N,01131.0200,E,1,07,1.1,546.0,M,46.9,M,,")
+        );
+
+        let (track, warnings) =
+            read_nmea_from_reader_with_options(log.as_bytes(), &ParseOptions::lenient()).unwrap();
+        assert_eq!(track.route.len(), 1);
+        assert_eq!(track.route[0].points.len(), 2);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].point_index, 1);
+        assert!(matches!(
+            warnings[0].error,
+            ParseError::InvalidValue { location: Location { line: 2, .. }, .. }
+        ));
+    }
+
+    #[test]
+    fn test_reading_missing_nmea_file() {
+        let result = read_nmea("/nonexistent/path/to/track.nmea");
+        assert!(matches!(result, Err(ParseError::Io(_))));
+    }
+}
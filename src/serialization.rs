@@ -0,0 +1,267 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Serde support of the data model enabled by `serde` feature.
+//
+// Encoding of values without an obvious form:
+// * `DateTime<Utc>` is an RFC 3339 string in UTC, e.g. `"2020-04-22T16:01:58Z"`. Fractions
+//   of a second are written only when present.
+// * `Duration` is a number of seconds, e.g. `1.5`.
+// * `ActivityType` is the variant name, e.g. `"Running"`, or `{"Other": "Kayaking"}`.
+
+/// `Duration` as floating point number of seconds
+pub(crate) mod duration_seconds {
+    use std::time::Duration;
+
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let seconds = f64::deserialize(deserializer)?;
+        // Rejects negative, non-finite and too large values
+        Duration::try_from_secs_f64(seconds)
+            .map_err(|_| D::Error::custom(format!("invalid duration: {}", seconds)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use serde_json::json;
+    use test_case::test_case;
+
+    use super::super::*;
+
+    /// Serializes value, reads it back and checks that nothing was lost on the way
+    fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> (T, serde_json::Value) {
+        let json = serde_json::to_value(value).unwrap();
+        let parsed: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+        (parsed, json)
+    }
+
+    fn new_point() -> TrackPoint {
+        let mut point = TrackPoint::from_coordinates(10.1025420, 15.1583540);
+        point.elevation = Some(478.2);
+        point.time = Some(Utc.ymd(2020, 4, 22).and_hms_milli(16, 1, 58, 250));
+        point.heart_rate = Some(95);
+        point.power = Some(250);
+        point.satellites = Some(9);
+        point
+    }
+
+    fn new_track() -> Track {
+        let mut lap = Lap::new(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58));
+        lap.total_time = Duration::from_millis(1500);
+        lap.distance = 5.5;
+        lap.segment_count = 1;
+
+        let mut track = Track::new();
+        track.name = String::from("Evening run");
+        track.creation_time = Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58));
+        track.activity_type = Some(ActivityType::Running);
+        track.links.push(Link::new("https://example.com/activity/1"));
+        track.route.push(TrackSegment {
+            points: vec![new_point(), TrackPoint::from_coordinates(10.1026420, 15.1583542)],
+        });
+        track.laps.push(lap);
+        track
+    }
+
+    #[test]
+    fn test_track_point_round_trip() {
+        let (point, json) = round_trip(&new_point());
+
+        assert_eq!(json["time"], json!("2020-04-22T16:01:58.250Z"));
+        assert_eq!(json["cadence"], json!(null));
+        assert_eq!(point.time, new_point().time);
+        assert_eq!(point.latitude, 10.1025420);
+        assert_eq!(point.satellites, Some(9));
+    }
+
+    #[test]
+    fn test_track_segment_round_trip() {
+        let segment = TrackSegment {
+            points: vec![new_point(), new_point()],
+        };
+
+        let (parsed, _) = round_trip(&segment);
+        assert_eq!(parsed.points.len(), 2);
+    }
+
+    #[test]
+    fn test_track_round_trip() {
+        let (track, json) = round_trip(&new_track());
+
+        assert_eq!(json["creation_time"], json!("2020-04-22T16:01:58Z"));
+        assert_eq!(json["activity_type"], json!("Running"));
+        assert_eq!(json["laps"][0]["total_time"], json!(1.5));
+        assert_eq!(track.name, "Evening run");
+        assert_eq!(track.route[0].points.len(), 2);
+        assert_eq!(track.laps[0].total_time, Duration::from_millis(1500));
+        assert_eq!(track.distance(), new_track().distance());
+    }
+
+    #[test]
+    fn test_activity_type_round_trip() {
+        let (_, json) = round_trip(&ActivityType::Other(String::from("Kayaking")));
+        assert_eq!(json, json!({"Other": "Kayaking"}));
+
+        let (activity_type, _) = round_trip(&ActivityType::Cycling);
+        assert_eq!(activity_type, ActivityType::Cycling);
+    }
+
+    #[test]
+    fn test_lap_round_trip() {
+        let lap = &new_track().laps[0];
+        let (parsed, json) = round_trip(lap);
+
+        assert_eq!(json["start_time"], json!("2020-04-22T16:01:58Z"));
+        assert_eq!(parsed.start_time, lap.start_time);
+        assert_eq!(parsed.total_time, lap.total_time);
+    }
+
+    #[test_case(json!(-1.0); "negative")]
+    #[test_case(json!(1e300); "too large")]
+    fn test_lap_with_invalid_duration(total_time: serde_json::Value) {
+        let mut json = serde_json::to_value(&new_track().laps[0]).unwrap();
+        json["total_time"] = total_time;
+
+        let result = serde_json::from_value::<Lap>(json);
+        assert!(result.unwrap_err().to_string().contains("invalid duration"));
+    }
+
+    #[test]
+    fn test_gpx_round_trip() {
+        let mut waypoint = Waypoint::new(52.5161, 13.3776);
+        waypoint.name = Some(String::from("Gate"));
+        let mut route = Route::new();
+        route.points.push(waypoint.clone());
+
+        let mut gpx = Gpx::new();
+        gpx.creator = Some(String::from("Forerunner 245"));
+        gpx.metadata.author = Some(Person {
+            name: Some(String::from("Jane Doe")),
+            email: None,
+            link: Some(Link::new("https://example.com/jane")),
+        });
+        gpx.metadata.copyright = Some(Copyright {
+            author: String::from("Jane Doe"),
+            year: Some(2020),
+            license: None,
+        });
+        gpx.metadata.bounds = Some(Bounds {
+            min_latitude: 52.1,
+            min_longitude: 13.2,
+            max_latitude: 52.3,
+            max_longitude: 13.4,
+        });
+        gpx.metadata.keywords = vec![String::from("running")];
+        gpx.waypoints.push(waypoint);
+        gpx.routes.push(route);
+        gpx.tracks.push(new_track());
+
+        let (parsed, _) = round_trip(&gpx);
+        assert_eq!(parsed.creator, gpx.creator);
+        assert_eq!(parsed.metadata.author, gpx.metadata.author);
+        assert_eq!(parsed.metadata.copyright, gpx.metadata.copyright);
+        assert_eq!(parsed.metadata.bounds, gpx.metadata.bounds);
+        assert_eq!(parsed.waypoints[0].name.as_deref(), Some("Gate"));
+        assert_eq!(parsed.routes[0].points.len(), 1);
+        assert_eq!(parsed.tracks.len(), 1);
+    }
+
+    #[test]
+    fn test_split_round_trip() {
+        let split = Split {
+            start_distance: 1000.0,
+            distance: 1000.0,
+            total_distance: 2000.0,
+            duration: Duration::from_secs(300),
+            pace: 300,
+            elevation_delta: Some(-12),
+        };
+
+        let (parsed, json) = round_trip(&split);
+        assert_eq!(
+            json,
+            json!({
+                "start_distance": 1000.0,
+                "distance": 1000.0,
+                "total_distance": 2000.0,
+                "duration": 300.0,
+                "pace": 300,
+                "elevation_delta": -12
+            })
+        );
+        assert_eq!(parsed, split);
+    }
+
+    #[test]
+    fn test_elevation_stats_round_trip() {
+        let stats = ElevationStats {
+            max_elevation: 120,
+            min_elevation: -5,
+            gain: 300,
+        };
+
+        let (parsed, _) = round_trip(&stats);
+        assert_eq!(parsed.max_elevation, 120);
+        assert_eq!(parsed.min_elevation, -5);
+        assert_eq!(parsed.gain, 300);
+    }
+
+    #[test]
+    fn test_location_and_options_round_trip() {
+        let (location, _) = round_trip(&Location { line: 3, column: 7 });
+        assert_eq!(location, Location { line: 3, column: 7 });
+
+        let (options, _) = round_trip(&ParseOptions::lenient());
+        assert!(options.lenient);
+
+        let (options, json) = round_trip(&PauseOptions::new());
+        assert_eq!(json["max_gap"], json!(60.0));
+        assert_eq!(options, PauseOptions::new());
+
+        let (options, _) = round_trip(&SpeedOptions::new());
+        assert_eq!(options, SpeedOptions::new());
+
+        let (options, json) = round_trip(&SplitOptions::miles());
+        assert_eq!(json["units"], json!("Imperial"));
+        assert_eq!(options, SplitOptions::miles());
+    }
+
+    #[test]
+    fn test_speed_stats_round_trip() {
+        let stats = SpeedStats {
+            average: 2.5,
+            moving_average: 3.0,
+            max: None,
+        };
+
+        let (parsed, json) = round_trip(&stats);
+        assert_eq!(json, json!({"average": 2.5, "moving_average": 3.0, "max": null}));
+        assert_eq!(parsed, stats);
+    }
+}
@@ -0,0 +1,387 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::time::Duration;
+
+use super::stats::{calc_track_duration, distance_with_elevation, duration_between_points, pause_between};
+use super::{SpeedOptions, SpeedStats, Track, TrackPoint};
+
+/// Point is a spike only if both speeds into and out of it exceed this, meters per second
+const MIN_SPIKE_SPEED: f64 = 10.0;
+/// ... and both are that many times higher than the speed of going straight past the point
+const SPIKE_SPEED_RATIO: f64 = 3.0;
+
+/// Speed between two points, meters per second. Empty for points without time or at the same time.
+fn speed_between(point1: &TrackPoint, point2: &TrackPoint) -> Option<f64> {
+    let duration = duration_between_points(point1, point2).as_secs_f64();
+    if duration > 0.0 {
+        Some(distance_with_elevation(point1, point2) / duration)
+    } else {
+        None
+    }
+}
+
+/// Speed of every point over the stretch from the previous point, meters per second.
+/// Empty for the first point of a segment and for points without time.
+pub fn calc_track_point_speeds(track: &Track) -> Vec<Vec<Option<f64>>> {
+    track
+        .route
+        .iter()
+        .map(|segment| {
+            let mut speeds = Vec::with_capacity(segment.points.len());
+            speeds.extend(segment.points.first().map(|_| None));
+            speeds.extend(segment.points.windows(2).map(|pair| speed_between(&pair[0], &pair[1])));
+            speeds
+        })
+        .collect()
+}
+
+/// Single point jumping away from the path and back, typical GPS glitch
+fn is_spike(previous: &TrackPoint, point: &TrackPoint, next: &TrackPoint) -> bool {
+    let speeds = (
+        speed_between(previous, point),
+        speed_between(point, next),
+        speed_between(previous, next),
+    );
+    match speeds {
+        (Some(speed_in), Some(speed_out), Some(speed_past)) => {
+            let speed = speed_in.min(speed_out);
+            speed > MIN_SPIKE_SPEED && speed > SPIKE_SPEED_RATIO * speed_past
+        }
+        _ => false,
+    }
+}
+
+/// Distance from the start of the segment to every point. The path goes straight past
+/// spikes, which get no distance.
+fn filtered_distances(points: &[TrackPoint]) -> Vec<Option<f64>> {
+    let mut distances = Vec::with_capacity(points.len());
+    let mut total_distance = 0.0;
+    let mut last_valid = 0;
+
+    for (i, point) in points.iter().enumerate() {
+        let spike = i + 1 < points.len() && is_spike(&points[last_valid], point, &points[i + 1]);
+        if spike {
+            distances.push(None);
+            continue;
+        }
+
+        if i > 0 {
+            total_distance += distance_with_elevation(&points[last_valid], point);
+            last_valid = i;
+        }
+        distances.push(Some(total_distance));
+    }
+
+    distances
+}
+
+/// Distance covered from the point `start` to the point `end` and time it took.
+/// Empty if any of the points is a spike or has no time.
+fn stretch(
+    points: &[TrackPoint],
+    distances: &[Option<f64>],
+    start: usize,
+    end: usize,
+) -> Option<(f64, Duration)> {
+    points[start].time?;
+    points[end].time?;
+    let distance = distances[end]? - distances[start]?;
+    Some((distance, duration_between_points(&points[start], &points[end])))
+}
+
+/// Moves window `start` forward to the latest usable point at least `window` before
+/// the point `end`, which must be usable itself
+fn advance_window_start(
+    points: &[TrackPoint],
+    distances: &[Option<f64>],
+    mut start: usize,
+    end: usize,
+    window: Duration,
+) -> usize {
+    let usable = |i: usize| points[i].time.is_some() && distances[i].is_some();
+
+    while start < end {
+        let next = match (start + 1..=end).find(|&i| usable(i)) {
+            Some(next) => next,
+            None => break,
+        };
+        if usable(start) && duration_between_points(&points[next], &points[end]) < window {
+            break;
+        }
+        start = next;
+    }
+
+    start
+}
+
+/// Speed of every point averaged over at least `window` before it, meters per second.
+/// Spikes are bypassed. Empty for points without time and at the start of a segment.
+pub fn calc_track_smoothed_speeds(track: &Track, window: Duration) -> Vec<Vec<Option<f64>>> {
+    track
+        .route
+        .iter()
+        .map(|segment| {
+            let points = &segment.points;
+            let distances = filtered_distances(points);
+            let mut start = 0;
+
+            (0..points.len())
+                .map(|end| {
+                    points[end].time?;
+                    distances[end]?;
+                    start = advance_window_start(points, &distances, start, end, window);
+                    let (distance, duration) = stretch(points, &distances, start, end)?;
+                    if duration > Duration::new(0, 0) {
+                        Some(distance / duration.as_secs_f64())
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Highest speed kept for at least `window`. Empty if no segment lasts that long.
+fn calc_track_max_sustained_speed(track: &Track, window: Duration) -> Option<f64> {
+    let mut max_speed: Option<f64> = None;
+
+    for segment in &track.route {
+        let points = &segment.points;
+        let distances = filtered_distances(points);
+        let mut start = 0;
+
+        for end in 0..points.len() {
+            if points[end].time.is_none() || distances[end].is_none() {
+                continue;
+            }
+
+            start = advance_window_start(points, &distances, start, end, window);
+            let (distance, duration) = match stretch(points, &distances, start, end) {
+                Some(stretch) => stretch,
+                None => continue,
+            };
+            if duration < window || duration == Duration::new(0, 0) {
+                continue;
+            }
+
+            let speed = distance / duration.as_secs_f64();
+            max_speed = Some(max_speed.map_or(speed, |max_speed| max_speed.max(speed)));
+        }
+    }
+
+    max_speed
+}
+
+/// Calculates average and max speeds. Returns `None` if the track has no duration.
+pub fn calc_track_speed_stats(track: &Track, options: &SpeedOptions) -> Option<SpeedStats> {
+    let duration = calc_track_duration(track).as_secs_f64();
+    if duration == 0.0 {
+        return None;
+    }
+
+    let mut distance = 0.0;
+    let mut moving_distance = 0.0;
+    let mut moving_time = 0.0;
+
+    for pair in track.route.iter().flat_map(|segment| segment.points.windows(2)) {
+        let stretch = distance_with_elevation(&pair[0], &pair[1]);
+        distance += stretch;
+
+        let stretch_duration = duration_between_points(&pair[0], &pair[1]).as_secs_f64();
+        if stretch_duration > 0.0 && pause_between(&pair[0], &pair[1], &options.pauses).is_none() {
+            moving_distance += stretch;
+            moving_time += stretch_duration;
+        }
+    }
+
+    let moving_average = if moving_time > 0.0 {
+        moving_distance / moving_time
+    } else {
+        0.0
+    };
+
+    Some(SpeedStats {
+        average: distance / duration,
+        moving_average,
+        max: calc_track_max_sustained_speed(track, options.max_speed_window),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f64::consts::PI;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::super::{PauseOptions, TrackSegment};
+
+    /// Point on the equator `meters` east of zero meridian, recorded at `seconds`
+    fn new_timed_point(meters: f64, seconds: i64) -> TrackPoint {
+        const LONGITUDE_STEP: f64 = 2.0 * PI * 6371008.8 / 360.0;
+
+        let mut point = TrackPoint::from_coordinates(0.0, meters / LONGITUDE_STEP);
+        point.time = Utc.timestamp_opt(seconds, 0).single();
+        point
+    }
+
+    fn new_track(points: Vec<TrackPoint>) -> Track {
+        let mut track = Track::new();
+        track.route.push(TrackSegment { points });
+        track
+    }
+
+    /// One minute at 3 m/s recorded every second, with the point at 30 s jumping 100 m aside
+    fn new_track_with_spike() -> Track {
+        let mut points: Vec<TrackPoint> = (0..=60)
+            .map(|i| new_timed_point(f64::from(i) * 3.0, i64::from(i)))
+            .collect();
+        points[30].latitude += 100.0 / (2.0 * PI * 6371008.8 / 360.0);
+        new_track(points)
+    }
+
+    #[test]
+    fn test_calc_track_point_speeds() {
+        let mut points = vec![
+            new_timed_point(0.0, 100),
+            new_timed_point(1000.0, 500),
+            new_timed_point(1000.0, 500),
+            new_timed_point(2000.0, 0),
+        ];
+        points[3].time = None;
+
+        let speeds = calc_track_point_speeds(&new_track(points));
+        assert_eq!(speeds.len(), 1);
+        assert_eq!(speeds[0].len(), 4);
+        assert_eq!(speeds[0][0], None);
+        assert!((speeds[0][1].unwrap() - 2.5).abs() <= 1e-6);
+        // Same time or unknown time gives no speed
+        assert_eq!(speeds[0][2], None);
+        assert_eq!(speeds[0][3], None);
+    }
+
+    #[test]
+    fn test_filtered_distances_bypass_spike() {
+        let track = new_track_with_spike();
+        let distances = filtered_distances(&track.route[0].points);
+
+        assert_eq!(distances[30], None);
+        assert!((distances[31].unwrap() - 93.0).abs() <= 1e-6);
+        assert!((distances[60].unwrap() - 180.0).abs() <= 1e-6);
+    }
+
+    #[test]
+    fn test_calc_track_smoothed_speeds() {
+        let track = new_track_with_spike();
+
+        let raw_speeds = calc_track_point_speeds(&track);
+        assert!(raw_speeds[0][30].unwrap() > 50.0);
+
+        let speeds = calc_track_smoothed_speeds(&track, Duration::from_secs(5));
+        assert_eq!(speeds[0].len(), 61);
+        assert_eq!(speeds[0][0], None);
+        assert_eq!(speeds[0][30], None);
+        for (i, speed) in speeds[0].iter().enumerate().skip(1).filter(|&(i, _)| i != 30) {
+            assert!((speed.unwrap() - 3.0).abs() <= 1e-6, "{}: {:?}", i, speed);
+        }
+    }
+
+    #[test]
+    fn test_calc_track_smoothed_speeds_of_sparse_points() {
+        let points = vec![
+            new_timed_point(0.0, 0),
+            new_timed_point(100.0, 20),
+            new_timed_point(300.0, 40),
+        ];
+
+        let speeds = calc_track_smoothed_speeds(&new_track(points), Duration::from_secs(5));
+        assert_eq!(speeds[0][0], None);
+        assert!((speeds[0][1].unwrap() - 5.0).abs() <= 1e-6);
+        assert!((speeds[0][2].unwrap() - 10.0).abs() <= 1e-6);
+    }
+
+    #[test]
+    fn test_max_speed_ignores_spike() {
+        let track = new_track_with_spike();
+
+        let max_speed = calc_track_max_sustained_speed(&track, Duration::from_secs(10)).unwrap();
+        assert!((max_speed - 3.0).abs() <= 1e-6, "{}", max_speed);
+    }
+
+    #[test]
+    fn test_max_sustained_speed() {
+        // 5 m/s for 20 seconds in the middle of a 3 m/s run
+        let mut points = Vec::new();
+        let mut meters = 0.0;
+        for second in 0..=120 {
+            points.push(new_timed_point(meters, second));
+            meters += if (50..70).contains(&second) { 5.0 } else { 3.0 };
+        }
+        let track = new_track(points);
+
+        let max_speed = calc_track_max_sustained_speed(&track, Duration::from_secs(10)).unwrap();
+        assert!((max_speed - 5.0).abs() <= 1e-6);
+
+        let max_speed = calc_track_max_sustained_speed(&track, Duration::from_secs(40)).unwrap();
+        assert!((max_speed - 4.0).abs() <= 1e-6);
+
+        assert_eq!(calc_track_max_sustained_speed(&track, Duration::from_secs(600)), None);
+    }
+
+    #[test]
+    fn test_calc_track_speed_stats() {
+        // 300 m in 100 s, 10 minutes of rest and 300 m more in 100 s
+        let mut points = Vec::new();
+        for i in 0..=10 {
+            points.push(new_timed_point(f64::from(i) * 30.0, i64::from(i) * 10));
+        }
+        for i in 1..=60 {
+            points.push(new_timed_point(300.0, 100 + i64::from(i) * 10));
+        }
+        for i in 1..=10 {
+            points.push(new_timed_point(300.0 + f64::from(i) * 30.0, 700 + i64::from(i) * 10));
+        }
+        let track = new_track(points);
+
+        let stats = calc_track_speed_stats(&track, &SpeedOptions::new()).unwrap();
+        assert!((stats.average - 600.0 / 800.0).abs() <= 1e-6);
+        assert!((stats.moving_average - 3.0).abs() <= 1e-6);
+        assert!((stats.max.unwrap() - 3.0).abs() <= 1e-6);
+
+        // Without pause detection the rest counts as slow moving
+        let options = SpeedOptions {
+            pauses: PauseOptions {
+                min_speed: 0.0,
+                max_gap: Duration::from_secs(3600),
+            },
+            ..SpeedOptions::new()
+        };
+        let stats = calc_track_speed_stats(&track, &options).unwrap();
+        assert!((stats.moving_average - stats.average).abs() <= 1e-6);
+    }
+
+    #[test]
+    fn test_calc_track_speed_stats_without_time() {
+        let track = new_track(vec![
+            TrackPoint::from_coordinates(0.0, 0.0),
+            TrackPoint::from_coordinates(0.0, 0.01),
+        ]);
+
+        assert!(calc_track_speed_stats(&track, &SpeedOptions::new()).is_none());
+    }
+}
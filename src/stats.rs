@@ -16,7 +16,7 @@
 use std::f64::consts::PI;
 use std::time::Duration;
 
-use super::{Split, Track, TrackPoint, ElevationStats};
+use super::{ElevationStats, Pause, PauseOptions, Split, SplitOptions, Track, TrackPoint};
 
 /// In meters according to WGS84
 const EARTH_RADIUS: f64 = 6371008.8;
@@ -47,17 +47,19 @@ fn distance(lat1: f64, long1: f64, lat2: f64, long2: f64) -> f64 {
 }
 
 /// Calculates distance taking into account elevations of two points
-fn distance_with_elevation(point1: &TrackPoint, point2: &TrackPoint) -> f64 {
+pub(crate) fn distance_with_elevation(point1: &TrackPoint, point2: &TrackPoint) -> f64 {
     let cathet1 = distance(
         point1.latitude,
         point1.longitude,
         point2.latitude,
         point2.longitude,
     );
-    let cathet2 = (point2.elevation - point1.elevation).abs();
+    let cathet2 = match (point1.elevation, point2.elevation) {
+        (Some(elevation1), Some(elevation2)) => (elevation2 - elevation1).abs(),
+        _ => 0.0,
+    };
 
-    let hypot = (cathet1 * cathet1 + cathet2 * cathet2).sqrt();
-    hypot
+    (cathet1 * cathet1 + cathet2 * cathet2).sqrt()
 }
 
 fn calc_track_distance_segment(points: &[TrackPoint]) -> f64 {
@@ -89,16 +91,40 @@ pub fn calc_track_distance(track: &Track) -> u64 {
     }
 }
 
-fn duration_between_points(point1: &TrackPoint, point2: &TrackPoint) -> Duration {
-    point2
-        .time
-        .signed_duration_since(point1.time)
-        .to_std()
-        .unwrap()
+/// Distance covered from the start of the track to every point, grouped by segment.
+/// Gaps between segments don't count, same as in `calc_track_distance`.
+pub fn calc_track_point_distances(track: &Track) -> Vec<Vec<f64>> {
+    let mut total_distance = 0.0;
+    let mut distances = Vec::with_capacity(track.route.len());
+
+    for segment in &track.route {
+        let mut segment_distances = Vec::with_capacity(segment.points.len());
+        for (i, point) in segment.points.iter().enumerate() {
+            if i > 0 {
+                total_distance += distance_with_elevation(&segment.points[i - 1], point);
+            }
+            segment_distances.push(total_distance);
+        }
+        distances.push(segment_distances);
+    }
+
+    distances
+}
+
+/// Time elapsed between two points. Zero if any of them has no time or the second one
+/// is earlier.
+pub(crate) fn duration_between_points(point1: &TrackPoint, point2: &TrackPoint) -> Duration {
+    match (point1.time, point2.time) {
+        (Some(time1), Some(time2)) => time2
+            .signed_duration_since(time1)
+            .to_std()
+            .unwrap_or_default(),
+        _ => Duration::new(0, 0),
+    }
 }
 
 fn calc_track_duration_segment(points: &[TrackPoint]) -> Duration {
-    if points.len() == 0 {
+    if points.is_empty() {
         return Duration::new(0, 0);
     }
 
@@ -130,7 +156,65 @@ pub fn calc_track_duration(track: &Track) -> Duration {
     total_duration
 }
 
-pub fn calc_track_average_heart_rate(track: &Track) -> u8 {
+/// Pause covering the stretch between two consecutive points, if the athlete stood still there.
+/// Stretches without time are never pauses.
+pub(crate) fn pause_between(point: &TrackPoint, next: &TrackPoint, options: &PauseOptions) -> Option<Pause> {
+    let (start, end) = (point.time?, next.time?);
+    let duration = duration_between_points(point, next);
+    if duration == Duration::new(0, 0) {
+        return None;
+    }
+
+    let speed = distance_with_elevation(point, next) / duration.as_secs_f64();
+    if duration <= options.max_gap && speed >= options.min_speed {
+        return None;
+    }
+
+    Some(Pause {
+        start,
+        end,
+        latitude: point.latitude,
+        longitude: point.longitude,
+    })
+}
+
+/// Finds periods of standing still. Consecutive stopped stretches are merged into one pause.
+/// Gaps between segments are not pauses, since they don't count in the duration either.
+pub fn calc_track_pauses(track: &Track, options: &PauseOptions) -> Vec<Pause> {
+    let mut pauses = Vec::new();
+
+    for segment in &track.route {
+        let mut current: Option<Pause> = None;
+        for pair in segment.points.windows(2) {
+            match (pause_between(&pair[0], &pair[1], options), current.as_mut()) {
+                (Some(pause), Some(current)) => current.end = pause.end,
+                (Some(pause), None) => current = Some(pause),
+                (None, _) => pauses.extend(current.take()),
+            }
+        }
+        pauses.extend(current);
+    }
+
+    pauses
+}
+
+/// Same as `calc_track_duration`, but without pauses
+pub fn calc_track_moving_time(track: &Track, options: &PauseOptions) -> Duration {
+    track
+        .route
+        .iter()
+        .flat_map(|segment| segment.points.windows(2))
+        .filter(|pair| pause_between(&pair[0], &pair[1], options).is_none())
+        .map(|pair| duration_between_points(&pair[0], &pair[1]))
+        .sum()
+}
+
+/// Calculates time-weighted average of a point value. Values between two points are
+/// approximated linearly. Points without value are skipped.
+fn calc_track_time_weighted_average<F>(track: &Track, value: F) -> Option<u64>
+where
+    F: Fn(&TrackPoint) -> Option<u64>,
+{
     let mut total_duration_sec: u64 = 0;
     let mut sum: u64 = 0;
 
@@ -139,15 +223,16 @@ pub fn calc_track_average_heart_rate(track: &Track) -> u8 {
 
         for i in 0..segment.points.len() {
             let point = &segment.points[i];
-            if point.heart_rate == 0 {
-                continue; // Skip invalid data
-            }
+            let point_value = match value(point) {
+                Some(point_value) => point_value,
+                None => continue, // Skip invalid data
+            };
 
             let next_idx = i + 1;
             if next_idx >= segment.points.len() {
                 if single_point_segment {
                     // Count as one value for 1 seconds
-                    sum += point.heart_rate as u64;
+                    sum += point_value;
                     total_duration_sec += 1;
                 }
 
@@ -155,106 +240,152 @@ pub fn calc_track_average_heart_rate(track: &Track) -> u8 {
             }
 
             single_point_segment = false;
-            let next_point = &segment.points[i + 1];
+            let next_point = &segment.points[next_idx];
 
-            if next_point.heart_rate == 0 {
-                // Current point has HR, next one doesn't. Count as single HR value for 1 second
-                sum += point.heart_rate as u64;
-                total_duration_sec += 1;
-            }
+            let next_value = match value(next_point) {
+                Some(next_value) => next_value,
+                None => {
+                    // Current point has value, next one doesn't. Count as single value for 1 second
+                    sum += point_value;
+                    total_duration_sec += 1;
+                    continue;
+                }
+            };
 
-            // Both points has HR values. Use linear approximation for the values in between.
+            // Both points have values. Use linear approximation for the values in between.
             let duration_sec = duration_between_points(point, next_point).as_secs();
             if duration_sec == 0 {
                 continue;
             }
 
-            let s = (point.heart_rate as u64 + next_point.heart_rate as u64) * duration_sec / 2;
-            sum += s;
+            sum += (point_value + next_value) * duration_sec / 2;
             total_duration_sec += duration_sec;
         }
     }
 
-    if total_duration_sec != 0 {
-        (sum / total_duration_sec) as u8
-    } else {
-        0
+    sum.checked_div(total_duration_sec)
+}
+
+pub fn calc_track_average_heart_rate(track: &Track) -> Option<u8> {
+    calc_track_time_weighted_average(track, |point| point.heart_rate.map(u64::from))
+        .map(|average| average as u8)
+}
+
+pub fn calc_track_average_power(track: &Track) -> Option<u16> {
+    calc_track_time_weighted_average(track, |point| point.power.map(u64::from))
+        .map(|average| average as u16)
+}
+
+pub fn calc_track_max_power(track: &Track) -> Option<u16> {
+    track
+        .route
+        .iter()
+        .flat_map(|segment| segment.points.iter())
+        .filter_map(|point| point.power)
+        .max()
+}
+
+/// Difference between two elevations if both of them are known
+fn elevation_delta(start: Option<f64>, end: Option<f64>) -> Option<i16> {
+    match (start, end) {
+        (Some(start), Some(end)) => Some((end - start).round() as i16),
+        _ => None,
+    }
+}
+
+/// Elevation at `fraction` of the way between two points. Known elevation of one point is
+/// taken if the other one has none.
+fn interpolate_elevation(point: &TrackPoint, next: &TrackPoint, fraction: f64) -> Option<f64> {
+    match (point.elevation, next.elevation) {
+        (Some(elevation), Some(next_elevation)) => {
+            Some(elevation + (next_elevation - elevation) * fraction)
+        }
+        (elevation, next_elevation) => next_elevation.or(elevation),
     }
 }
 
-/// Calculates track splits. Return value is array of paces per km in seconds.
-pub fn calc_track_splits(track: &Track) -> Vec<Split> {
-    const METERS_IN_KM: f64 = 1000.0;
-    const MIN_SPLIT_THRESHOLD: f64 = 100.0;
+/// Seconds per unit of distance, `unit` and `distance` in meters
+fn calc_pace(duration: f64, distance: f64, unit: f64) -> u64 {
+    (duration * unit / distance).round() as u64
+}
+
+/// Divides the track into splits of equal length. A stretch between two points may be
+/// shared by several splits, its time is divided in proportion to distance.
+pub fn calc_track_splits(track: &Track, options: &SplitOptions) -> Vec<Split> {
+    let length = options.distance;
+    if !length.is_finite() || length <= 0.0 {
+        return Vec::new();
+    }
+
+    let unit = options.units.meters();
+    let new_split = |start_distance: f64, distance: f64, duration: f64, elevation_delta| Split {
+        start_distance,
+        distance,
+        total_distance: start_distance + distance,
+        duration: Duration::from_secs_f64(duration),
+        pace: calc_pace(duration, distance, unit),
+        elevation_delta,
+    };
 
     let mut splits = Vec::new();
 
-    let mut dist_accumulator: f64 = 0.0;
-    let mut current_km_duration: u64 = 0;
-    let mut start_elevation: f64 = 0.0;
-    let mut latest_elevation: f64 = 0.0;
+    let mut start_distance = 0.0;
+    let mut split_distance = 0.0;
+    let mut split_duration = 0.0;
+    let mut start_elevation: Option<f64> = None;
+    let mut latest_elevation: Option<f64> = None;
 
     for segment in &track.route {
-        for i in 0..segment.points.len() - 1 {
-            let point = &segment.points[i];
-            let next = &segment.points[i + 1];
+        for pair in segment.points.windows(2) {
+            let point = &pair[0];
+            let next = &pair[1];
 
             let dist = distance_with_elevation(point, next);
-            let duration = duration_between_points(point, next).as_secs();
-            if dist_accumulator == 0.0 {
+            let duration = match &options.pauses {
+                Some(pauses) if pause_between(point, next, pauses).is_some() => 0.0,
+                _ => duration_between_points(point, next).as_secs_f64(),
+            };
+            // Split starting at a sensor dropout takes the first elevation known after it
+            if split_distance == 0.0 || start_elevation.is_none() {
                 start_elevation = point.elevation;
             }
 
-            latest_elevation = next.elevation;
-
-            let pending = dist_accumulator + dist;
-            if pending < METERS_IN_KM {
-                dist_accumulator = pending;
-                current_km_duration += duration;
-            } else if pending == METERS_IN_KM {
-                current_km_duration += duration;
-                let delta = next.elevation - start_elevation;
-                splits.push(Split {
-                    distance: METERS_IN_KM as u16,
-                    pace: current_km_duration,
-                    elevation_delta: delta.round() as i16,
-                });
-
-                current_km_duration = 0;
-                dist_accumulator = 0.0;
-                start_elevation = next.elevation;
-            } else {
-                let extra = (pending - METERS_IN_KM) / dist;
-                let extra_duration = (duration as f64 * extra) as u64;
-
-                current_km_duration += duration - extra_duration;
-                let current_delta = next.elevation - point.elevation;
-                let extra_elevation = current_delta * extra;
-                let current_end_elevation = point.elevation + extra_elevation;
-                let split_delta = current_end_elevation - start_elevation;
-
-                splits.push(Split {
-                    distance: METERS_IN_KM as u16,
-                    pace: current_km_duration,
-                    elevation_delta: split_delta.round() as i16,
-                });
-
-                current_km_duration = extra_duration;
-                dist_accumulator = extra * dist;
-                start_elevation = current_end_elevation;
+            latest_elevation = next.elevation.or(latest_elevation);
+
+            // Part of the stretch already given to completed splits, meters
+            let mut consumed = 0.0;
+            while split_distance + (dist - consumed) >= length {
+                let needed = length - split_distance;
+                consumed += needed;
+                split_duration += duration * needed / dist;
+
+                let end_elevation = interpolate_elevation(point, next, consumed / dist);
+                splits.push(new_split(
+                    start_distance,
+                    length,
+                    split_duration,
+                    elevation_delta(start_elevation, end_elevation),
+                ));
+
+                start_distance += length;
+                split_distance = 0.0;
+                split_duration = 0.0;
+                start_elevation = end_elevation;
             }
+
+            let rest = dist - consumed;
+            split_distance += rest;
+            split_duration += if dist > 0.0 { duration * rest / dist } else { duration };
         }
     }
 
-    if dist_accumulator >= MIN_SPLIT_THRESHOLD && current_km_duration > 0 {
-        let coeff = dist_accumulator / METERS_IN_KM;
-        let estimated_duration = (current_km_duration as f64 / coeff) as u64;
-        let split_delta = latest_elevation - start_elevation;
-        splits.push(Split {
-            distance: dist_accumulator as u16,
-            pace: estimated_duration,
-            elevation_delta: split_delta.round() as i16,
-        });
+    if split_distance >= options.min_partial_distance && split_distance > 0.0 && split_duration > 0.0 {
+        splits.push(new_split(
+            start_distance,
+            split_distance,
+            split_duration,
+            elevation_delta(start_elevation, latest_elevation),
+        ));
     }
 
     splits
@@ -276,7 +407,8 @@ fn min_f64(a: f64, b: f64) -> f64 {
     }
 }
 
-pub fn calc_track_elevation_stats(track: &Track) -> ElevationStats {
+/// Calculates elevation range and gain. Returns `None` if no point has elevation.
+pub fn calc_track_elevation_stats(track: &Track) -> Option<ElevationStats> {
     let mut max_elevation: Option<f64> = None;
     let mut min_elevation: Option<f64> = None;
     let mut gain: f64 = 0.0;
@@ -284,35 +416,36 @@ pub fn calc_track_elevation_stats(track: &Track) -> ElevationStats {
 
     for segment in &track.route {
         for point in &segment.points {
-            if point.elevation == 0.0 {
-                continue;
-            }
+            let elevation = match point.elevation {
+                Some(elevation) => elevation,
+                None => continue,
+            };
 
             max_elevation = match max_elevation {
-                Some(elev) => Some(max_f64(elev, point.elevation)),
-                None => Some(point.elevation)
+                Some(elev) => Some(max_f64(elev, elevation)),
+                None => Some(elevation)
             };
 
             min_elevation = match min_elevation {
-                Some(elev) => Some(min_f64(elev, point.elevation)),
-                None => Some(point.elevation)
+                Some(elev) => Some(min_f64(elev, elevation)),
+                None => Some(elevation)
             };
 
             if let Some(elev) = prev_elevation {
-                if point.elevation > elev {
-                    gain += point.elevation - elev;
+                if elevation > elev {
+                    gain += elevation - elev;
                 }
             }
 
-            prev_elevation = Some(point.elevation);
+            prev_elevation = Some(elevation);
         }
     }
 
-    let max_elevation = max_elevation.unwrap_or(0.0).round() as i16;
-    let min_elevation = min_elevation.unwrap_or(0.0).round() as i16;
+    let max_elevation = max_elevation?.round() as i16;
+    let min_elevation = min_elevation?.round() as i16;
     let gain = gain.round() as u16;
 
-    ElevationStats {max_elevation, min_elevation, gain}
+    Some(ElevationStats {max_elevation, min_elevation, gain})
 }
 
 #[cfg(test)]
@@ -329,10 +462,19 @@ mod tests {
         TrackPoint {
             latitude,
             longitude,
-            elevation,
-            time: Utc::now(),
-            heart_rate: 0,
-            cadence: 0,
+            elevation: Some(elevation),
+            time: None,
+            heart_rate: None,
+            cadence: None,
+            speed: None,
+            course: None,
+            air_temperature: None,
+            water_temperature: None,
+            depth: None,
+            power: None,
+            distance: None,
+            hdop: None,
+            satellites: None,
         }
     }
 
@@ -340,13 +482,51 @@ mod tests {
         TrackPoint {
             latitude: 0.0,
             longitude: 0.0,
-            elevation,
-            time: Utc::now(),
-            heart_rate: 0,
-            cadence: 0,
+            elevation: Some(elevation),
+            time: None,
+            heart_rate: None,
+            cadence: None,
+            speed: None,
+            course: None,
+            air_temperature: None,
+            water_temperature: None,
+            depth: None,
+            power: None,
+            distance: None,
+            hdop: None,
+            satellites: None,
         }
     }
 
+    /// Point on the equator `meters` east of zero meridian, recorded at `seconds`
+    fn new_timed_point(meters: f64, seconds: i64) -> TrackPoint {
+        const LONGITUDE_STEP: f64 = 2.0 * PI * EARTH_RADIUS / 360.0;
+
+        let mut point = new_point_from_coords(0.0, meters / LONGITUDE_STEP, 0.0);
+        point.time = Some(new_date_time(seconds));
+        point
+    }
+
+    /// Runs 300 m in 100 s, stands still for 20 minutes and runs 300 m more
+    fn new_track_with_stop() -> Track {
+        let mut segment = TrackSegment::new();
+        for i in 0..=10 {
+            segment.points.push(new_timed_point(f64::from(i) * 30.0, i64::from(i) * 10));
+        }
+        for i in 1..=120 {
+            // GPS drifts a little while standing
+            let drift = if i % 2 == 0 { 0.0 } else { 1.0 };
+            segment.points.push(new_timed_point(300.0 + drift, 100 + i64::from(i) * 10));
+        }
+        for i in 1..=10 {
+            segment.points.push(new_timed_point(300.0 + f64::from(i) * 30.0, 1300 + i64::from(i) * 10));
+        }
+
+        let mut track = Track::new();
+        track.route.push(segment);
+        track
+    }
+
     #[test]
     fn test_zero_distance() {
         let dist = distance(0.0, 0.0, 0.0, 0.0);
@@ -395,7 +575,7 @@ mod tests {
 
     #[test]
     fn test_calc_track_distance_with_elevation() {
-        let point1 = TrackPoint::from_coordinates(1.0, 1.0);
+        let mut point1 = TrackPoint::from_coordinates(1.0, 1.0);
         let mut point2 = TrackPoint::from_coordinates(2.0, 1.0);
 
         // Make elevation of second point be equal to horizontal distance.
@@ -406,7 +586,8 @@ mod tests {
             point2.latitude,
             point2.longitude,
         );
-        point2.elevation = dist;
+        point1.elevation = Some(0.0);
+        point2.elevation = Some(dist);
 
         let mut segment = TrackSegment::new();
         segment.points.push(point1);
@@ -419,6 +600,56 @@ mod tests {
         assert_eq!(calc_track_distance(&track), expected_dist);
     }
 
+    #[test]
+    fn test_calc_track_distance_with_missing_elevation() {
+        let mut point1 = TrackPoint::from_coordinates(1.0, 1.0);
+        let point2 = TrackPoint::from_coordinates(2.0, 1.0);
+        point1.elevation = Some(1000.0);
+
+        let dist = distance(
+            point1.latitude,
+            point1.longitude,
+            point2.latitude,
+            point2.longitude,
+        );
+
+        let mut segment = TrackSegment::new();
+        segment.points.push(point1);
+        segment.points.push(point2);
+
+        let mut track = Track::new();
+        track.route.push(segment);
+
+        // Unknown elevation must not be treated as a descent to the sea level
+        assert_eq!(calc_track_distance(&track), dist as u64);
+    }
+
+    #[test]
+    fn test_calc_track_point_distances() {
+        let dist = distance(1.0, 1.0, 2.0, 1.0);
+
+        let mut track = Track::new();
+        track.route.push(TrackSegment {
+            points: vec![
+                TrackPoint::from_coordinates(1.0, 1.0),
+                TrackPoint::from_coordinates(2.0, 1.0),
+            ],
+        });
+        // Gap between segments doesn't count
+        track.route.push(TrackSegment {
+            points: vec![
+                TrackPoint::from_coordinates(5.0, 1.0),
+                TrackPoint::from_coordinates(6.0, 1.0),
+            ],
+        });
+
+        let distances = calc_track_point_distances(&track);
+        assert_eq!(distances.len(), 2);
+        assert_eq!(distances[0], vec![0.0, dist]);
+        assert_eq!(distances[1][0], dist);
+        assert!((distances[1][1] - 2.0 * dist).abs() <= 1.0);
+    }
+
     #[test]
     fn test_calc_track_duration_10_points() {
         const POINTS_NUM: usize = 10;
@@ -431,7 +662,7 @@ mod tests {
         for i in 0..POINTS_NUM {
             let secs = offset_sec + step_sec * i as i64;
             let mut point = TrackPoint::new();
-            point.time = new_date_time(secs);
+            point.time = Some(new_date_time(secs));
             points.push(point);
         }
 
@@ -450,7 +681,7 @@ mod tests {
     #[test]
     fn test_calc_track_duration_1_point() {
         let mut point = TrackPoint::new();
-        point.time = new_date_time(123456);
+        point.time = Some(new_date_time(123456));
 
         let mut segment = TrackSegment::new();
         segment.points.push(point);
@@ -464,13 +695,12 @@ mod tests {
     #[test]
     fn test_calc_track_duration_2_same_point() {
         let mut points = Vec::with_capacity(2);
-        [TrackPoint::new(); 2];
         let mut point = TrackPoint::new();
-        point.time = new_date_time(123456);
+        point.time = Some(new_date_time(123456));
         points.push(point);
 
         let mut point = TrackPoint::new();
-        point.time = new_date_time(123456);
+        point.time = Some(new_date_time(123456));
         points.push(point);
 
         let mut segment = TrackSegment::new();
@@ -482,10 +712,32 @@ mod tests {
         assert_eq!(calc_track_duration(&track).as_millis(), 0);
     }
 
+    #[test]
+    fn test_calc_track_duration_backwards_time() {
+        let mut segment = TrackSegment::new();
+        for secs in &[100, 160, 130] {
+            let mut point = TrackPoint::new();
+            point.time = Some(new_date_time(*secs));
+            segment.points.push(point);
+        }
+
+        let mut track = Track::new();
+        track.route.push(segment);
+
+        // Going back in time adds nothing instead of panicking
+        assert_eq!(calc_track_duration(&track).as_secs(), 60);
+    }
+
     fn new_track_point_hr(seconds: i64, heart_rate: u8) -> TrackPoint {
         let mut point = TrackPoint::new();
-        point.time = new_date_time(seconds);
-        point.heart_rate = heart_rate;
+        point.time = Some(new_date_time(seconds));
+        point.heart_rate = Some(heart_rate);
+        point
+    }
+
+    fn new_track_point_without_hr(seconds: i64) -> TrackPoint {
+        let mut point = TrackPoint::new();
+        point.time = Some(new_date_time(seconds));
         point
     }
 
@@ -500,7 +752,7 @@ mod tests {
         track.route.push(segment);
 
         let avg_heart_rate = calc_track_average_heart_rate(&track);
-        assert_eq!(avg_heart_rate, 105);
+        assert_eq!(avg_heart_rate, Some(105));
     }
 
     #[test]
@@ -519,7 +771,71 @@ mod tests {
         track.route.push(segment);
 
         let avg_heart_rate = calc_track_average_heart_rate(&track);
-        assert_eq!(avg_heart_rate, 115);
+        assert_eq!(avg_heart_rate, Some(115));
+    }
+
+    #[test]
+    fn test_calc_track_average_heartrate_with_gap() {
+        let mut track = Track::new();
+
+        let mut segment = TrackSegment::new();
+        segment.points.push(new_track_point_hr(100, 100));
+        segment.points.push(new_track_point_without_hr(110));
+        segment.points.push(new_track_point_hr(120, 120));
+        segment.points.push(new_track_point_hr(130, 130));
+        track.route.push(segment);
+
+        // 100 bpm for a second, then 120..130 bpm for 10 seconds
+        let avg_heart_rate = calc_track_average_heart_rate(&track);
+        assert_eq!(avg_heart_rate, Some(122));
+    }
+
+    #[test]
+    fn test_calc_track_average_heartrate_without_sensor() {
+        let mut track = Track::new();
+
+        let mut segment = TrackSegment::new();
+        segment.points.push(new_track_point_without_hr(100));
+        segment.points.push(new_track_point_without_hr(110));
+        track.route.push(segment);
+
+        assert_eq!(calc_track_average_heart_rate(&track), None);
+    }
+
+    fn new_track_point_power(seconds: i64, power: Option<u16>) -> TrackPoint {
+        let mut point = TrackPoint::new();
+        point.time = Some(new_date_time(seconds));
+        point.power = power;
+        point
+    }
+
+    #[test]
+    fn test_calc_track_power() {
+        let mut track = Track::new();
+
+        let mut segment = TrackSegment::new();
+        segment.points.push(new_track_point_power(100, Some(200)));
+        segment.points.push(new_track_point_power(110, Some(300)));
+        segment.points.push(new_track_point_power(120, Some(0)));
+        segment.points.push(new_track_point_power(130, Some(0)));
+        track.route.push(segment);
+
+        // Coasting with zero power must lower the average
+        assert_eq!(calc_track_average_power(&track), Some(133));
+        assert_eq!(calc_track_max_power(&track), Some(300));
+    }
+
+    #[test]
+    fn test_calc_track_power_without_data() {
+        let mut track = Track::new();
+
+        let mut segment = TrackSegment::new();
+        segment.points.push(new_track_point_power(100, None));
+        segment.points.push(new_track_point_power(110, None));
+        track.route.push(segment);
+
+        assert_eq!(calc_track_average_power(&track), None);
+        assert_eq!(calc_track_max_power(&track), None);
     }
 
     #[test]
@@ -552,34 +868,262 @@ mod tests {
             100.0,
         ));
 
-        segment.points[0].time = new_date_time(100);
-        segment.points[1].time = new_date_time(500);
-        segment.points[2].time = new_date_time(700);
-        segment.points[3].time = new_date_time(1050);
-        segment.points[4].time = new_date_time(1350);
+        segment.points[0].time = Some(new_date_time(100));
+        segment.points[1].time = Some(new_date_time(500));
+        segment.points[2].time = Some(new_date_time(700));
+        segment.points[3].time = Some(new_date_time(1050));
+        segment.points[4].time = Some(new_date_time(1350));
 
         let mut track = Track::new();
         track.route.push(segment);
 
-        let splits = calc_track_splits(&track);
+        let splits = calc_track_splits(&track, &SplitOptions::new());
 
         assert_eq!(splits.len(), 4);
 
         assert!((splits[0].distance as i32 - 1000).abs() <= 2);
         assert!((splits[0].pace as i32 - 400).abs() <= 2);
-        assert_eq!(splits[0].elevation_delta, 0);
+        assert_eq!(splits[0].elevation_delta, Some(0));
 
         assert!((splits[1].distance as i32 - 1000).abs() <= 2);
         assert!((splits[1].pace as i32 - 375).abs() <= 2);
-        assert_eq!(splits[1].elevation_delta, 0);
+        assert_eq!(splits[1].elevation_delta, Some(0));
 
         assert!((splits[2].distance as i32 - 1000).abs() <= 2);
         assert!((splits[2].pace as i32 - 325).abs() <= 2);
-        assert_eq!(splits[2].elevation_delta, 0);
+        assert_eq!(splits[2].elevation_delta, Some(0));
 
         assert!((splits[3].distance as i32 - 500).abs() <= 2);
         assert!((splits[3].pace as i32 - 300).abs() <= 2);
-        assert_eq!(splits[3].elevation_delta, 0);
+        assert_eq!(splits[3].elevation_delta, Some(0));
+
+        assert_eq!(splits[2].start_distance, 2000.0);
+        assert_eq!(splits[2].total_distance, 3000.0);
+        assert!((splits[3].total_distance - 3500.0).abs() <= 2.0);
+        assert!((splits[3].duration.as_secs_f64() - 150.0).abs() <= 1.0);
+    }
+
+    /// Straight line along the equator, a point every 500 meters and 150 seconds
+    fn new_evenly_paced_track(point_count: u32) -> Track {
+        const LONGITUDE_STEP: f64 = 2.0 * PI * EARTH_RADIUS / 360.0;
+
+        let mut segment = TrackSegment::new();
+        for i in 0..point_count {
+            let mut point = TrackPoint::from_coordinates(0.0, f64::from(i) * 500.0 / LONGITUDE_STEP);
+            point.time = Some(new_date_time(i64::from(i) * 150));
+            segment.points.push(point);
+        }
+
+        let mut track = Track::new();
+        track.route.push(segment);
+        track
+    }
+
+    #[test]
+    fn test_calc_track_splits_shorter_than_stretch() {
+        // 2 km, each stretch between points holds more than one split
+        let track = new_evenly_paced_track(5);
+        let options = SplitOptions {
+            distance: 400.0,
+            ..SplitOptions::new()
+        };
+
+        let splits = calc_track_splits(&track, &options);
+        assert_eq!(splits.len(), 5);
+        for (i, split) in splits.iter().enumerate() {
+            assert!((split.start_distance - i as f64 * 400.0).abs() <= 1e-6);
+            assert!((split.distance - 400.0).abs() <= 1e-6);
+            assert!((split.duration.as_secs_f64() - 120.0).abs() <= 1e-6);
+            assert_eq!(split.pace, 300);
+        }
+        assert!((splits[4].total_distance - 2000.0).abs() <= 1e-6);
+    }
+
+    #[test]
+    fn test_calc_track_mile_splits() {
+        // 5 km at 5 minutes per km
+        let track = new_evenly_paced_track(11);
+
+        let splits = calc_track_splits(&track, &SplitOptions::miles());
+        assert_eq!(splits.len(), 4);
+        assert_eq!(splits[0].distance, 1609.344);
+        // 8:03 per mile
+        assert_eq!(splits[0].pace, 483);
+        assert!((splits[2].total_distance - 3.0 * 1609.344).abs() <= 1e-6);
+
+        let last = &splits[3];
+        assert!((last.distance - (5000.0 - 3.0 * 1609.344)).abs() <= 1e-6);
+        assert_eq!(last.pace, 483);
+    }
+
+    #[test]
+    fn test_calc_track_splits_partial_threshold() {
+        // 2.5 km, the last split is 500 meters
+        let track = new_evenly_paced_track(6);
+
+        let options = SplitOptions {
+            min_partial_distance: 600.0,
+            ..SplitOptions::new()
+        };
+        assert_eq!(calc_track_splits(&track, &options).len(), 2);
+
+        let options = SplitOptions {
+            min_partial_distance: 450.0,
+            ..SplitOptions::new()
+        };
+        assert_eq!(calc_track_splits(&track, &options).len(), 3);
+    }
+
+    #[test]
+    fn test_calc_track_splits_with_fractional_seconds() {
+        const LONGITUDE_STEP: f64 = 2.0 * PI * EARTH_RADIUS / 360.0;
+
+        // Over 1 km at 4 m/s, a point every 1.5 seconds
+        let mut segment = TrackSegment::new();
+        for i in 0..=170 {
+            let mut point = TrackPoint::from_coordinates(0.0, f64::from(i) * 6.0 / LONGITUDE_STEP);
+            point.time = Some(new_date_time(0) + chrono::Duration::milliseconds(i64::from(i) * 1500));
+            segment.points.push(point);
+        }
+        let mut track = Track::new();
+        track.route.push(segment);
+
+        let splits = calc_track_splits(&track, &SplitOptions::new());
+        assert_eq!(splits[0].pace, 250);
+        assert!((splits[0].duration.as_secs_f64() - 250.0).abs() <= 0.01);
+    }
+
+    #[test]
+    fn test_calc_track_splits_of_invalid_length() {
+        let track = new_evenly_paced_track(6);
+
+        for distance in [0.0, -1000.0, f64::NAN] {
+            let options = SplitOptions {
+                distance,
+                ..SplitOptions::new()
+            };
+            assert!(calc_track_splits(&track, &options).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_calc_track_moving_time() {
+        let track = new_track_with_stop();
+
+        assert_eq!(calc_track_duration(&track), Duration::from_secs(1400));
+        assert_eq!(calc_track_moving_time(&track, &PauseOptions::new()), Duration::from_secs(200));
+    }
+
+    #[test]
+    fn test_calc_track_pauses() {
+        let track = new_track_with_stop();
+
+        let pauses = calc_track_pauses(&track, &PauseOptions::new());
+        assert_eq!(pauses.len(), 1);
+        assert_eq!(pauses[0].start, new_date_time(100));
+        assert_eq!(pauses[0].end, new_date_time(1300));
+        assert_eq!(pauses[0].duration(), Duration::from_secs(1200));
+        assert_eq!(pauses[0].latitude, 0.0);
+        assert!((pauses[0].longitude - track.route[0].points[10].longitude).abs() < 1e-12);
+
+        // Drift of 0.1 m/s counts as moving with a lower threshold
+        let options = PauseOptions {
+            min_speed: 0.05,
+            ..PauseOptions::new()
+        };
+        assert!(calc_track_pauses(&track, &options).is_empty());
+        assert_eq!(calc_track_moving_time(&track, &options), calc_track_duration(&track));
+    }
+
+    #[test]
+    fn test_calc_track_pauses_on_long_gap() {
+        let mut segment = TrackSegment::new();
+        segment.points.push(new_timed_point(0.0, 0));
+        segment.points.push(new_timed_point(30.0, 10));
+        // Fast enough, but the device obviously was paused
+        segment.points.push(new_timed_point(230.0, 310));
+        segment.points.push(new_timed_point(260.0, 320));
+        let mut track = Track::new();
+        track.route.push(segment);
+
+        let pauses = calc_track_pauses(&track, &PauseOptions::new());
+        assert_eq!(pauses.len(), 1);
+        assert_eq!(pauses[0].start, new_date_time(10));
+        assert_eq!(pauses[0].end, new_date_time(310));
+        assert_eq!(calc_track_moving_time(&track, &PauseOptions::new()), Duration::from_secs(20));
+
+        let options = PauseOptions {
+            max_gap: Duration::from_secs(600),
+            ..PauseOptions::new()
+        };
+        assert!(calc_track_pauses(&track, &options).is_empty());
+        assert_eq!(calc_track_moving_time(&track, &options), Duration::from_secs(320));
+    }
+
+    #[test]
+    fn test_calc_track_pauses_without_time() {
+        let mut segment = TrackSegment::new();
+        segment.points.push(new_point_from_coords(0.0, 0.0, 0.0));
+        segment.points.push(new_point_from_coords(0.0, 0.0, 0.0));
+        let mut track = Track::new();
+        track.route.push(segment);
+
+        assert!(calc_track_pauses(&track, &PauseOptions::new()).is_empty());
+        assert_eq!(calc_track_moving_time(&track, &PauseOptions::new()), Duration::new(0, 0));
+    }
+
+    #[test]
+    fn test_calc_track_moving_splits() {
+        // 1 km in 400 s, 15 minutes of rest and 1 km more in 400 s
+        let mut segment = TrackSegment::new();
+        for i in 0..=40 {
+            segment.points.push(new_timed_point(f64::from(i) * 25.0, i64::from(i) * 10));
+        }
+        for i in 1..=90 {
+            segment.points.push(new_timed_point(1000.0, 400 + i64::from(i) * 10));
+        }
+        for i in 1..=40 {
+            segment.points.push(new_timed_point(1000.0 + f64::from(i) * 25.0, 1300 + i64::from(i) * 10));
+        }
+        let mut track = Track::new();
+        track.route.push(segment);
+
+        let splits = calc_track_splits(&track, &SplitOptions::new());
+        assert_eq!(splits.len(), 2);
+        // The rest lands into one of the splits depending on rounding at 1 km
+        let total_pace: u64 = splits.iter().map(|split| split.pace).sum();
+        assert!((total_pace as i64 - 1700).abs() <= 4);
+
+        let options = SplitOptions {
+            pauses: Some(PauseOptions::new()),
+            ..SplitOptions::new()
+        };
+        let splits = calc_track_splits(&track, &options);
+        assert_eq!(splits.len(), 2);
+        assert!((splits[0].pace as i64 - 400).abs() <= 2);
+        assert!((splits[1].pace as i64 - 400).abs() <= 2);
+    }
+
+    #[test]
+    fn test_calc_track_splits_without_elevation() {
+        const LONGITUDE_STEP: f64 = 2.0 * PI * EARTH_RADIUS / 360.0;
+        const LONGITUDE_STEP_1KM: f64 = 1000.0 / LONGITUDE_STEP;
+
+        let mut segment = TrackSegment::new();
+        segment.points.push(TrackPoint::from_coordinates(0.0, 100.0));
+        segment.points.push(TrackPoint::from_coordinates(0.0, 100.0 + LONGITUDE_STEP_1KM));
+        segment.points.push(TrackPoint::from_coordinates(0.0, 100.0 + LONGITUDE_STEP_1KM * 1.5));
+        segment.points[0].time = Some(new_date_time(100));
+        segment.points[1].time = Some(new_date_time(500));
+        segment.points[2].time = Some(new_date_time(700));
+
+        let mut track = Track::new();
+        track.route.push(segment);
+
+        let splits = calc_track_splits(&track, &SplitOptions::new());
+        assert_eq!(splits.len(), 2);
+        assert_eq!(splits[0].elevation_delta, None);
+        assert_eq!(splits[1].elevation_delta, None);
     }
 
     #[test]
@@ -600,9 +1144,56 @@ mod tests {
         segment.points.push(new_point_from_elevation(17.0));
         track.route.push(segment);
 
-        let elevation_stats = calc_track_elevation_stats(&track);
+        let elevation_stats = calc_track_elevation_stats(&track).unwrap();
         assert_eq!(elevation_stats.max_elevation, 17);
         assert_eq!(elevation_stats.min_elevation, -3);
         assert_eq!(elevation_stats.gain, 25);
     }
+
+    #[test]
+    fn test_calc_track_elevation_stats_at_sea_level() {
+        let mut track = Track::new();
+
+        let mut segment = TrackSegment::new();
+        segment.points.push(new_point_from_elevation(0.0));
+        segment.points.push(new_point_from_elevation(3.0));
+        segment.points.push(new_point_from_elevation(0.0));
+        segment.points.push(new_point_from_elevation(2.0));
+        track.route.push(segment);
+
+        let elevation_stats = calc_track_elevation_stats(&track).unwrap();
+        assert_eq!(elevation_stats.max_elevation, 3);
+        assert_eq!(elevation_stats.min_elevation, 0);
+        assert_eq!(elevation_stats.gain, 5);
+    }
+
+    #[test]
+    fn test_calc_track_elevation_stats_with_dropouts() {
+        let mut track = Track::new();
+
+        let mut segment = TrackSegment::new();
+        segment.points.push(new_point_from_elevation(10.0));
+        segment.points.push(TrackPoint::new());
+        segment.points.push(new_point_from_elevation(14.0));
+        segment.points.push(TrackPoint::new());
+        segment.points.push(new_point_from_elevation(12.0));
+        track.route.push(segment);
+
+        let elevation_stats = calc_track_elevation_stats(&track).unwrap();
+        assert_eq!(elevation_stats.max_elevation, 14);
+        assert_eq!(elevation_stats.min_elevation, 10);
+        assert_eq!(elevation_stats.gain, 4);
+    }
+
+    #[test]
+    fn test_calc_track_elevation_stats_without_elevation() {
+        let mut track = Track::new();
+
+        let mut segment = TrackSegment::new();
+        segment.points.push(TrackPoint::new());
+        segment.points.push(TrackPoint::new());
+        track.route.push(segment);
+
+        assert!(calc_track_elevation_stats(&track).is_none());
+    }
 }
@@ -0,0 +1,866 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::mem;
+use std::time::Duration;
+
+use xml::attribute::OwnedAttribute;
+use xml::common::Position;
+use xml::name::OwnedName;
+use xml::reader::XmlEvent;
+use xml::EventReader;
+
+use chrono::prelude::*;
+
+use super::gpx_parser::{convert_xml_error, invalid_value, to_location};
+use super::{ActivityType, Lap, Location, ParseError, Track, TrackPoint, TrackSegment};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum TcxXmlTag {
+    Activity,
+    Course,
+    Id,
+    Notes,
+    Creator,
+    Name,
+    Lap,
+    TotalTimeSeconds,
+    DistanceMeters,
+    Calories,
+    AverageHeartRateBpm,
+    MaximumHeartRateBpm,
+    HeartRateBpm,
+    Value,
+    Cadence,
+    Track,
+    Trackpoint,
+    Time,
+    Position,
+    LatitudeDegrees,
+    LongitudeDegrees,
+    AltitudeMeters,
+    ExtSpeed,
+    ExtWatts,
+    ExtRunCadence,
+}
+
+impl TcxXmlTag {
+    fn name(self) -> &'static str {
+        match self {
+            TcxXmlTag::Activity => "Activity",
+            TcxXmlTag::Course => "Course",
+            TcxXmlTag::Id => "Id",
+            TcxXmlTag::Notes => "Notes",
+            TcxXmlTag::Creator => "Creator",
+            TcxXmlTag::Name => "Name",
+            TcxXmlTag::Lap => "Lap",
+            TcxXmlTag::TotalTimeSeconds => "TotalTimeSeconds",
+            TcxXmlTag::DistanceMeters => "DistanceMeters",
+            TcxXmlTag::Calories => "Calories",
+            TcxXmlTag::AverageHeartRateBpm => "AverageHeartRateBpm",
+            TcxXmlTag::MaximumHeartRateBpm => "MaximumHeartRateBpm",
+            TcxXmlTag::HeartRateBpm => "HeartRateBpm",
+            TcxXmlTag::Value => "Value",
+            TcxXmlTag::Cadence => "Cadence",
+            TcxXmlTag::Track => "Track",
+            TcxXmlTag::Trackpoint => "Trackpoint",
+            TcxXmlTag::Time => "Time",
+            TcxXmlTag::Position => "Position",
+            TcxXmlTag::LatitudeDegrees => "LatitudeDegrees",
+            TcxXmlTag::LongitudeDegrees => "LongitudeDegrees",
+            TcxXmlTag::AltitudeMeters => "AltitudeMeters",
+            TcxXmlTag::ExtSpeed => "Speed",
+            TcxXmlTag::ExtWatts => "Watts",
+            TcxXmlTag::ExtRunCadence => "RunCadence",
+        }
+    }
+}
+
+struct ParserContext {
+    in_activity: bool,
+    in_course: bool,
+    in_track: bool,
+    in_track_point: bool,
+    in_position: bool,
+    in_creator: bool,
+    current_tag: Option<TcxXmlTag>,
+    /// Heart rate element whose `<Value>` is being read
+    heart_rate_tag: Option<TcxXmlTag>,
+    /// Activity or course being read
+    current_activity: Track,
+    /// Activity lap being read. Laps of courses carry no data of interest and are skipped.
+    current_lap: Option<Lap>,
+    current_segment: TrackSegment,
+    current_track_point: TrackPoint,
+    has_latitude: bool,
+    has_longitude: bool,
+}
+
+impl ParserContext {
+    fn new() -> ParserContext {
+        ParserContext {
+            in_activity: false,
+            in_course: false,
+            in_track: false,
+            in_track_point: false,
+            in_position: false,
+            in_creator: false,
+            current_tag: None,
+            heart_rate_tag: None,
+            current_activity: Track::new(),
+            current_lap: None,
+            current_segment: TrackSegment::new(),
+            current_track_point: TrackPoint::new(),
+            has_latitude: false,
+            has_longitude: false,
+        }
+    }
+}
+
+const GARMIN_TCX_SCHEMA: &str = "http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2";
+const GARMIN_ACTIVITY_EXT_SCHEMA: &str = "http://www.garmin.com/xmlschemas/ActivityExtension/v2";
+
+const GARMIN_TCX_MAPPINGS: [(&str, TcxXmlTag); 22] = [
+    ("Activity", TcxXmlTag::Activity),
+    ("Course", TcxXmlTag::Course),
+    ("Id", TcxXmlTag::Id),
+    ("Notes", TcxXmlTag::Notes),
+    ("Creator", TcxXmlTag::Creator),
+    ("Name", TcxXmlTag::Name),
+    ("Lap", TcxXmlTag::Lap),
+    ("TotalTimeSeconds", TcxXmlTag::TotalTimeSeconds),
+    ("DistanceMeters", TcxXmlTag::DistanceMeters),
+    ("Calories", TcxXmlTag::Calories),
+    ("AverageHeartRateBpm", TcxXmlTag::AverageHeartRateBpm),
+    ("MaximumHeartRateBpm", TcxXmlTag::MaximumHeartRateBpm),
+    ("HeartRateBpm", TcxXmlTag::HeartRateBpm),
+    ("Value", TcxXmlTag::Value),
+    ("Cadence", TcxXmlTag::Cadence),
+    ("Track", TcxXmlTag::Track),
+    ("Trackpoint", TcxXmlTag::Trackpoint),
+    ("Time", TcxXmlTag::Time),
+    ("Position", TcxXmlTag::Position),
+    ("LatitudeDegrees", TcxXmlTag::LatitudeDegrees),
+    ("LongitudeDegrees", TcxXmlTag::LongitudeDegrees),
+    ("AltitudeMeters", TcxXmlTag::AltitudeMeters),
+];
+
+const GARMIN_ACTIVITY_EXT_MAPPINGS: [(&str, TcxXmlTag); 3] = [
+    ("Speed", TcxXmlTag::ExtSpeed),
+    ("Watts", TcxXmlTag::ExtWatts),
+    ("RunCadence", TcxXmlTag::ExtRunCadence),
+];
+
+fn find_tag_in_mapping(tag: &str, mapping: &[(&'static str, TcxXmlTag)]) -> Option<TcxXmlTag> {
+    mapping
+        .iter()
+        .find(|&&(mapped_tag, _)| mapped_tag == tag)
+        .map(|&(_, value)| value)
+}
+
+fn parse_tcx_xml_tag(name: &OwnedName) -> Option<TcxXmlTag> {
+    let tag = name.local_name.as_str();
+    match name.namespace.as_deref() {
+        Some(GARMIN_TCX_SCHEMA) => find_tag_in_mapping(tag, &GARMIN_TCX_MAPPINGS),
+        Some(GARMIN_ACTIVITY_EXT_SCHEMA) => find_tag_in_mapping(tag, &GARMIN_ACTIVITY_EXT_MAPPINGS),
+        _ => None,
+    }
+}
+
+fn unexpected_element(tag: TcxXmlTag, location: Location) -> ParseError {
+    ParseError::UnexpectedElement {
+        element: tag.name().to_string(),
+        location,
+    }
+}
+
+fn parse_time(field: &str, value: &str, location: Location) -> Result<DateTime<Utc>, ParseError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(DateTime::<Utc>::from)
+        .map_err(|_| invalid_value(field, value, location))
+}
+
+fn parse_value<T: std::str::FromStr>(
+    tag: TcxXmlTag,
+    value: &str,
+    location: Location,
+) -> Result<T, ParseError> {
+    value
+        .trim()
+        .parse::<T>()
+        .map_err(|_| invalid_value(tag.name(), value, location))
+}
+
+fn parse_lap_start_time(
+    attributes: &[OwnedAttribute],
+    location: Location,
+) -> Result<DateTime<Utc>, ParseError> {
+    let start_time = attributes
+        .iter()
+        .find(|attr| attr.name.local_name == "StartTime")
+        .ok_or_else(|| ParseError::MissingAttribute {
+            element: TcxXmlTag::Lap.name().to_string(),
+            attribute: String::from("StartTime"),
+            location,
+        })?;
+
+    parse_time("Lap@StartTime", &start_time.value, location)
+}
+
+fn parse_start_xml_element(
+    tag: TcxXmlTag,
+    attributes: &[OwnedAttribute],
+    location: Location,
+    context: &mut ParserContext,
+) -> Result<(), ParseError> {
+    context.current_tag = Some(tag);
+
+    match tag {
+        TcxXmlTag::Activity | TcxXmlTag::Course => {
+            if context.in_activity || context.in_course {
+                return Err(unexpected_element(tag, location));
+            }
+
+            context.in_activity = tag == TcxXmlTag::Activity;
+            context.in_course = tag == TcxXmlTag::Course;
+            context.current_activity = Track::new();
+            context.current_activity.activity_type = attributes
+                .iter()
+                .find(|attr| attr.name.local_name == "Sport")
+                .map(|attr| ActivityType::from_name(&attr.value));
+        }
+        TcxXmlTag::Creator => context.in_creator = context.in_activity || context.in_course,
+        TcxXmlTag::Lap if context.in_course => {}
+        TcxXmlTag::Lap => {
+            if !context.in_activity || context.current_lap.is_some() {
+                return Err(unexpected_element(tag, location));
+            }
+
+            let mut lap = Lap::new(parse_lap_start_time(attributes, location)?);
+            lap.first_segment = context.current_activity.route.len();
+            context.current_lap = Some(lap);
+        }
+        TcxXmlTag::Track => {
+            let in_lap = context.current_lap.is_some();
+            if !(in_lap || context.in_course) || context.in_track {
+                return Err(unexpected_element(tag, location));
+            }
+
+            context.in_track = true;
+        }
+        TcxXmlTag::Trackpoint => {
+            if !context.in_track || context.in_track_point {
+                return Err(unexpected_element(tag, location));
+            }
+
+            context.in_track_point = true;
+            context.current_track_point = TrackPoint::new();
+            context.has_latitude = false;
+            context.has_longitude = false;
+        }
+        // Course points and course laps carry positions of their own, only track point
+        // positions are read
+        TcxXmlTag::Position => context.in_position = context.in_track_point,
+        TcxXmlTag::AverageHeartRateBpm
+        | TcxXmlTag::MaximumHeartRateBpm
+        | TcxXmlTag::HeartRateBpm => context.heart_rate_tag = Some(tag),
+        TcxXmlTag::LatitudeDegrees | TcxXmlTag::LongitudeDegrees
+            if context.in_track_point && !context.in_position =>
+        {
+            return Err(unexpected_element(tag, location));
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn parse_xml_characters(
+    characters: String,
+    location: Location,
+    context: &mut ParserContext,
+) -> Result<(), ParseError> {
+    let tag = match context.current_tag {
+        Some(tag) => tag,
+        None => return Ok(()),
+    };
+
+    let point = &mut context.current_track_point;
+    let in_lap = context.current_lap.is_some();
+
+    match tag {
+        TcxXmlTag::Id if context.in_activity && !in_lap => {
+            context.current_activity.creation_time =
+                Some(parse_time(tag.name(), characters.trim(), location)?);
+        }
+        TcxXmlTag::Notes if context.in_activity && !in_lap => {
+            context.current_activity.name = characters;
+        }
+        TcxXmlTag::Name if context.in_creator => {
+            context.current_activity.device = Some(characters);
+        }
+        // Course name comes first, names of course points follow the track
+        TcxXmlTag::Name if context.in_course && context.current_activity.name.is_empty() => {
+            context.current_activity.name = characters;
+        }
+        TcxXmlTag::Time if context.in_track_point => {
+            point.time = Some(parse_time(tag.name(), characters.trim(), location)?);
+        }
+        TcxXmlTag::LatitudeDegrees if context.in_position => {
+            point.latitude = parse_value(tag, &characters, location)?;
+            context.has_latitude = true;
+        }
+        TcxXmlTag::LongitudeDegrees if context.in_position => {
+            point.longitude = parse_value(tag, &characters, location)?;
+            context.has_longitude = true;
+        }
+        TcxXmlTag::AltitudeMeters if context.in_track_point => {
+            point.elevation = Some(parse_value(tag, &characters, location)?);
+        }
+        TcxXmlTag::DistanceMeters if context.in_track_point => {
+            point.distance = Some(parse_value(tag, &characters, location)?);
+        }
+        TcxXmlTag::Cadence if context.in_track_point => {
+            point.cadence = Some(parse_value(tag, &characters, location)?);
+        }
+        TcxXmlTag::ExtSpeed if context.in_track_point => {
+            point.speed = Some(parse_value(tag, &characters, location)?);
+        }
+        TcxXmlTag::ExtWatts if context.in_track_point => {
+            point.power = Some(parse_value(tag, &characters, location)?);
+        }
+        // Running watches put cadence into the extension instead of `<Cadence>`
+        TcxXmlTag::ExtRunCadence if context.in_track_point => {
+            let cadence = parse_value(tag, &characters, location)?;
+            point.cadence = point.cadence.or(Some(cadence));
+        }
+        TcxXmlTag::Value
+            if context.in_track_point && context.heart_rate_tag == Some(TcxXmlTag::HeartRateBpm) =>
+        {
+            point.heart_rate = Some(parse_value(TcxXmlTag::HeartRateBpm, &characters, location)?);
+        }
+        _ if !context.in_track_point => {
+            if let Some(lap) = context.current_lap.as_mut() {
+                parse_lap_characters(tag, context.heart_rate_tag, &characters, location, lap)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Summary values of the lap, they precede its tracks
+fn parse_lap_characters(
+    tag: TcxXmlTag,
+    heart_rate_tag: Option<TcxXmlTag>,
+    characters: &str,
+    location: Location,
+    lap: &mut Lap,
+) -> Result<(), ParseError> {
+    match tag {
+        TcxXmlTag::DistanceMeters => {
+            lap.distance = parse_value(tag, characters, location)?;
+        }
+        TcxXmlTag::TotalTimeSeconds => {
+            let seconds: f64 = parse_value(tag, characters, location)?;
+            if !seconds.is_finite() || seconds < 0.0 {
+                return Err(invalid_value(tag.name(), characters, location));
+            }
+            lap.total_time = Duration::from_secs_f64(seconds);
+        }
+        TcxXmlTag::Calories => {
+            lap.calories = Some(parse_value(tag, characters, location)?);
+        }
+        TcxXmlTag::Value => match heart_rate_tag {
+            Some(TcxXmlTag::AverageHeartRateBpm) => {
+                let heart_rate = parse_value(TcxXmlTag::AverageHeartRateBpm, characters, location)?;
+                lap.avg_heart_rate = Some(heart_rate);
+            }
+            Some(TcxXmlTag::MaximumHeartRateBpm) => {
+                let heart_rate = parse_value(TcxXmlTag::MaximumHeartRateBpm, characters, location)?;
+                lap.max_heart_rate = Some(heart_rate);
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn parse_end_xml_element(tag: TcxXmlTag, activities: &mut Vec<Track>, context: &mut ParserContext) {
+    context.current_tag = None;
+
+    match tag {
+        TcxXmlTag::Activity | TcxXmlTag::Course => {
+            context.in_activity = false;
+            context.in_course = false;
+            activities.push(mem::take(&mut context.current_activity));
+        }
+        TcxXmlTag::Creator => context.in_creator = false,
+        TcxXmlTag::Lap => {
+            if let Some(mut lap) = context.current_lap.take() {
+                lap.segment_count = context.current_activity.route.len() - lap.first_segment;
+                context.current_activity.laps.push(lap);
+            }
+        }
+        TcxXmlTag::Track => {
+            context.in_track = false;
+            let segment = mem::take(&mut context.current_segment);
+            if !segment.points.is_empty() {
+                context.current_activity.route.push(segment);
+            }
+        }
+        TcxXmlTag::Trackpoint => {
+            context.in_track_point = false;
+            // Points recorded before GPS fix or on a treadmill have no position.
+            // They can't take part in distance calculation, so they're dropped.
+            if context.has_latitude && context.has_longitude {
+                context
+                    .current_segment
+                    .points
+                    .push(context.current_track_point);
+            }
+        }
+        TcxXmlTag::Position => context.in_position = false,
+        TcxXmlTag::AverageHeartRateBpm
+        | TcxXmlTag::MaximumHeartRateBpm
+        | TcxXmlTag::HeartRateBpm => context.heart_rate_tag = None,
+        _ => {}
+    }
+}
+
+fn read_tcx_activities_from<R: Read>(reader: BufReader<R>) -> Result<Vec<Track>, ParseError> {
+    let mut parser = EventReader::new(reader);
+    let mut activities = Vec::new();
+    let mut context = ParserContext::new();
+
+    loop {
+        let event = parser.next();
+        let location = to_location(parser.position());
+
+        match event {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                if let Some(tag) = parse_tcx_xml_tag(&name) {
+                    parse_start_xml_element(tag, &attributes, location, &mut context)?;
+                }
+            }
+            Ok(XmlEvent::EndElement { name }) => {
+                if let Some(tag) = parse_tcx_xml_tag(&name) {
+                    parse_end_xml_element(tag, &mut activities, &mut context);
+                }
+            }
+            Ok(XmlEvent::Characters(characters)) => {
+                parse_xml_characters(characters, location, &mut context)?;
+            }
+            Ok(XmlEvent::EndDocument) => break,
+            Err(err) => return Err(convert_xml_error(err)),
+            _ => {}
+        }
+    }
+
+    for activity in &mut activities {
+        activity.sort_points_by_time();
+    }
+    Ok(activities)
+}
+
+/// Parses every activity and course of TCX document. Each of them becomes a separate track,
+/// each TCX `<Track>` becomes a track segment.
+pub fn read_tcx_activities_from_reader<R: Read>(reader: R) -> Result<Vec<Track>, ParseError> {
+    read_tcx_activities_from(BufReader::new(reader))
+}
+
+pub fn read_tcx_activities(path: &str) -> Result<Vec<Track>, ParseError> {
+    let file = File::open(path)?;
+
+    read_tcx_activities_from_reader(file)
+}
+
+/// Parses the first activity or course of TCX document. Documents without them give
+/// an empty track.
+pub fn read_tcx_from_reader<R: Read>(reader: R) -> Result<Track, ParseError> {
+    read_tcx_activities_from_reader(reader)
+        .map(|activities| activities.into_iter().next().unwrap_or_default())
+}
+
+pub fn read_tcx(path: &str) -> Result<Track, ParseError> {
+    let file = File::open(path)?;
+
+    read_tcx_from_reader(file)
+}
+
+pub fn read_tcx_from_bytes(bytes: &[u8]) -> Result<Track, ParseError> {
+    read_tcx_from_reader(bytes)
+}
+
+pub fn read_tcx_from_str(tcx: &str) -> Result<Track, ParseError> {
+    read_tcx_from_bytes(tcx.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    const TCX_STR: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\"
+xmlns:ns3=\"http://www.garmin.com/xmlschemas/ActivityExtension/v2\">
+  <Activities>
+    <Activity Sport=\"Running\">
+      <Id>2020-04-22T16:01:58Z</Id>
+      <Lap StartTime=\"2020-04-22T16:01:58Z\">
+        <TotalTimeSeconds>6.0</TotalTimeSeconds>
+        <DistanceMeters>12.5</DistanceMeters>
+        <Calories>1</Calories>
+        <AverageHeartRateBpm><Value>96</Value></AverageHeartRateBpm>
+        <MaximumHeartRateBpm><Value>98</Value></MaximumHeartRateBpm>
+        <Intensity>Active</Intensity>
+        <Cadence>80</Cadence>
+        <TriggerMethod>Manual</TriggerMethod>
+        <Track>
+          <Trackpoint>
+            <Time>2020-04-22T16:01:56Z</Time>
+            <HeartRateBpm><Value>94</Value></HeartRateBpm>
+          </Trackpoint>
+          <Trackpoint>
+            <Time>2020-04-22T16:01:58Z</Time>
+            <Position>
+              <LatitudeDegrees>10.1025420</LatitudeDegrees>
+              <LongitudeDegrees>15.1583540</LongitudeDegrees>
+            </Position>
+            <AltitudeMeters>478.2</AltitudeMeters>
+            <DistanceMeters>0.0</DistanceMeters>
+            <HeartRateBpm><Value>95</Value></HeartRateBpm>
+            <Cadence>79</Cadence>
+            <Extensions>
+              <ns3:TPX>
+                <ns3:Speed>2.5</ns3:Speed>
+                <ns3:Watts>250</ns3:Watts>
+              </ns3:TPX>
+            </Extensions>
+          </Trackpoint>
+          <Trackpoint>
+            <Time>2020-04-22T16:02:04Z</Time>
+            <Position>
+              <LatitudeDegrees>10.1026420</LatitudeDegrees>
+              <LongitudeDegrees>15.1583542</LongitudeDegrees>
+            </Position>
+            <AltitudeMeters>480.3</AltitudeMeters>
+            <DistanceMeters>12.5</DistanceMeters>
+            <HeartRateBpm><Value>98</Value></HeartRateBpm>
+            <Extensions>
+              <ns3:TPX>
+                <ns3:Speed>2.1</ns3:Speed>
+                <ns3:RunCadence>81</ns3:RunCadence>
+              </ns3:TPX>
+            </Extensions>
+          </Trackpoint>
+        </Track>
+      </Lap>
+      <Lap StartTime=\"2020-04-22T16:02:04Z\">
+        <TotalTimeSeconds>3.5</TotalTimeSeconds>
+        <DistanceMeters>11.1</DistanceMeters>
+        <Track>
+          <Trackpoint>
+            <Time>2020-04-22T16:02:07Z</Time>
+            <Position>
+              <LatitudeDegrees>10.1027420</LatitudeDegrees>
+              <LongitudeDegrees>15.1583544</LongitudeDegrees>
+            </Position>
+          </Trackpoint>
+        </Track>
+      </Lap>
+      <Notes>Evening run</Notes>
+      <Creator xsi:type=\"Device_t\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">
+        <Name>Forerunner 245</Name>
+      </Creator>
+    </Activity>
+  </Activities>
+</TrainingCenterDatabase>";
+
+    #[test_case("Activity", TcxXmlTag::Activity; "Activity tag")]
+    #[test_case("Lap", TcxXmlTag::Lap; "Lap tag")]
+    #[test_case("Track", TcxXmlTag::Track; "Track tag")]
+    #[test_case("Trackpoint", TcxXmlTag::Trackpoint; "Trackpoint tag")]
+    #[test_case("HeartRateBpm", TcxXmlTag::HeartRateBpm; "HeartRateBpm tag")]
+    #[test_case("DistanceMeters", TcxXmlTag::DistanceMeters; "DistanceMeters tag")]
+    #[test_case("Creator", TcxXmlTag::Creator; "Creator tag")]
+    #[test_case("Name", TcxXmlTag::Name; "Name tag")]
+    fn test_garmin_tcx_mapping(tag: &str, expected: TcxXmlTag) {
+        let name = OwnedName {
+            local_name: String::from(tag),
+            namespace: Some(String::from(GARMIN_TCX_SCHEMA)),
+            prefix: None,
+        };
+
+        let parsed = parse_tcx_xml_tag(&name);
+        assert_eq!(parsed, Some(expected));
+    }
+
+    #[test_case("Speed", TcxXmlTag::ExtSpeed; "Speed tag")]
+    #[test_case("Watts", TcxXmlTag::ExtWatts; "Watts tag")]
+    #[test_case("RunCadence", TcxXmlTag::ExtRunCadence; "RunCadence tag")]
+    fn test_garmin_activity_ext_mapping(tag: &str, expected: TcxXmlTag) {
+        let name = OwnedName {
+            local_name: String::from(tag),
+            namespace: Some(String::from(GARMIN_ACTIVITY_EXT_SCHEMA)),
+            prefix: None,
+        };
+
+        let parsed = parse_tcx_xml_tag(&name);
+        assert_eq!(parsed, Some(expected));
+    }
+
+    #[test]
+    fn test_unknown_namespace_tcx_mapping() {
+        let name = OwnedName {
+            local_name: String::from("Trackpoint"),
+            namespace: Some(String::from("http://www.topografix.com/GPX/1/1")),
+            prefix: None,
+        };
+
+        let parsed = parse_tcx_xml_tag(&name);
+        assert_eq!(parsed, None);
+    }
+
+    #[test]
+    fn test_parsing_tcx() {
+        let track = read_tcx_from_str(TCX_STR).unwrap();
+        assert_eq!(track.name, "Evening run");
+        assert_eq!(track.creation_time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58)));
+        assert_eq!(track.activity_type, Some(ActivityType::Running));
+        assert_eq!(track.device.as_deref(), Some("Forerunner 245"));
+        assert_eq!(track.route.len(), 2);
+        assert_eq!(track.route[0].points.len(), 2);
+        assert_eq!(track.route[1].points.len(), 1);
+
+        let point = &track.route[0].points[0];
+        assert_eq!(point.latitude, 10.1025420);
+        assert_eq!(point.longitude, 15.1583540);
+        assert_eq!(point.elevation, Some(478.2));
+        assert_eq!(point.time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58)));
+        assert_eq!(point.distance, Some(0.0));
+        assert_eq!(point.heart_rate, Some(95));
+        assert_eq!(point.cadence, Some(79));
+        assert_eq!(point.speed, Some(2.5));
+        assert_eq!(point.power, Some(250));
+
+        let point = &track.route[0].points[1];
+        assert_eq!(point.distance, Some(12.5));
+        assert_eq!(point.heart_rate, Some(98));
+        assert_eq!(point.cadence, Some(81));
+        assert_eq!(point.speed, Some(2.1));
+        assert_eq!(point.power, None);
+
+        let point = &track.route[1].points[0];
+        assert_eq!(point.elevation, None);
+        assert_eq!(point.heart_rate, None);
+    }
+
+    #[test]
+    fn test_parsing_tcx_laps() {
+        let track = read_tcx_from_str(TCX_STR).unwrap();
+        assert_eq!(track.laps.len(), 2);
+
+        let lap = &track.laps[0];
+        assert_eq!(lap.start_time, Utc.ymd(2020, 4, 22).and_hms(16, 1, 58));
+        assert_eq!(lap.total_time, Duration::from_secs(6));
+        assert_eq!(lap.distance, 12.5);
+        assert_eq!(lap.calories, Some(1));
+        assert_eq!(lap.avg_heart_rate, Some(96));
+        assert_eq!(lap.max_heart_rate, Some(98));
+        assert_eq!(lap.first_segment, 0);
+        assert_eq!(lap.segment_count, 1);
+
+        let lap = &track.laps[1];
+        assert_eq!(lap.start_time, Utc.ymd(2020, 4, 22).and_hms(16, 2, 4));
+        assert_eq!(lap.total_time, Duration::from_millis(3500));
+        assert_eq!(lap.calories, None);
+        assert_eq!(lap.avg_heart_rate, None);
+        assert_eq!(lap.first_segment, 1);
+        assert_eq!(lap.segment_count, 1);
+    }
+
+    #[test]
+    fn test_tcx_track_stats() {
+        let track = read_tcx_from_str(TCX_STR).unwrap();
+        assert_eq!(track.distance(), 11);
+        assert_eq!(track.duration(), Duration::from_secs(6));
+        assert_eq!(track.avg_heart_rate(), Some(96));
+        assert_eq!(track.avg_power(), Some(250));
+    }
+
+    #[test]
+    fn test_parsing_multiple_activities() {
+        let tcx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\">
+  <Activities>
+    <Activity Sport=\"Running\"><Id>2020-04-22T16:01:58Z</Id></Activity>
+    <Activity Sport=\"Biking\"><Id>2020-04-23T10:00:00Z</Id></Activity>
+  </Activities>
+</TrainingCenterDatabase>";
+
+        let activities = read_tcx_activities_from_reader(tcx_str.as_bytes()).unwrap();
+        assert_eq!(activities.len(), 2);
+        assert_eq!(activities[1].creation_time, Some(Utc.ymd(2020, 4, 23).and_hms(10, 0, 0)));
+        assert_eq!(activities[1].activity_type, Some(ActivityType::Cycling));
+        assert_eq!(activities[1].device, None);
+    }
+
+    #[test]
+    fn test_parsing_tcx_without_activities() {
+        let tcx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\">
+  <Activities/>
+</TrainingCenterDatabase>";
+
+        let track = read_tcx_from_str(tcx_str).unwrap();
+        assert!(track.route.is_empty());
+        assert!(track.laps.is_empty());
+    }
+
+    #[test]
+    fn test_parsing_course() {
+        let tcx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\">
+  <Courses>
+    <Course>
+      <Name>River loop</Name>
+      <Lap>
+        <TotalTimeSeconds>600.0</TotalTimeSeconds>
+        <DistanceMeters>2000.0</DistanceMeters>
+        <BeginPosition><LatitudeDegrees>10.0</LatitudeDegrees><LongitudeDegrees>15.0</LongitudeDegrees></BeginPosition>
+        <EndPosition><LatitudeDegrees>10.001</LatitudeDegrees><LongitudeDegrees>15.0</LongitudeDegrees></EndPosition>
+        <Intensity>Active</Intensity>
+      </Lap>
+      <Track>
+        <Trackpoint>
+          <Time>2020-04-22T16:01:58Z</Time>
+          <Position><LatitudeDegrees>10.0</LatitudeDegrees><LongitudeDegrees>15.0</LongitudeDegrees></Position>
+        </Trackpoint>
+        <Trackpoint>
+          <Position><LatitudeDegrees>10.001</LatitudeDegrees><LongitudeDegrees>15.0</LongitudeDegrees></Position>
+        </Trackpoint>
+      </Track>
+      <CoursePoint>
+        <Name>Bridge</Name>
+        <Time>2020-04-22T16:05:00Z</Time>
+        <Position><LatitudeDegrees>20.0</LatitudeDegrees><LongitudeDegrees>25.0</LongitudeDegrees></Position>
+        <AltitudeMeters>120.0</AltitudeMeters>
+        <PointType>Generic</PointType>
+      </CoursePoint>
+    </Course>
+  </Courses>
+</TrainingCenterDatabase>";
+
+        let track = read_tcx_from_str(tcx_str).unwrap();
+        assert_eq!(track.name, "River loop");
+        assert!(track.laps.is_empty());
+        assert_eq!(track.route.len(), 1);
+        assert_eq!(track.route[0].points.len(), 2);
+        assert_eq!(track.route[0].points[0].time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 1, 58)));
+        assert_eq!(track.route[0].points[1].time, None);
+        // Course point doesn't leak into the last track point
+        assert_eq!(track.route[0].points[1].latitude, 10.001);
+        assert_eq!(track.route[0].points[1].longitude, 15.0);
+        assert_eq!(track.route[0].points[1].elevation, None);
+    }
+
+    #[test]
+    fn test_parsing_track_outside_lap() {
+        let tcx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\">
+  <Activities><Activity><Track/></Activity></Activities>
+</TrainingCenterDatabase>";
+
+        match read_tcx_from_str(tcx_str) {
+            Err(ParseError::UnexpectedElement { element, .. }) => assert_eq!(element, "Track"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parsing_lap_without_start_time() {
+        let tcx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\">
+  <Activities><Activity><Lap></Lap></Activity></Activities>
+</TrainingCenterDatabase>";
+
+        match read_tcx_from_str(tcx_str) {
+            Err(ParseError::MissingAttribute {
+                element, attribute, ..
+            }) => {
+                assert_eq!(element, "Lap");
+                assert_eq!(attribute, "StartTime");
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parsing_trackpoint_outside_track() {
+        let tcx_str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\">
+  <Activities>
+    <Activity><Lap StartTime=\"2020-04-22T16:01:58Z\"><Trackpoint/></Lap></Activity>
+  </Activities>
+</TrainingCenterDatabase>";
+
+        match read_tcx_from_str(tcx_str) {
+            Err(ParseError::UnexpectedElement { element, location }) => {
+                assert_eq!(element, "Trackpoint");
+                assert_eq!(location, Location { line: 4, column: 53 });
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test_case("<HeartRateBpm><Value>fast</Value></HeartRateBpm>", "HeartRateBpm", "fast"; "heart rate")]
+    #[test_case("<Extensions><x:TPX><x:Watts>-5</x:Watts></x:TPX></Extensions>", "Watts", "-5"; "watts")]
+    #[test_case("<Time>yesterday</Time>", "Time", "yesterday"; "time")]
+    fn test_parsing_invalid_value(content: &str, expected_field: &str, expected_value: &str) {
+        let tcx_str = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\"
+xmlns:x=\"http://www.garmin.com/xmlschemas/ActivityExtension/v2\">
+  <Activities><Activity><Lap StartTime=\"2020-04-22T16:01:58Z\"><Track>
+    <Trackpoint>{}</Trackpoint>
+  </Track></Lap></Activity></Activities>
+</TrainingCenterDatabase>",
+            content
+        );
+
+        match read_tcx_from_str(&tcx_str) {
+            Err(ParseError::InvalidValue { field, value, .. }) => {
+                assert_eq!(field, expected_field);
+                assert_eq!(value, expected_value);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reading_missing_tcx_file() {
+        let result = read_tcx("/nonexistent/path/to/activity.tcx");
+        assert!(matches!(result, Err(ParseError::Io(_))));
+    }
+}
@@ -0,0 +1,453 @@
+// Runstats
+// Copyright (C) 2020  Konstantin Zhukov
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use xml::writer::{EmitterConfig, Error as EmitterError, EventWriter, XmlEvent};
+
+use super::gpx_writer::{convert_emitter_error, format_xml_time, write_text_element};
+use super::stats;
+use super::{ActivityType, SplitOptions, Track, TrackPoint, TrackSegment};
+
+const GARMIN_TCX_SCHEMA: &str = "http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2";
+
+/// How the track is divided into TCX laps
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TcxLaps {
+    /// Lap per split, see `Track::splits_with_options`
+    Splits(SplitOptions),
+    /// Lap per track segment
+    Segments,
+}
+
+struct TcxLap {
+    start_time: DateTime<Utc>,
+    total_time: Duration,
+    /// Meters
+    distance: f64,
+    avg_heart_rate: Option<u8>,
+    /// Points of the lap. Their `distance` holds the distance from the start of the track.
+    route: Vec<TrackSegment>,
+    trigger_method: &'static str,
+}
+
+/// Copy of the track route where every point knows the distance from the start of the track.
+/// TCX requires time of every trackpoint, so untimed points are left out. They still count
+/// to the distance of the following points.
+fn route_with_distances(track: &Track) -> Vec<TrackSegment> {
+    let distances = stats::calc_track_point_distances(track);
+
+    track
+        .route
+        .iter()
+        .zip(distances)
+        .map(|(segment, distances)| TrackSegment {
+            points: segment
+                .points
+                .iter()
+                .zip(distances)
+                .filter(|(point, _)| point.time.is_some())
+                .map(|(point, distance)| TrackPoint {
+                    distance: Some(distance),
+                    ..*point
+                })
+                .collect(),
+        })
+        .filter(|segment| !segment.points.is_empty())
+        .collect()
+}
+
+/// Distance covered by the route, from distances of its points
+fn route_distance(route: &[TrackSegment]) -> f64 {
+    route
+        .iter()
+        .filter_map(|segment| {
+            let first = segment.points.first()?.distance?;
+            let last = segment.points.last()?.distance?;
+            Some(last - first)
+        })
+        .sum()
+}
+
+fn first_point_time(route: &[TrackSegment]) -> Option<DateTime<Utc>> {
+    route
+        .iter()
+        .flat_map(|segment| segment.points.iter())
+        .find_map(|point| point.time)
+}
+
+/// Lap with totals calculated over its own points
+fn measured_lap(route: Vec<TrackSegment>, start_time: DateTime<Utc>) -> TcxLap {
+    let mut track = Track::new();
+    track.route = route;
+
+    TcxLap {
+        start_time: first_point_time(&track.route).unwrap_or(start_time),
+        total_time: stats::calc_track_duration(&track),
+        distance: route_distance(&track.route),
+        avg_heart_rate: stats::calc_track_average_heart_rate(&track),
+        route: track.route,
+        trigger_method: "Manual",
+    }
+}
+
+fn laps_by_segments(route: Vec<TrackSegment>, start_time: DateTime<Utc>) -> Vec<TcxLap> {
+    route
+        .into_iter()
+        .map(|segment| measured_lap(vec![segment], start_time))
+        .collect()
+}
+
+/// Lap per split. Distance and time of the lap come from the split, points are distributed
+/// between laps by their distance from the start.
+fn laps_by_splits(
+    track: &Track,
+    options: &SplitOptions,
+    route: Vec<TrackSegment>,
+    start_time: DateTime<Utc>,
+) -> Vec<TcxLap> {
+    let splits = stats::calc_track_splits(track, options);
+    if splits.is_empty() {
+        return vec![measured_lap(route, start_time)];
+    }
+
+    let mut lap_routes: Vec<Vec<TrackSegment>> = splits.iter().map(|_| Vec::new()).collect();
+    for segment in route {
+        let mut previous_lap = None;
+        for point in segment.points {
+            let distance = point.distance.unwrap_or_default();
+            // Points after the last split, if it was too short to count, join the last lap
+            let lap = splits
+                .partition_point(|split| split.start_distance <= distance)
+                .max(1)
+                - 1;
+            if previous_lap != Some(lap) {
+                lap_routes[lap].push(TrackSegment::new());
+                previous_lap = Some(lap);
+            }
+            if let Some(lap_segment) = lap_routes[lap].last_mut() {
+                lap_segment.points.push(point);
+            }
+        }
+    }
+
+    let mut lap_start_time = start_time;
+    splits
+        .iter()
+        .zip(lap_routes)
+        .map(|(split, route)| {
+            let mut track = Track::new();
+            track.route = route;
+            lap_start_time = first_point_time(&track.route).unwrap_or(lap_start_time);
+
+            TcxLap {
+                start_time: lap_start_time,
+                total_time: split.duration,
+                distance: split.distance,
+                avg_heart_rate: stats::calc_track_average_heart_rate(&track),
+                route: track.route,
+                trigger_method: "Distance",
+            }
+        })
+        .collect()
+}
+
+fn write_point<W: Write>(writer: &mut EventWriter<W>, point: &TrackPoint) -> Result<(), EmitterError> {
+    writer.write(XmlEvent::start_element("Trackpoint"))?;
+
+    // Untimed points were left out by `route_with_distances`
+    if let Some(time) = &point.time {
+        write_text_element(writer, "Time", &format_xml_time(time))?;
+    }
+    writer.write(XmlEvent::start_element("Position"))?;
+    write_text_element(writer, "LatitudeDegrees", &point.latitude.to_string())?;
+    write_text_element(writer, "LongitudeDegrees", &point.longitude.to_string())?;
+    writer.write(XmlEvent::end_element())?;
+    if let Some(elevation) = point.elevation {
+        write_text_element(writer, "AltitudeMeters", &elevation.to_string())?;
+    }
+    if let Some(distance) = point.distance {
+        write_text_element(writer, "DistanceMeters", &distance.to_string())?;
+    }
+    if let Some(heart_rate) = point.heart_rate {
+        writer.write(XmlEvent::start_element("HeartRateBpm"))?;
+        write_text_element(writer, "Value", &heart_rate.to_string())?;
+        writer.write(XmlEvent::end_element())?;
+    }
+    if let Some(cadence) = point.cadence {
+        write_text_element(writer, "Cadence", &cadence.to_string())?;
+    }
+
+    writer.write(XmlEvent::end_element())
+}
+
+fn write_lap<W: Write>(writer: &mut EventWriter<W>, lap: &TcxLap) -> Result<(), EmitterError> {
+    let start_time = format_xml_time(&lap.start_time);
+    writer.write(XmlEvent::start_element("Lap").attr("StartTime", &start_time))?;
+
+    let total_time = lap.total_time.as_secs_f64().to_string();
+    write_text_element(writer, "TotalTimeSeconds", &total_time)?;
+    write_text_element(writer, "DistanceMeters", &lap.distance.to_string())?;
+    // Required by the schema, but runstats doesn't know the energy spent
+    write_text_element(writer, "Calories", "0")?;
+    if let Some(avg_heart_rate) = lap.avg_heart_rate {
+        writer.write(XmlEvent::start_element("AverageHeartRateBpm"))?;
+        write_text_element(writer, "Value", &avg_heart_rate.to_string())?;
+        writer.write(XmlEvent::end_element())?;
+    }
+    write_text_element(writer, "Intensity", "Active")?;
+    write_text_element(writer, "TriggerMethod", lap.trigger_method)?;
+
+    for segment in &lap.route {
+        writer.write(XmlEvent::start_element("Track"))?;
+        for point in &segment.points {
+            write_point(writer, point)?;
+        }
+        writer.write(XmlEvent::end_element())?;
+    }
+
+    writer.write(XmlEvent::end_element())
+}
+
+/// TCX knows running and biking only, everything else is `Other`
+fn sport(track: &Track) -> &'static str {
+    match track.activity_type {
+        Some(ActivityType::Running) => "Running",
+        Some(ActivityType::Cycling) => "Biking",
+        _ => "Other",
+    }
+}
+
+fn write_activity<W: Write>(
+    writer: &mut EventWriter<W>,
+    track: &Track,
+    start_time: &DateTime<Utc>,
+    laps: &[TcxLap],
+) -> Result<(), EmitterError> {
+    writer.write(
+        XmlEvent::start_element("TrainingCenterDatabase")
+            .attr(
+                "xsi:schemaLocation",
+                "http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2 \
+http://www.garmin.com/xmlschemas/TrainingCenterDatabasev2.xsd",
+            )
+            .default_ns(GARMIN_TCX_SCHEMA)
+            .ns("xsi", "http://www.w3.org/2001/XMLSchema-instance"),
+    )?;
+    writer.write(XmlEvent::start_element("Activities"))?;
+    writer.write(XmlEvent::start_element("Activity").attr("Sport", sport(track)))?;
+
+    write_text_element(writer, "Id", &format_xml_time(start_time))?;
+    for lap in laps {
+        write_lap(writer, lap)?;
+    }
+    if !track.name.is_empty() {
+        write_text_element(writer, "Notes", &track.name)?;
+    }
+
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    writer.inner_mut().flush()?;
+    Ok(())
+}
+
+/// Writes the track as TCX activity. Lap totals and the distance of every trackpoint
+/// are calculated from the track. TCX is time based, so the track must have either
+/// creation time or timed points. Untimed points are not written.
+pub fn write_tcx<W: Write>(track: &Track, laps: TcxLaps, writer: W) -> io::Result<()> {
+    let route = route_with_distances(track);
+    let start_time = track
+        .creation_time
+        .or_else(|| first_point_time(&route))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "track has no time"))?;
+
+    let laps = match laps {
+        TcxLaps::Splits(options) => laps_by_splits(track, &options, route, start_time),
+        TcxLaps::Segments => laps_by_segments(route, start_time),
+    };
+
+    let mut writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(writer);
+    write_activity(&mut writer, track, &start_time, &laps).map_err(convert_emitter_error)
+}
+
+/// Writes the track as TCX file, replacing the existing one
+pub fn write_tcx_file(track: &Track, laps: TcxLaps, path: &str) -> io::Result<()> {
+    let file = File::create(path)?;
+
+    write_tcx(track, laps, BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    use super::super::read_tcx_from_str;
+
+    /// Degrees of longitude at the equator making 250 meters
+    const LONGITUDE_STEP_250M: f64 = 250.0 / (2.0 * std::f64::consts::PI * 6371008.8 / 360.0);
+
+    fn write_to_string(track: &Track, laps: TcxLaps) -> String {
+        let mut buffer = Vec::new();
+        write_tcx(track, laps, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    /// 2.5 km along the equator at 100 seconds per 250 meters, split into two segments
+    fn new_track() -> Track {
+        let start = Utc.ymd(2020, 4, 22).and_hms(16, 0, 0);
+        let new_point = |step: u32| {
+            let mut point = TrackPoint::from_coordinates(0.0, LONGITUDE_STEP_250M * f64::from(step));
+            point.time = Some(start + chrono::Duration::seconds(i64::from(step) * 100));
+            point.heart_rate = Some(100 + step as u8);
+            point
+        };
+
+        let mut track = Track::new();
+        track.name = String::from("Tempo run");
+        track.activity_type = Some(ActivityType::Running);
+        track.route.push(TrackSegment {
+            points: (0..=6).map(new_point).collect(),
+        });
+        track.route.push(TrackSegment {
+            points: (6..=10).map(new_point).collect(),
+        });
+        track
+    }
+
+    #[test]
+    fn test_writing_tcx_laps_by_splits() {
+        let track = new_track();
+        let tcx = write_to_string(&track, TcxLaps::Splits(SplitOptions::new()));
+        let activity = read_tcx_from_str(&tcx).unwrap();
+
+        assert_eq!(activity.name, "Tempo run");
+        assert_eq!(activity.activity_type, Some(ActivityType::Running));
+        assert_eq!(activity.creation_time, Some(Utc.ymd(2020, 4, 22).and_hms(16, 0, 0)));
+        assert_eq!(activity.laps.len(), 3);
+
+        let first = &activity.laps[0];
+        assert_eq!(first.start_time, Utc.ymd(2020, 4, 22).and_hms(16, 0, 0));
+        assert!((first.distance - 1000.0).abs() <= 2.0);
+        assert!((first.total_time.as_secs_f64() - 400.0).abs() <= 2.0);
+        assert_eq!(first.avg_heart_rate, Some(102));
+
+        let last = &activity.laps[2];
+        assert!((last.distance - 500.0).abs() <= 2.0);
+        assert!((last.total_time.as_secs_f64() - 200.0).abs() <= 2.0);
+        // Second lap crosses the segment boundary
+        assert_eq!(activity.laps[1].segment_count, 2);
+        assert_eq!(last.first_segment + last.segment_count, activity.route.len());
+
+        let point_count: usize = activity.route.iter().map(|segment| segment.points.len()).sum();
+        assert_eq!(point_count, 12);
+        assert_eq!(activity.route[0].points[0].distance, Some(0.0));
+        let last_point = activity.route.last().unwrap().points.last().unwrap();
+        assert!((last_point.distance.unwrap() - track.distance() as f64).abs() <= 1.0);
+    }
+
+    #[test]
+    fn test_writing_tcx_laps_by_segments() {
+        let track = new_track();
+        let tcx = write_to_string(&track, TcxLaps::Segments);
+        let activity = read_tcx_from_str(&tcx).unwrap();
+
+        assert_eq!(activity.laps.len(), 2);
+        assert_eq!(activity.route.len(), 2);
+        assert!(tcx.contains("<Activity Sport=\"Running\">"));
+
+        let second = &activity.laps[1];
+        assert_eq!(second.start_time, Utc.ymd(2020, 4, 22).and_hms(16, 10, 0));
+        assert_eq!(second.total_time, Duration::from_secs(400));
+        assert!((second.distance - 1000.0).abs() <= 1e-6);
+        assert_eq!(second.avg_heart_rate, Some(108));
+        assert_eq!(second.first_segment, 1);
+
+        // Distance keeps growing across laps
+        let first_point = &activity.route[1].points[0];
+        assert!((first_point.distance.unwrap() - 1500.0).abs() <= 2.0);
+        assert_eq!(first_point.heart_rate, Some(106));
+    }
+
+    #[test]
+    fn test_writing_tcx_laps_by_custom_splits() {
+        let options = SplitOptions {
+            distance: 800.0,
+            min_partial_distance: 0.0,
+            ..SplitOptions::new()
+        };
+        let tcx = write_to_string(&new_track(), TcxLaps::Splits(options));
+        let activity = read_tcx_from_str(&tcx).unwrap();
+
+        assert_eq!(activity.laps.len(), 4);
+        assert!((activity.laps[0].distance - 800.0).abs() <= 1e-6);
+        assert!((activity.laps[0].total_time.as_secs_f64() - 320.0).abs() <= 2.0);
+        assert!((activity.laps[3].distance - 100.0).abs() <= 2.0);
+    }
+
+    #[test]
+    fn test_writing_partly_timed_tcx_track() {
+        let mut track = new_track();
+        track.route[0].points[3].time = None;
+        track.route[1].points.iter_mut().for_each(|point| point.time = None);
+
+        let tcx = write_to_string(&track, TcxLaps::Segments);
+        assert_eq!(tcx.matches("<Trackpoint>").count(), 6);
+        assert_eq!(tcx.matches("<Time>").count(), 6);
+
+        let activity = read_tcx_from_str(&tcx).unwrap();
+        assert_eq!(activity.laps.len(), 1);
+        assert_eq!(activity.route.len(), 1);
+        // Untimed point still counts to the distance, which isn't rounded
+        let lap_distance = activity.laps[0].distance;
+        assert!((lap_distance - 1500.0).abs() <= 2.0);
+        assert!(lap_distance.fract() != 0.0);
+        let fourth_point = &activity.route[0].points[3];
+        assert!((fourth_point.distance.unwrap() - 1000.0).abs() <= 2.0);
+    }
+
+    #[test]
+    fn test_writing_short_tcx_track() {
+        let mut track = new_track();
+        track.route.truncate(1);
+        track.route[0].points.truncate(1);
+
+        let tcx = write_to_string(&track, TcxLaps::Splits(SplitOptions::new()));
+        assert!(tcx.contains("<TotalTimeSeconds>0</TotalTimeSeconds>"));
+        assert!(tcx.contains("<TriggerMethod>Manual</TriggerMethod>"));
+
+        let activity = read_tcx_from_str(&tcx).unwrap();
+        assert_eq!(activity.laps.len(), 1);
+        assert_eq!(activity.route[0].points.len(), 1);
+    }
+
+    #[test]
+    fn test_writing_untimed_tcx_track() {
+        let mut track = Track::new();
+        track.route.push(TrackSegment {
+            points: vec![TrackPoint::from_coordinates(10.0, 15.0)],
+        });
+
+        let result = write_tcx(&track, TcxLaps::Segments, Vec::new());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}